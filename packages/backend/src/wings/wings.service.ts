@@ -284,7 +284,21 @@ export class WingsService implements OnModuleInit {
   }
 
   async listFiles(node: NodeEntity, serverUuid: string, filePath: string): Promise<any> {
-    return this.httpRequest(node, 'GET', `/servers/${serverUuid}/files?path=${encodeURIComponent(filePath)}`);
+    // Wings lists large directories a page at a time; follow the cursor to the end
+    const files: unknown[] = [];
+    let cursor: string | null | undefined;
+    do {
+      let query = `path=${encodeURIComponent(filePath)}`;
+      if (cursor) query += `&cursor=${encodeURIComponent(cursor)}`;
+      const page = await this.httpRequest<{ files: unknown[]; next_cursor?: string | null }>(
+        node,
+        'GET',
+        `/servers/${serverUuid}/files?${query}`,
+      );
+      files.push(...page.files);
+      cursor = page.next_cursor;
+    } while (cursor);
+    return { files };
   }
 
   async readFile(node: NodeEntity, serverUuid: string, filePath: string): Promise<any> {
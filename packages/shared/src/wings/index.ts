@@ -11,8 +11,40 @@ export interface WingsServerConfig {
   memoryLimit: number;
   cpuLimit: number;
   diskLimit: number;
-  portMappings: { hostPort: number; containerPort: number }[];
+  portMappings: WingsPortMapping[];
   volumePath: string;
+  /** Overrides the node's pull policy */
+  pullPolicy?: WingsPullPolicy;
+  /** Swap on top of the memory limit in MiB; 0 disables swap, -1 is unlimited */
+  swapLimit?: number;
+  /** Memory added to the hard limit as headroom, in MiB */
+  memoryOverhead?: number;
+  /** Relative block IO weight from 10 to 1000; 0 keeps Docker's default */
+  ioWeight?: number;
+  /** Maximum number of processes; 0 is unlimited */
+  pidsLimit?: number;
+  /** CPUs the server may run on, e.g. `0-3,6`; empty allows all */
+  cpuset?: string;
+  /** Keep the kernel from killing the server when it runs out of memory */
+  oomKillDisable?: boolean;
+  /** Run on a network of its own, unreachable from other servers */
+  networkIsolated?: boolean;
+  /** Outbound bandwidth in Mbit/s; 0 is unlimited */
+  egressLimit?: number;
+  /** Inbound bandwidth in Mbit/s; 0 is unlimited */
+  ingressLimit?: number;
+}
+
+export type WingsPullPolicy = 'always' | 'if-not-present' | 'never';
+
+export interface WingsPortMapping {
+  hostPort: number;
+  containerPort: number;
+  protocol?: 'tcp' | 'udp' | 'both';
+  /** Host address to bind; all addresses when omitted */
+  bindIp?: string;
+  /** Exposed to the server as SERVER_IP/SERVER_PORT; defaults to the first mapping */
+  primary?: boolean;
 }
 
 export interface WingsCreateServerRequest {
@@ -63,10 +95,18 @@ export interface WingsDecompressRequest {
   destination: string;
 }
 
+export interface WingsResourceStats extends ResourceStats {
+  /** CPU use as a percent of the server's CPU limit, or of the whole node without one */
+  cpuLimitPercent: number;
+  /** Throughput since the previous sample */
+  networkRxBytesPerSec: number;
+  networkTxBytesPerSec: number;
+}
+
 export interface WingsServerStatus {
   uuid: string;
   state: ServerPowerState;
-  resources: ResourceStats;
+  resources: WingsResourceStats;
   containerId?: string;
 }
 
@@ -90,7 +130,7 @@ export interface WingsPowerStateEvent {
 export interface WingsResourceStatsEvent {
   type: WingsEventType.RESOURCE_STATS;
   serverUuid: string;
-  stats: ResourceStats;
+  stats: WingsResourceStats;
 }
 
 export interface WingsConsoleOutputEvent {
@@ -0,0 +1,1092 @@
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::WingsError;
+use crate::files::{self, ArchiveEntry, ExtractLimits};
+use crate::grpc::proto::{
+    self, wings_event, BackupCompleted, BackupRestored, BackupsPruned, WingsEvent,
+};
+use crate::jobs::{self, JobProgress};
+use crate::s3::S3Client;
+use crate::snapshots::{self, RetentionPolicy, VerifyReport};
+use crate::state::AppState;
+
+/// Gitignore-style file in a server root listing paths to leave out of backups.
+pub const IGNORE_FILE: &str = ".nexusignore";
+const CHECKSUM_TYPE: &str = "sha256";
+
+/// Where a backup archive is stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackupAdapter {
+    #[default]
+    Local,
+    /// The bucket from `[storage.s3]`
+    S3,
+}
+
+impl From<BackupAdapter> for proto::BackupAdapter {
+    fn from(adapter: BackupAdapter) -> Self {
+        match adapter {
+            BackupAdapter::Local => proto::BackupAdapter::Local,
+            BackupAdapter::S3 => proto::BackupAdapter::S3,
+        }
+    }
+}
+
+/// How a backup's files are stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackupFormat {
+    /// A single tar.zst archive
+    #[default]
+    Archive,
+    /// A snapshot of deduplicated chunks, see [`snapshots`]
+    Chunked,
+}
+
+impl From<BackupFormat> for proto::BackupFormat {
+    fn from(format: BackupFormat) -> Self {
+        match format {
+            BackupFormat::Archive => proto::BackupFormat::Archive,
+            BackupFormat::Chunked => proto::BackupFormat::Chunked,
+        }
+    }
+}
+
+/// Metadata stored next to each backup archive. Metadata of remote backups
+/// is also kept locally, so listing never needs the remote.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupInfo {
+    pub uuid: String,
+    pub server_uuid: String,
+    #[serde(default)]
+    pub adapter: BackupAdapter,
+    #[serde(default)]
+    pub format: BackupFormat,
+    /// Hex digest of the archive, or of the manifest for chunked snapshots
+    pub checksum: String,
+    pub checksum_type: String,
+    pub size: u64,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<&BackupInfo> for proto::BackupInfo {
+    fn from(info: &BackupInfo) -> Self {
+        Self {
+            backup_uuid: info.uuid.clone(),
+            checksum: info.checksum.clone(),
+            checksum_type: info.checksum_type.clone(),
+            size: info.size,
+            created_at_ms: info.created_at.timestamp_millis(),
+            adapter: proto::BackupAdapter::from(info.adapter).into(),
+            format: proto::BackupFormat::from(info.format).into(),
+        }
+    }
+}
+
+/// Backup ids end up in file names, so only canonical UUIDs are accepted.
+pub fn parse_backup_uuid(id: &str) -> Result<String, WingsError> {
+    uuid::Uuid::parse_str(id)
+        .map(|u| u.to_string())
+        .map_err(|_| WingsError::InvalidRequest(format!("Invalid backup uuid: {id}")))
+}
+
+fn server_backup_dir(backup_dir: &Path, server_uuid: &str) -> PathBuf {
+    backup_dir.join(server_uuid)
+}
+
+fn archive_path(backup_dir: &Path, server_uuid: &str, backup_uuid: &str) -> PathBuf {
+    server_backup_dir(backup_dir, server_uuid).join(format!("{backup_uuid}.tar.zst"))
+}
+
+fn metadata_path(backup_dir: &Path, server_uuid: &str, backup_uuid: &str) -> PathBuf {
+    server_backup_dir(backup_dir, server_uuid).join(format!("{backup_uuid}.json"))
+}
+
+/// Object keys of a remote backup's archive and metadata.
+fn object_keys(client: &S3Client, server_uuid: &str, backup_uuid: &str) -> (String, String) {
+    (
+        client.key(&format!("{server_uuid}/{backup_uuid}.tar.zst")),
+        client.key(&format!("{server_uuid}/{backup_uuid}.json")),
+    )
+}
+
+fn require_s3(s3: Option<&S3Client>) -> Result<&S3Client, WingsError> {
+    s3.ok_or_else(|| {
+        WingsError::InvalidRequest("S3 backups are not configured on this node".into())
+    })
+}
+
+/// All completed backups of a server, newest first.
+pub fn list(backup_dir: &Path, server_uuid: &str) -> Result<Vec<BackupInfo>, WingsError> {
+    let dir = server_backup_dir(backup_dir, server_uuid);
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(WingsError::Io(e)),
+    };
+
+    let mut backups: Vec<BackupInfo> = entries
+        .flatten()
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|e| std::fs::read(e.path()).ok())
+        .filter_map(|json| serde_json::from_slice(&json).ok())
+        .collect();
+    backups.sort_by_key(|b| std::cmp::Reverse(b.created_at));
+    Ok(backups)
+}
+
+pub fn get(
+    backup_dir: &Path,
+    server_uuid: &str,
+    backup_uuid: &str,
+) -> Result<BackupInfo, WingsError> {
+    let path = metadata_path(backup_dir, server_uuid, backup_uuid);
+    let json = std::fs::read(&path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => WingsError::BackupNotFound(backup_uuid.to_string()),
+        _ => WingsError::Io(e),
+    })?;
+    serde_json::from_slice(&json).map_err(|e| WingsError::Io(std::io::Error::other(e)))
+}
+
+pub async fn delete(
+    backup_dir: &Path,
+    s3: Option<&S3Client>,
+    server_uuid: &str,
+    backup_uuid: &str,
+) -> Result<(), WingsError> {
+    let info = get(backup_dir, server_uuid, backup_uuid)?;
+    if info.adapter == BackupAdapter::S3 {
+        let client = require_s3(s3)?;
+        let (archive_key, metadata_key) = object_keys(client, server_uuid, backup_uuid);
+        client.delete_object(&archive_key).await?;
+        client.delete_object(&metadata_key).await?;
+    }
+    // The metadata goes first so a half-deleted backup is never listed
+    std::fs::remove_file(metadata_path(backup_dir, server_uuid, backup_uuid))
+        .map_err(WingsError::Io)?;
+    // Chunks of a deleted snapshot are reclaimed by the next prune
+    let data_path = match info.format {
+        BackupFormat::Archive => archive_path(backup_dir, server_uuid, backup_uuid),
+        BackupFormat::Chunked => {
+            snapshots::manifest_path(&server_backup_dir(backup_dir, server_uuid), backup_uuid)
+        }
+    };
+    match std::fs::remove_file(data_path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(WingsError::Io(e)),
+        _ => Ok(()),
+    }
+}
+
+/// Writer that hashes and counts everything written through it.
+struct HashWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    written: u64,
+}
+
+impl<W: Write> Write for HashWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Everything below `root` that belongs in a backup, skipping paths matched
+/// by `.nexusignore` files and Wings' own bookkeeping files.
+fn collect_entries(root: &Path) -> Result<Vec<ArchiveEntry>, WingsError> {
+    let walker = ignore::WalkBuilder::new(root)
+        .standard_filters(false)
+        .add_custom_ignore_filename(IGNORE_FILE)
+        .follow_links(false)
+        .build();
+
+    let mut entries = Vec::new();
+    for entry in walker {
+        let entry = entry.map_err(|e| WingsError::Io(std::io::Error::other(e)))?;
+        if entry.depth() == 0 || files::is_wings_file(&entry.file_name().to_string_lossy()) {
+            continue;
+        }
+        let name = entry
+            .path()
+            .strip_prefix(root)
+            .unwrap_or(entry.path())
+            .to_string_lossy()
+            .to_string();
+        let metadata = entry.path().symlink_metadata().map_err(WingsError::Io)?;
+        entries.push(ArchiveEntry {
+            path: entry.into_path(),
+            name,
+            metadata,
+        });
+    }
+    Ok(entries)
+}
+
+/// Write a tar.zst of `entries` to `writer`, returning its checksum, its
+/// size and the writer.
+pub fn write_archive<W: Write>(
+    writer: W,
+    entries: &[ArchiveEntry],
+    progress: &JobProgress,
+) -> std::io::Result<(String, u64, W)> {
+    let writer = HashWriter {
+        inner: writer,
+        hasher: Sha256::new(),
+        written: 0,
+    };
+    let encoder = zstd::stream::write::Encoder::new(writer, 0)?;
+    let mut writer = files::write_tar(encoder, entries, progress)?.finish()?;
+    writer.flush()?;
+    Ok((
+        hex::encode(writer.hasher.finalize()),
+        writer.written,
+        writer.inner,
+    ))
+}
+
+fn write_local(
+    path: &Path,
+    entries: &[ArchiveEntry],
+    progress: &JobProgress,
+) -> std::io::Result<(String, u64)> {
+    let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let (checksum, size, file) = write_archive(file, entries, progress)?;
+    file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    Ok((checksum, size))
+}
+
+/// Stream the archive straight into a multipart upload, without staging it
+/// on disk.
+fn write_remote(
+    client: &S3Client,
+    key: &str,
+    entries: &[ArchiveEntry],
+    progress: &JobProgress,
+) -> Result<(String, u64), WingsError> {
+    let writer = client.upload_writer(key)?;
+    match write_archive(writer, entries, progress) {
+        Ok((checksum, size, writer)) => {
+            writer.finish()?;
+            Ok((checksum, size))
+        }
+        Err(_) if progress.is_cancelled() => Err(WingsError::Cancelled),
+        Err(e) => Err(WingsError::Io(e)),
+    }
+}
+
+/// Archive the server root `root` into a new backup, stored locally or in
+/// the S3 bucket when `s3` is given.
+pub fn create(
+    root: &Path,
+    backup_dir: &Path,
+    s3: Option<&S3Client>,
+    server_uuid: &str,
+    backup_uuid: &str,
+    progress: &JobProgress,
+) -> Result<BackupInfo, WingsError> {
+    let entries = collect_entries(root)?;
+    progress.set_total(total_size(&entries));
+    std::fs::create_dir_all(server_backup_dir(backup_dir, server_uuid)).map_err(WingsError::Io)?;
+
+    let (adapter, checksum, size) = match s3 {
+        Some(client) => {
+            let (archive_key, _) = object_keys(client, server_uuid, backup_uuid);
+            let (checksum, size) = write_remote(client, &archive_key, &entries, progress)?;
+            (BackupAdapter::S3, checksum, size)
+        }
+        None => {
+            let path = archive_path(backup_dir, server_uuid, backup_uuid);
+            let tmp_path = path.with_extension("part");
+            let (checksum, size) = match write_local(&tmp_path, &entries, progress) {
+                Ok(result) => result,
+                Err(_) if progress.is_cancelled() => {
+                    let _ = std::fs::remove_file(&tmp_path);
+                    return Err(WingsError::Cancelled);
+                }
+                Err(e) => {
+                    let _ = std::fs::remove_file(&tmp_path);
+                    return Err(WingsError::Io(e));
+                }
+            };
+            std::fs::rename(&tmp_path, &path).map_err(WingsError::Io)?;
+            (BackupAdapter::Local, checksum, size)
+        }
+    };
+
+    let info = BackupInfo {
+        uuid: backup_uuid.to_string(),
+        server_uuid: server_uuid.to_string(),
+        adapter,
+        format: BackupFormat::Archive,
+        checksum,
+        checksum_type: CHECKSUM_TYPE.to_string(),
+        size,
+        created_at: Utc::now(),
+    };
+    let json = serde_json::to_vec_pretty(&info).map_err(|e| WingsError::Io(e.into()))?;
+    if let Some(client) = s3 {
+        // Keep the bucket self-describing in case the node is lost
+        let (_, metadata_key) = object_keys(client, server_uuid, backup_uuid);
+        tokio::runtime::Handle::current()
+            .block_on(client.put_object(&metadata_key, json.clone().into()))?;
+    }
+    files::write_file(&metadata_path(backup_dir, server_uuid, backup_uuid), &json)?;
+    Ok(info)
+}
+
+/// Snapshot the server root `root` into the server's chunk store, writing
+/// only chunks that no earlier snapshot has stored.
+pub fn create_chunked(
+    root: &Path,
+    backup_dir: &Path,
+    server_uuid: &str,
+    backup_uuid: &str,
+    progress: &JobProgress,
+) -> Result<BackupInfo, WingsError> {
+    let entries = collect_entries(root)?;
+    progress.set_total(total_size(&entries));
+    let dir = server_backup_dir(backup_dir, server_uuid);
+    std::fs::create_dir_all(&dir).map_err(WingsError::Io)?;
+
+    let stats = snapshots::create(&dir, backup_uuid, &entries, progress)?;
+    tracing::info!(
+        uuid = %server_uuid,
+        backup = %backup_uuid,
+        new_chunks = stats.new_chunks,
+        new_bytes = stats.new_bytes,
+        "Snapshot written"
+    );
+    let info = BackupInfo {
+        uuid: backup_uuid.to_string(),
+        server_uuid: server_uuid.to_string(),
+        adapter: BackupAdapter::Local,
+        format: BackupFormat::Chunked,
+        checksum: stats.checksum,
+        checksum_type: CHECKSUM_TYPE.to_string(),
+        size: stats.size,
+        created_at: Utc::now(),
+    };
+    let json = serde_json::to_vec_pretty(&info).map_err(|e| WingsError::Io(e.into()))?;
+    files::write_file(&metadata_path(backup_dir, server_uuid, backup_uuid), &json)?;
+    Ok(info)
+}
+
+fn total_size(entries: &[ArchiveEntry]) -> u64 {
+    entries
+        .iter()
+        .filter(|e| e.metadata.is_file())
+        .map(|e| e.metadata.len())
+        .sum()
+}
+
+fn checksum_file(path: &Path, progress: &JobProgress) -> Result<String, WingsError> {
+    let mut file = std::fs::File::open(path).map_err(WingsError::Io)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 256 * 1024];
+    loop {
+        if progress.is_cancelled() {
+            return Err(WingsError::Cancelled);
+        }
+        let n = file.read(&mut buf).map_err(WingsError::Io)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Remove everything in a server root except Wings' own files.
+fn wipe(root: &Path) -> Result<(), WingsError> {
+    for entry in std::fs::read_dir(root).map_err(WingsError::Io)? {
+        let entry = entry.map_err(WingsError::Io)?;
+        if files::is_wings_file(&entry.file_name().to_string_lossy()) {
+            continue;
+        }
+        let path = entry.path();
+        let result = if entry.file_type().map_err(WingsError::Io)?.is_dir() {
+            std::fs::remove_dir_all(&path)
+        } else {
+            std::fs::remove_file(&path)
+        };
+        result.map_err(WingsError::Io)?;
+    }
+    Ok(())
+}
+
+/// Backups are our own checksummed archives, so only path safety is
+/// enforced when extracting them.
+pub fn restore_limits() -> ExtractLimits {
+    ExtractLimits {
+        max_size: u64::MAX,
+        max_entries: u64::MAX,
+        max_ratio: u64::MAX,
+        ..Default::default()
+    }
+}
+
+/// Verify `archive` against its recorded checksum and extract it over the
+/// server root, optionally wiping the root first.
+pub fn restore(
+    root: &Path,
+    archive: &Path,
+    info: &BackupInfo,
+    wipe_first: bool,
+    progress: &JobProgress,
+) -> Result<(), WingsError> {
+    if checksum_file(archive, progress)? != info.checksum {
+        return Err(WingsError::ArchiveRejected(format!(
+            "Backup {} does not match its checksum",
+            info.uuid
+        )));
+    }
+    if wipe_first {
+        wipe(root)?;
+    }
+    let dest = root.canonicalize().map_err(WingsError::Io)?;
+    files::decompress(root, archive, &dest, progress, &restore_limits())
+}
+
+/// Rebuild the server root from a chunked snapshot, optionally wiping the
+/// root first. The manifest is verified up front; chunks as they are read.
+pub fn restore_chunked(
+    root: &Path,
+    backup_dir: &Path,
+    info: &BackupInfo,
+    wipe_first: bool,
+    progress: &JobProgress,
+) -> Result<(), WingsError> {
+    let dir = server_backup_dir(backup_dir, &info.server_uuid);
+    let manifest = snapshots::open(&dir, &info.uuid, &info.checksum)?;
+    if wipe_first {
+        wipe(root)?;
+    }
+    let dest = root.canonicalize().map_err(WingsError::Io)?;
+    snapshots::extract(
+        &dir,
+        &manifest,
+        root,
+        &dest,
+        info.size,
+        progress,
+        &restore_limits(),
+    )
+}
+
+/// Check a backup's stored data against its checksums. Remote archives are
+/// downloaded in full to do so.
+pub fn verify(
+    backup_dir: &Path,
+    s3: Option<&S3Client>,
+    server_uuid: &str,
+    backup_uuid: &str,
+) -> Result<VerifyReport, WingsError> {
+    let info = get(backup_dir, server_uuid, backup_uuid)?;
+    let missing = VerifyReport {
+        archive_missing: true,
+        ..Default::default()
+    };
+    let checksum = match (info.format, info.adapter) {
+        (BackupFormat::Chunked, _) => {
+            let dir = server_backup_dir(backup_dir, server_uuid);
+            if !snapshots::manifest_path(&dir, backup_uuid).exists() {
+                return Ok(missing);
+            }
+            return snapshots::verify(&dir, backup_uuid, &info.checksum);
+        }
+        (BackupFormat::Archive, BackupAdapter::Local) => {
+            let archive = archive_path(backup_dir, server_uuid, backup_uuid);
+            if !archive.exists() {
+                return Ok(missing);
+            }
+            checksum_file(&archive, &JobProgress::default())?
+        }
+        (BackupFormat::Archive, BackupAdapter::S3) => {
+            let client = require_s3(s3)?;
+            let (archive_key, _) = object_keys(client, server_uuid, backup_uuid);
+            let runtime = tokio::runtime::Handle::current();
+            if !runtime.block_on(client.object_exists(&archive_key))? {
+                return Ok(missing);
+            }
+            let mut writer = HashWriter {
+                inner: std::io::sink(),
+                hasher: Sha256::new(),
+                written: 0,
+            };
+            std::io::copy(&mut client.object_reader(&archive_key)?, &mut writer)
+                .map_err(WingsError::Io)?;
+            hex::encode(writer.hasher.finalize())
+        }
+    };
+    Ok(VerifyReport {
+        valid: checksum == info.checksum,
+        ..Default::default()
+    })
+}
+
+/// Result of applying a retention policy.
+#[derive(Debug, Default)]
+pub struct PruneOutcome {
+    pub removed: Vec<String>,
+    pub freed_chunks: u64,
+    pub freed_bytes: u64,
+}
+
+/// Delete the chunked snapshots of a server not kept by `policy`, then drop
+/// chunks no remaining snapshot references. Archive backups are left alone.
+pub fn prune(
+    backup_dir: &Path,
+    server_uuid: &str,
+    policy: &RetentionPolicy,
+) -> Result<PruneOutcome, WingsError> {
+    let dir = server_backup_dir(backup_dir, server_uuid);
+    let snapshots: Vec<BackupInfo> = list(backup_dir, server_uuid)?
+        .into_iter()
+        .filter(|b| b.format == BackupFormat::Chunked)
+        .collect();
+    let created: Vec<_> = snapshots.iter().map(|b| b.created_at).collect();
+    let kept = policy.keep(&created);
+
+    let mut outcome = PruneOutcome::default();
+    let mut manifests = Vec::new();
+    for (i, info) in snapshots.iter().enumerate() {
+        if kept.contains(&i) {
+            // A snapshot that can't be read aborts the prune rather than
+            // losing the chunks it references
+            manifests.push(snapshots::open(&dir, &info.uuid, &info.checksum)?);
+            continue;
+        }
+        std::fs::remove_file(metadata_path(backup_dir, server_uuid, &info.uuid))
+            .map_err(WingsError::Io)?;
+        let _ = std::fs::remove_file(snapshots::manifest_path(&dir, &info.uuid));
+        outcome.removed.push(info.uuid.clone());
+    }
+    (outcome.freed_chunks, outcome.freed_bytes) = snapshots::collect_garbage(&dir, &manifests)?;
+    Ok(outcome)
+}
+
+/// Reader that fails at end of stream if the data did not match `expected`.
+struct VerifyingReader<R> {
+    inner: R,
+    hasher: Option<Sha256>,
+    expected: String,
+}
+
+impl<R: Read> Read for VerifyingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            if let Some(hasher) = &mut self.hasher {
+                hasher.update(&buf[..n]);
+            }
+        } else if let Some(hasher) = self.hasher.take() {
+            if hex::encode(hasher.finalize()) != self.expected {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "backup does not match its checksum",
+                ));
+            }
+        }
+        Ok(n)
+    }
+}
+
+/// Stream a remote backup from the bucket and extract it over the server
+/// root. The checksum is verified as the download completes; on a mismatch
+/// the extracted files are removed again, but a wipe cannot be undone.
+pub fn restore_remote(
+    root: &Path,
+    client: &S3Client,
+    info: &BackupInfo,
+    wipe_first: bool,
+    progress: &JobProgress,
+) -> Result<(), WingsError> {
+    let (archive_key, _) = object_keys(client, &info.server_uuid, &info.uuid);
+    // Start the download before wiping, so a missing object changes nothing
+    let reader = VerifyingReader {
+        inner: client.object_reader(&archive_key)?,
+        hasher: Some(Sha256::new()),
+        expected: info.checksum.clone(),
+    };
+    if wipe_first {
+        wipe(root)?;
+    }
+    let dest = root.canonicalize().map_err(WingsError::Io)?;
+    files::decompress_tar_stream(
+        root,
+        reader,
+        &dest,
+        files::Compression::Zstd,
+        info.size,
+        progress,
+        &restore_limits(),
+    )
+}
+
+fn server_root(state: &AppState, server_uuid: &str) -> PathBuf {
+    Path::new(&state.config.storage.data_dir).join(server_uuid)
+}
+
+/// Start a job for `operation`. Only one backup, restore, prune or
+/// transfer runs for a server at a time; the others are refused while it
+/// does.
+fn spawn_job<F>(
+    state: &Arc<AppState>,
+    server_uuid: &str,
+    operation: &'static str,
+    work: F,
+) -> Result<String, WingsError>
+where
+    F: FnOnce(&JobProgress) -> Result<(), WingsError> + Send + 'static,
+{
+    jobs::spawn_exclusive(
+        state.clone(),
+        server_uuid,
+        operation,
+        jobs::SERVER_DATA_JOBS,
+        work,
+    )
+}
+
+/// Start backing up a server in the background. Returns the backup uuid
+/// (generated unless given) and the job id.
+pub fn start_backup(
+    state: &Arc<AppState>,
+    server_uuid: &str,
+    backup_uuid: Option<&str>,
+    adapter: BackupAdapter,
+    format: BackupFormat,
+) -> Result<(String, String), WingsError> {
+    if adapter == BackupAdapter::S3 {
+        require_s3(state.s3.as_ref())?;
+        if format == BackupFormat::Chunked {
+            return Err(WingsError::InvalidRequest(
+                "Chunked backups can only be stored locally".into(),
+            ));
+        }
+    }
+    let backup_uuid = match backup_uuid.filter(|id| !id.is_empty()) {
+        Some(id) => parse_backup_uuid(id)?,
+        None => uuid::Uuid::new_v4().to_string(),
+    };
+    let backup_dir = PathBuf::from(&state.config.storage.backup_dir);
+    if metadata_path(&backup_dir, server_uuid, &backup_uuid).exists() {
+        return Err(WingsError::InvalidRequest(format!(
+            "Backup {backup_uuid} already exists"
+        )));
+    }
+    let root = server_root(state, server_uuid);
+    if !root.is_dir() {
+        return Err(WingsError::ServerNotFound(server_uuid.to_string()));
+    }
+
+    let job_state = state.clone();
+    let uuid = server_uuid.to_string();
+    let id = backup_uuid.clone();
+    let job_id = spawn_job(state, server_uuid, "backup", move |progress| {
+        let s3 = match adapter {
+            BackupAdapter::S3 => job_state.s3.as_ref(),
+            BackupAdapter::Local => None,
+        };
+        let result = match format {
+            BackupFormat::Archive => create(&root, &backup_dir, s3, &uuid, &id, progress),
+            BackupFormat::Chunked => create_chunked(&root, &backup_dir, &uuid, &id, progress),
+        };
+        if let Err(e) = &result {
+            tracing::error!(uuid = %uuid, backup = %id, error = %e, "Backup failed");
+        }
+        job_state.emit_event(WingsEvent {
+            event: Some(wings_event::Event::BackupCompleted(BackupCompleted {
+                uuid: uuid.clone(),
+                backup_uuid: id.clone(),
+                successful: result.is_ok(),
+                backup: result.as_ref().ok().map(Into::into),
+                error_message: result
+                    .as_ref()
+                    .err()
+                    .map(|e| e.to_string())
+                    .unwrap_or_default(),
+                timestamp_ms: Utc::now().timestamp_millis(),
+            })),
+        });
+        result.map(|_| ())
+    })?;
+    Ok((backup_uuid, job_id))
+}
+
+/// Restore a backup in the background: the server is stopped if running,
+/// the archive is extracted and the server is started again.
+pub fn start_restore(
+    state: &Arc<AppState>,
+    server_uuid: &str,
+    backup_uuid: &str,
+    wipe_first: bool,
+) -> Result<String, WingsError> {
+    let backup_uuid = parse_backup_uuid(backup_uuid)?;
+    let backup_dir = PathBuf::from(&state.config.storage.backup_dir);
+    let info = get(&backup_dir, server_uuid, &backup_uuid)?;
+    if info.adapter == BackupAdapter::S3 {
+        require_s3(state.s3.as_ref())?;
+    }
+    let archive = archive_path(&backup_dir, server_uuid, &backup_uuid);
+    let root = server_root(state, server_uuid);
+
+    let job_state = state.clone();
+    let uuid = server_uuid.to_string();
+    let job_id = spawn_job(state, server_uuid, "restore", move |progress| {
+        // Docker calls are async; this closure runs on a blocking thread
+        let runtime = tokio::runtime::Handle::current();
+        let docker = &job_state.docker;
+        // Keep power actions from starting the server while its files are
+        // replaced
+        let lock = job_state.server_lock(&uuid);
+        let _guard = lock.blocking_lock();
+        let was_running = runtime
+            .block_on(docker.get_container_status(&uuid))
+            .is_ok_and(|status| status == "running");
+
+        let result = (|| {
+            if was_running {
+                runtime.block_on(docker.stop_server(&uuid, 30))?;
+            }
+            match (info.format, job_state.s3.as_ref()) {
+                (BackupFormat::Chunked, _) => {
+                    restore_chunked(&root, &backup_dir, &info, wipe_first, progress)?
+                }
+                (BackupFormat::Archive, Some(client)) if info.adapter == BackupAdapter::S3 => {
+                    restore_remote(&root, client, &info, wipe_first, progress)?
+                }
+                _ => restore(&root, &archive, &info, wipe_first, progress)?,
+            }
+            files::chown_recursive(&root, docker.container_user())
+        })();
+
+        if was_running {
+            if let Err(e) = runtime.block_on(docker.start_server(&uuid)) {
+                tracing::error!(uuid = %uuid, error = %e, "Failed to start server after restore");
+            }
+        }
+        if let Err(e) = &result {
+            tracing::error!(uuid = %uuid, backup = %info.uuid, error = %e, "Restore failed");
+        }
+        job_state.emit_event(WingsEvent {
+            event: Some(wings_event::Event::BackupRestored(BackupRestored {
+                uuid: uuid.clone(),
+                backup_uuid: info.uuid.clone(),
+                successful: result.is_ok(),
+                error_message: result
+                    .as_ref()
+                    .err()
+                    .map(|e| e.to_string())
+                    .unwrap_or_default(),
+                timestamp_ms: Utc::now().timestamp_millis(),
+            })),
+        });
+        result
+    })?;
+    Ok(job_id)
+}
+
+/// Apply a retention policy to a server's chunked snapshots in the
+/// background. Running as a job keeps backups from adding chunks while
+/// unreferenced ones are collected.
+pub fn start_prune(
+    state: &Arc<AppState>,
+    server_uuid: &str,
+    policy: RetentionPolicy,
+) -> Result<String, WingsError> {
+    if policy.keeps_nothing() {
+        return Err(WingsError::InvalidRequest(
+            "Retention policy must keep at least one snapshot".into(),
+        ));
+    }
+    let backup_dir = PathBuf::from(&state.config.storage.backup_dir);
+
+    let job_state = state.clone();
+    let uuid = server_uuid.to_string();
+    let job_id = spawn_job(state, server_uuid, "prune", move |_| {
+        let result = prune(&backup_dir, &uuid, &policy);
+        match &result {
+            Ok(outcome) => tracing::info!(
+                uuid = %uuid,
+                removed = outcome.removed.len(),
+                freed_bytes = outcome.freed_bytes,
+                "Backups pruned"
+            ),
+            Err(e) => tracing::error!(uuid = %uuid, error = %e, "Prune failed"),
+        }
+        let outcome = result.as_ref().ok();
+        job_state.emit_event(WingsEvent {
+            event: Some(wings_event::Event::BackupsPruned(BackupsPruned {
+                uuid: uuid.clone(),
+                successful: result.is_ok(),
+                removed_backup_uuids: outcome.map(|o| o.removed.clone()).unwrap_or_default(),
+                freed_chunks: outcome.map_or(0, |o| o.freed_chunks),
+                freed_bytes: outcome.map_or(0, |o| o.freed_bytes),
+                error_message: result
+                    .as_ref()
+                    .err()
+                    .map(|e| e.to_string())
+                    .unwrap_or_default(),
+                timestamp_ms: Utc::now().timestamp_millis(),
+            })),
+        });
+        result.map(|_| ())
+    })?;
+    Ok(job_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const BACKUP_UUID: &str = "6f1c2a7e-43b1-4d43-9d0e-4f3a1c5b8e21";
+
+    #[test]
+    fn test_backup_honors_ignore_file_and_restores() {
+        let data = TempDir::new().unwrap();
+        let backups = TempDir::new().unwrap();
+        let root = data.path();
+        std::fs::create_dir_all(root.join("world/region")).unwrap();
+        std::fs::create_dir_all(root.join("logs")).unwrap();
+        std::fs::write(root.join("world/region/r.0.0.mca"), "chunks").unwrap();
+        std::fs::write(root.join("server.properties"), "motd=hi").unwrap();
+        std::fs::write(root.join("logs/latest.log"), "noise").unwrap();
+        std::fs::write(root.join("cache.tmp"), "noise").unwrap();
+        std::fs::write(root.join(".nexus-config.json"), "{}").unwrap();
+        std::fs::write(root.join(IGNORE_FILE), "logs/\n*.tmp\n").unwrap();
+
+        let progress = JobProgress::default();
+        let info = create(root, backups.path(), None, "srv", BACKUP_UUID, &progress).unwrap();
+        assert_eq!(info.checksum.len(), 64);
+        let listed = list(backups.path(), "srv").unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].size, info.size);
+
+        // Change the server, then restore with a wipe
+        std::fs::write(root.join("server.properties"), "motd=changed").unwrap();
+        std::fs::write(root.join("new.txt"), "new").unwrap();
+        let archive = archive_path(backups.path(), "srv", BACKUP_UUID);
+        restore(root, &archive, &info, true, &progress).unwrap();
+
+        let restored = std::fs::read_to_string(root.join("server.properties")).unwrap();
+        assert_eq!(restored, "motd=hi");
+        assert!(root.join("world/region/r.0.0.mca").exists());
+        assert!(!root.join("new.txt").exists());
+        assert!(!root.join("logs").exists());
+        assert!(!root.join("cache.tmp").exists());
+        // Wings' own config survives the wipe
+        assert!(root.join(".nexus-config.json").exists());
+
+        assert!(verify(backups.path(), None, "srv", BACKUP_UUID).unwrap().valid);
+        let stored = std::fs::read(&archive).unwrap();
+        std::fs::remove_file(&archive).unwrap();
+        let report = verify(backups.path(), None, "srv", BACKUP_UUID).unwrap();
+        assert!(!report.valid && report.archive_missing);
+        std::fs::write(&archive, stored).unwrap();
+
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(delete(backups.path(), None, "srv", BACKUP_UUID))
+            .unwrap();
+        assert!(list(backups.path(), "srv").unwrap().is_empty());
+        assert!(!archive.exists());
+    }
+
+    #[test]
+    fn test_restore_rejects_checksum_mismatch() {
+        let data = TempDir::new().unwrap();
+        let backups = TempDir::new().unwrap();
+        std::fs::write(data.path().join("a.txt"), "a").unwrap();
+
+        let progress = JobProgress::default();
+        let mut info = create(
+            data.path(),
+            backups.path(),
+            None,
+            "srv",
+            BACKUP_UUID,
+            &progress,
+        )
+        .unwrap();
+        info.checksum = "0".repeat(64);
+        let archive = archive_path(backups.path(), "srv", BACKUP_UUID);
+        let result = restore(data.path(), &archive, &info, true, &progress);
+        assert!(matches!(result, Err(WingsError::ArchiveRejected(_))));
+        // Nothing was wiped
+        assert!(data.path().join("a.txt").exists());
+    }
+
+    #[test]
+    fn test_parse_backup_uuid_rejects_paths() {
+        assert!(parse_backup_uuid("../../etc/passwd").is_err());
+        assert_eq!(parse_backup_uuid(BACKUP_UUID).unwrap(), BACKUP_UUID);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_s3_backup_roundtrip() {
+        let (config, store) = crate::s3::stand_in::spawn().await;
+        let client = S3Client::new(&config).unwrap();
+        let data = TempDir::new().unwrap();
+        let backups = TempDir::new().unwrap();
+        std::fs::write(data.path().join("server.properties"), "motd=hi").unwrap();
+
+        let (root, backup_dir) = (data.path().to_path_buf(), backups.path().to_path_buf());
+        let client = tokio::task::spawn_blocking(move || {
+            let progress = JobProgress::default();
+            let info = create(
+                &root,
+                &backup_dir,
+                Some(&client),
+                "srv",
+                BACKUP_UUID,
+                &progress,
+            )
+            .unwrap();
+            assert_eq!(info.adapter, BackupAdapter::S3);
+            // Only the metadata is kept on the node
+            assert!(!archive_path(&backup_dir, "srv", BACKUP_UUID).exists());
+
+            std::fs::write(root.join("server.properties"), "motd=changed").unwrap();
+            let info = get(&backup_dir, "srv", BACKUP_UUID).unwrap();
+            restore_remote(&root, &client, &info, true, &progress).unwrap();
+            assert!(verify(&backup_dir, Some(&client), "srv", BACKUP_UUID).unwrap().valid);
+            client
+        })
+        .await
+        .unwrap();
+
+        let restored = std::fs::read_to_string(data.path().join("server.properties")).unwrap();
+        assert_eq!(restored, "motd=hi");
+        let archive_key = format!("node/srv/{BACKUP_UUID}.tar.zst");
+        assert!(store.objects.lock().unwrap().remove(&archive_key).is_some());
+        let (backup_dir, client) = (backups.path().to_path_buf(), Arc::new(client));
+        let report = tokio::task::spawn_blocking({
+            let client = client.clone();
+            move || verify(&backup_dir, Some(&client), "srv", BACKUP_UUID)
+        })
+        .await
+        .unwrap()
+        .unwrap();
+        assert!(!report.valid && report.archive_missing);
+
+        delete(backups.path(), Some(&client), "srv", BACKUP_UUID)
+            .await
+            .unwrap();
+        assert!(store.objects.lock().unwrap().is_empty());
+        assert!(list(backups.path(), "srv").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_chunked_snapshots_dedupe_restore_verify_and_prune() {
+        let data = TempDir::new().unwrap();
+        let backups = TempDir::new().unwrap();
+        let root = data.path();
+        let mut seed = 7u32;
+        let world: Vec<u8> = (0..3 * 1024 * 1024)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 16) as u8
+            })
+            .collect();
+        std::fs::write(root.join("world.dat"), &world).unwrap();
+        std::fs::write(root.join("server.properties"), "motd=hi").unwrap();
+        let chunk_count = || {
+            walkdir::WalkDir::new(backups.path().join("srv/chunks"))
+                .into_iter()
+                .flatten()
+                .filter(|e| e.file_type().is_file())
+                .count()
+        };
+
+        let progress = JobProgress::default();
+        const SECOND_UUID: &str = "0b3e7c7a-5d9e-4b7e-8d1a-2c9f4e6a7b10";
+        let first = create_chunked(root, backups.path(), "srv", BACKUP_UUID, &progress).unwrap();
+        let stored = chunk_count();
+        assert!(stored > 2);
+        std::fs::write(root.join("server.properties"), "motd=changed").unwrap();
+        let second = create_chunked(root, backups.path(), "srv", SECOND_UUID, &progress).unwrap();
+        // Only the changed file needed a new chunk
+        assert_eq!(chunk_count(), stored + 1);
+        assert_eq!(second.format, BackupFormat::Chunked);
+        assert_eq!(second.size, first.size + 5);
+
+        std::fs::remove_file(root.join("world.dat")).unwrap();
+        restore_chunked(root, backups.path(), &second, true, &progress).unwrap();
+        assert_eq!(std::fs::read(root.join("world.dat")).unwrap(), world);
+        let restored = std::fs::read_to_string(root.join("server.properties")).unwrap();
+        assert_eq!(restored, "motd=changed");
+
+        // Both snapshots fall into the same hour, so only the newest is kept
+        let policy = RetentionPolicy {
+            hourly: 1,
+            ..Default::default()
+        };
+        let outcome = prune(backups.path(), "srv", &policy).unwrap();
+        assert_eq!(outcome.removed, vec![BACKUP_UUID.to_string()]);
+        assert_eq!(outcome.freed_chunks, 1);
+        assert_eq!(chunk_count(), stored);
+        assert!(
+            verify(backups.path(), None, "srv", SECOND_UUID)
+                .unwrap()
+                .valid
+        );
+
+        let chunk = walkdir::WalkDir::new(backups.path().join("srv/chunks"))
+            .into_iter()
+            .flatten()
+            .find(|e| e.file_type().is_file())
+            .unwrap();
+        std::fs::write(chunk.path(), zstd::encode_all(&b"garbage"[..], 3).unwrap()).unwrap();
+        let report = verify(backups.path(), None, "srv", SECOND_UUID).unwrap();
+        assert!(!report.valid);
+        assert_eq!(report.corrupt_chunks.len(), 1);
+        assert!(restore_chunked(root, backups.path(), &second, false, &progress).is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_restore_waits_for_server_lock() {
+        let dir = TempDir::new().unwrap();
+        let (state, _events) = crate::state::test_node(dir.path());
+        let root = dir.path().join("data/srv");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("server.properties"), "motd=hi").unwrap();
+        let backup_dir = dir.path().join("backups");
+        let progress = JobProgress::default();
+        create(&root, &backup_dir, None, "srv", BACKUP_UUID, &progress).unwrap();
+        std::fs::write(root.join("server.properties"), "motd=changed").unwrap();
+
+        let mut events = state.subscribe_events();
+        let lock = state.server_lock("srv");
+        let guard = lock.lock().await;
+        start_restore(&state, "srv", BACKUP_UUID, true).unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        let properties = || std::fs::read_to_string(root.join("server.properties")).unwrap();
+        assert_eq!(properties(), "motd=changed");
+
+        drop(guard);
+        let restored = loop {
+            if let Some(wings_event::Event::BackupRestored(restored)) =
+                events.recv().await.unwrap().event
+            {
+                break restored;
+            }
+        };
+        assert!(restored.successful, "{}", restored.error_message);
+        assert_eq!(properties(), "motd=hi");
+    }
+}
@@ -0,0 +1,259 @@
+//! Per-server bandwidth limits, shaped with `tc` on the host end of each of
+//! the container's veth pairs. Traffic the server sends arrives on the veth's
+//! ingress and is policed there; traffic to the server leaves through the
+//! veth's root qdisc, a token bucket filter.
+//!
+//! A veth only lives as long as its container runs, so limits are applied
+//! whenever Docker reports a server container starting.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bollard::system::EventsOptions;
+use futures_util::StreamExt;
+
+use crate::docker::ServerConfig;
+use crate::error::WingsError;
+use crate::state::AppState;
+
+/// How long to wait before watching Docker events again after losing them.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Bucket size for a rate: 10ms worth of traffic, and no less than a few
+/// full-size packets.
+fn burst_kbyte(mbit: u64) -> u64 {
+    (mbit * 1000 / 8 / 100).max(32)
+}
+
+/// `tc` invocations limiting `dev` to the server's limits. Existing limits
+/// must be cleared first.
+fn tc_commands(dev: &str, egress_mbit: u64, ingress_mbit: u64) -> Vec<Vec<String>> {
+    let mut commands = Vec::new();
+    let args = |line: String| line.split_whitespace().map(String::from).collect();
+    if ingress_mbit > 0 {
+        commands.push(args(format!(
+            "qdisc add dev {dev} root tbf rate {ingress_mbit}mbit burst {}kb latency 50ms",
+            burst_kbyte(ingress_mbit)
+        )));
+    }
+    if egress_mbit > 0 {
+        commands.push(args(format!("qdisc add dev {dev} handle ffff: ingress")));
+        commands.push(args(format!(
+            "filter add dev {dev} parent ffff: protocol all u32 match u32 0 0 \
+             police rate {egress_mbit}mbit burst {}kb drop flowid :1",
+            burst_kbyte(egress_mbit)
+        )));
+    }
+    commands
+}
+
+async fn tc(args: &[String]) -> Result<(), WingsError> {
+    let output = tokio::process::Command::new("tc")
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| WingsError::Io(std::io::Error::other(format!("Failed to run tc: {e}"))))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(WingsError::Io(std::io::Error::other(format!(
+            "tc {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))))
+    }
+}
+
+/// Host interface whose index is `ifindex`.
+fn interface_by_index(sys_class_net: &Path, ifindex: &str) -> Option<String> {
+    std::fs::read_dir(sys_class_net)
+        .ok()?
+        .flatten()
+        .find(|entry| {
+            std::fs::read_to_string(entry.path().join("ifindex"))
+                .is_ok_and(|index| index.trim() == ifindex)
+        })
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+}
+
+/// Host ends of the veth pairs of a container, whose sysfs shows its own
+/// network namespace: a container interface's `iflink` is the index of its
+/// peer on the host. A container on several networks has one per network.
+fn host_veths(container_net: &Path, host_net: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(container_net) else {
+        return Vec::new();
+    };
+    let mut veths: Vec<String> = entries
+        .flatten()
+        .filter(|entry| entry.file_name() != "lo")
+        .filter_map(|entry| {
+            let read = |file| std::fs::read_to_string(entry.path().join(file)).ok();
+            let iflink = read("iflink")?;
+            // Interfaces that aren't veths are their own link
+            if Some(iflink.trim()) == read("ifindex").as_deref().map(str::trim) {
+                return None;
+            }
+            interface_by_index(host_net, iflink.trim())
+        })
+        .collect();
+    veths.sort();
+    veths
+}
+
+/// Apply a server's bandwidth limits to its running container, replacing
+/// any applied before. Does nothing for stopped servers.
+pub async fn apply(state: &AppState, config: &ServerConfig) -> Result<(), WingsError> {
+    let Some(pid) = state.docker.container_pid(&config.uuid).await? else {
+        return Ok(());
+    };
+    let container_net = format!("/proc/{pid}/root/sys/class/net");
+    let devs = host_veths(Path::new(&container_net), Path::new("/sys/class/net"));
+    if devs.is_empty() {
+        return Err(WingsError::Io(std::io::Error::other(format!(
+            "No host interface for the container of {}",
+            config.uuid
+        ))));
+    }
+    for dev in &devs {
+        for qdisc in ["root", "ingress"] {
+            // Fails when there is nothing to delete
+            let _ = tc(&["qdisc", "del", "dev", dev, qdisc].map(String::from)).await;
+        }
+        for args in tc_commands(dev, config.egress_limit, config.ingress_limit) {
+            tc(&args).await?;
+        }
+    }
+    tracing::info!(
+        uuid = %config.uuid,
+        devs = devs.join(","),
+        egress_mbit = config.egress_limit,
+        ingress_mbit = config.ingress_limit,
+        "Applied bandwidth limits"
+    );
+    Ok(())
+}
+
+/// Apply the limits of a server that just started.
+async fn on_start(state: &AppState, uuid: &str) {
+    let Some(config) = state.get_server_config(uuid).await else {
+        return;
+    };
+    if config.egress_limit == 0 && config.ingress_limit == 0 {
+        return;
+    }
+    if let Err(e) = apply(state, &config).await {
+        tracing::warn!(uuid, error = %e, "Failed to apply bandwidth limits");
+    }
+}
+
+/// Apply bandwidth limits to running servers, then to every server
+/// container Docker starts, however it was started.
+pub fn start(state: &Arc<AppState>) {
+    let state = state.clone();
+    tokio::spawn(async move {
+        for config in state.list_server_configs().await {
+            on_start(&state, &config.uuid).await;
+        }
+        loop {
+            let filters = HashMap::from([
+                ("type", vec!["container"]),
+                ("event", vec!["start"]),
+                ("label", vec!["nexus.managed=true"]),
+            ]);
+            let mut events = state.docker.client().events(Some(EventsOptions {
+                filters,
+                ..Default::default()
+            }));
+            while let Some(event) = events.next().await {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(e) => {
+                        tracing::warn!(error = %e, "Lost Docker events");
+                        break;
+                    }
+                };
+                let uuid = event
+                    .actor
+                    .and_then(|actor| actor.attributes)
+                    .and_then(|mut attributes| attributes.remove("nexus.server_uuid"));
+                if let Some(uuid) = uuid {
+                    on_start(&state, &uuid).await;
+                }
+            }
+            tokio::time::sleep(RETRY_DELAY).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tc_commands() {
+        assert!(tc_commands("veth1", 0, 0).is_empty());
+
+        let commands: Vec<String> = tc_commands("veth1", 50, 200)
+            .iter()
+            .map(|args| args.join(" "))
+            .collect();
+        assert_eq!(
+            commands,
+            [
+                "qdisc add dev veth1 root tbf rate 200mbit burst 250kb latency 50ms",
+                "qdisc add dev veth1 handle ffff: ingress",
+                "filter add dev veth1 parent ffff: protocol all u32 match u32 0 0 \
+                 police rate 50mbit burst 62kb drop flowid :1",
+            ]
+        );
+
+        // Small rates still get a usable bucket
+        assert_eq!(
+            tc_commands("veth1", 1, 0)[1].join(" "),
+            "filter add dev veth1 parent ffff: protocol all u32 match u32 0 0 \
+             police rate 1mbit burst 32kb drop flowid :1"
+        );
+    }
+
+    #[test]
+    fn test_interface_by_index() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, index) in [("lo", "1"), ("eth0", "2"), ("veth9a1b2c3", "17")] {
+            std::fs::create_dir(dir.path().join(name)).unwrap();
+            std::fs::write(dir.path().join(name).join("ifindex"), format!("{index}\n")).unwrap();
+        }
+        assert_eq!(
+            interface_by_index(dir.path(), "17").as_deref(),
+            Some("veth9a1b2c3")
+        );
+        assert_eq!(interface_by_index(dir.path(), "4"), None);
+    }
+
+    #[test]
+    fn test_host_veths() {
+        let dir = tempfile::tempdir().unwrap();
+        let interface = |net: &str, name: &str, index: &str, link: &str| {
+            let path = dir.path().join(net).join(name);
+            std::fs::create_dir_all(&path).unwrap();
+            std::fs::write(path.join("ifindex"), format!("{index}\n")).unwrap();
+            std::fs::write(path.join("iflink"), format!("{link}\n")).unwrap();
+        };
+        interface("host", "lo", "1", "1");
+        interface("host", "veth9a1b2c3", "17", "2");
+        interface("host", "veth4d5e6f7", "19", "3");
+        interface("container", "lo", "1", "1");
+        interface("container", "eth0", "2", "17");
+        interface("container", "eth1", "3", "19");
+        interface("container", "tunl0", "4", "4");
+
+        let container = dir.path().join("container");
+        let host = dir.path().join("host");
+        assert_eq!(
+            host_veths(&container, &host),
+            ["veth4d5e6f7", "veth9a1b2c3"]
+        );
+        assert!(host_veths(&dir.path().join("gone"), &host).is_empty());
+    }
+}
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::net::Ipv4Addr;
 use std::path::Path;
 
 use crate::error::WingsError;
@@ -10,6 +11,8 @@ pub struct Config {
     pub docker: DockerConfig,
     pub storage: StorageConfig,
     pub logging: LoggingConfig,
+    #[serde(default)]
+    pub sftp: SftpConfig,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -34,12 +37,214 @@ pub struct ApiConfig {
 pub struct DockerConfig {
     #[serde(default = "default_socket")]
     pub socket: String,
+    /// User server containers run as. Files written through the API are
+    /// chowned to it. When unset, containers run as 988:988, or as the
+    /// image's default user if `hardening.non_root` is off.
+    #[serde(default)]
+    pub user: Option<ContainerUser>,
+    /// Credentials for private registries, picked by image host on pull
+    #[serde(default)]
+    pub registries: Vec<RegistryConfig>,
+    /// When to pull server and install images. Servers can override it.
+    #[serde(default)]
+    pub pull_policy: PullPolicy,
+    /// Restrictions on server containers; relax them for eggs that need more
+    #[serde(default)]
+    pub hardening: HardeningConfig,
+    #[serde(default)]
+    pub network: NetworkConfig,
+}
+
+/// Bridge network server containers join. Docker keeps a network's settings
+/// from when it was created, so changes need the network removed first.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct NetworkConfig {
+    #[serde(default = "default_network_name")]
+    pub name: String,
+    /// IPv4 subnet in CIDR form; Docker picks one when unset
+    #[serde(default)]
+    pub subnet: Option<String>,
+    #[serde(default)]
+    pub gateway: Option<String>,
+    #[serde(default)]
+    pub ipv6: bool,
+    /// IPv6 subnet in CIDR form, used when `ipv6` is on
+    #[serde(default)]
+    pub ipv6_subnet: Option<String>,
+    #[serde(default)]
+    pub mtu: Option<u32>,
+    /// Let containers on the network reach each other
+    #[serde(default = "default_true")]
+    pub icc: bool,
+    /// DNS servers for server containers instead of the host's
+    #[serde(default)]
+    pub dns: Vec<String>,
+    /// IPv4 range isolated networks take their subnets from. Docker's own
+    /// pools hand out a /16 or /20 per network and run out after about 30.
+    #[serde(default = "default_isolated_pool")]
+    pub isolated_pool: String,
+    /// Prefix length of each isolated network's subnet
+    #[serde(default = "default_isolated_prefix")]
+    pub isolated_prefix: u8,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            name: default_network_name(),
+            subnet: None,
+            gateway: None,
+            ipv6: false,
+            ipv6_subnet: None,
+            mtu: None,
+            icc: true,
+            dns: Vec::new(),
+            isolated_pool: default_isolated_pool(),
+            isolated_prefix: default_isolated_prefix(),
+        }
+    }
+}
+
+/// Address and prefix length of an IPv4 CIDR like `10.0.0.0/8`.
+pub fn ipv4_cidr(cidr: &str) -> Option<(Ipv4Addr, u8)> {
+    let (addr, prefix) = cidr.split_once('/')?;
+    let prefix = prefix.parse().ok().filter(|p| *p <= 32)?;
+    Some((addr.parse().ok()?, prefix))
+}
+
+impl NetworkConfig {
+    /// Base address and prefix length of `isolated_pool`.
+    pub fn isolated_pool(&self) -> Option<(Ipv4Addr, u8)> {
+        ipv4_cidr(&self.isolated_pool)
+    }
+
+    fn check(&self) -> Result<(), WingsError> {
+        if self.ipv6 && self.ipv6_subnet.is_none() {
+            return Err(WingsError::Config(
+                "docker.network.ipv6 needs an ipv6_subnet".to_string(),
+            ));
+        }
+        let Some((_, pool_prefix)) = self.isolated_pool() else {
+            return Err(WingsError::Config(format!(
+                "docker.network.isolated_pool {} is not an IPv4 CIDR",
+                self.isolated_pool
+            )));
+        };
+        if !(pool_prefix..=30).contains(&self.isolated_prefix) {
+            return Err(WingsError::Config(format!(
+                "docker.network.isolated_prefix must be between {pool_prefix} and 30"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// User server containers run as when `docker.user` is unset.
+pub const DEFAULT_CONTAINER_USER: ContainerUser = ContainerUser { uid: 988, gid: 988 };
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct HardeningConfig {
+    /// Drop every capability but `capabilities`
+    #[serde(default = "default_true")]
+    pub drop_capabilities: bool,
+    /// Capabilities to add, e.g. `NET_BIND_SERVICE`; with
+    /// `drop_capabilities` the only ones kept
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    /// Keep processes from gaining privileges through setuid binaries
+    #[serde(default = "default_true")]
+    pub no_new_privileges: bool,
+    /// Mount the image read-only; servers write to `/server` and `/tmp`
+    #[serde(default = "default_true")]
+    pub read_only_rootfs: bool,
+    /// Size of the tmpfs mounted at `/tmp` on a read-only root; 0 for none
+    #[serde(default = "default_tmpfs_size_mb")]
+    pub tmpfs_size_mb: u64,
+    /// Path to a seccomp profile JSON file to use instead of Docker's default
+    #[serde(default)]
+    pub seccomp_profile: Option<String>,
+    /// Run as 988:988 when `docker.user` is unset. Existing server files
+    /// must be owned by that user.
+    #[serde(default = "default_true")]
+    pub non_root: bool,
+}
+
+impl Default for HardeningConfig {
+    fn default() -> Self {
+        Self {
+            drop_capabilities: true,
+            capabilities: Vec::new(),
+            no_new_privileges: true,
+            read_only_rootfs: true,
+            tmpfs_size_mb: default_tmpfs_size_mb(),
+            seccomp_profile: None,
+            non_root: true,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PullPolicy {
+    /// Pull on every container creation, falling back to the local image
+    /// if the registry can't be reached
+    #[default]
+    Always,
+    /// Only pull images that aren't present
+    IfNotPresent,
+    /// Never pull; the image must already be present
+    Never,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RegistryConfig {
+    /// Registry host as it appears in image names, e.g. `ghcr.io`
+    pub host: String,
+    pub username: String,
+    /// Password or access token
+    #[serde(alias = "token")]
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct ContainerUser {
+    pub uid: u32,
+    pub gid: u32,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct StorageConfig {
     #[serde(default = "default_data_dir")]
     pub data_dir: String,
+    /// Where local server backups are kept
+    #[serde(default = "default_backup_dir")]
+    pub backup_dir: String,
+    /// S3-compatible bucket backups can be stored in instead
+    #[serde(default)]
+    pub s3: Option<S3Config>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct S3Config {
+    /// e.g. `https://s3.eu-central-1.amazonaws.com` or a MinIO URL
+    pub endpoint: String,
+    #[serde(default = "default_s3_region")]
+    pub region: String,
+    pub bucket: String,
+    pub access_key: String,
+    pub secret_key: String,
+    /// Key prefix for all objects
+    #[serde(default)]
+    pub prefix: String,
+    /// Address the bucket as `endpoint/bucket` rather than `bucket.endpoint`.
+    /// Most self-hosted S3 implementations need this.
+    #[serde(default = "default_true")]
+    pub path_style: bool,
+    /// Size of each multipart upload part; S3 requires at least 5 MiB
+    #[serde(default = "default_s3_part_size_mb")]
+    pub part_size_mb: u64,
+    #[serde(default = "default_s3_max_retries")]
+    pub max_retries: u32,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -49,6 +254,36 @@ pub struct LoggingConfig {
     pub file: Option<String>,
 }
 
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SftpConfig {
+    /// Off unless set, so a node doesn't expose a login port by surprise
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_host")]
+    pub host: String,
+    #[serde(default = "default_sftp_port")]
+    pub port: u16,
+    /// Ed25519 host key; generated on first start if missing
+    #[serde(default = "default_sftp_host_key")]
+    pub host_key: String,
+    /// Endpoint checking SFTP credentials. Defaults to the Panel's; point
+    /// it at a stub to test logins without a Panel.
+    #[serde(default)]
+    pub auth_url: Option<String>,
+}
+
+impl Default for SftpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: default_host(),
+            port: default_sftp_port(),
+            host_key: default_sftp_host_key(),
+            auth_url: None,
+        }
+    }
+}
+
 fn default_host() -> String {
     "0.0.0.0".to_string()
 }
@@ -61,6 +296,39 @@ fn default_socket() -> String {
 fn default_data_dir() -> String {
     "/var/lib/nexus-wings/data".to_string()
 }
+fn default_backup_dir() -> String {
+    "/var/lib/nexus-wings/backups".to_string()
+}
+fn default_s3_region() -> String {
+    "us-east-1".to_string()
+}
+fn default_true() -> bool {
+    true
+}
+fn default_network_name() -> String {
+    "nexus0".to_string()
+}
+fn default_isolated_pool() -> String {
+    "10.210.0.0/16".to_string()
+}
+fn default_isolated_prefix() -> u8 {
+    28
+}
+fn default_tmpfs_size_mb() -> u64 {
+    100
+}
+fn default_s3_part_size_mb() -> u64 {
+    16
+}
+fn default_s3_max_retries() -> u32 {
+    3
+}
+fn default_sftp_port() -> u16 {
+    2022
+}
+fn default_sftp_host_key() -> String {
+    "/var/lib/nexus-wings/sftp_host_key".to_string()
+}
 fn default_log_level() -> String {
     "info".to_string()
 }
@@ -71,6 +339,7 @@ impl Config {
             .map_err(|e| WingsError::Config(format!("Failed to read config file: {e}")))?;
         let config: Config = toml::from_str(&content)
             .map_err(|e| WingsError::Config(format!("Failed to parse config: {e}")))?;
+        config.docker.network.check()?;
         Ok(config)
     }
 }
@@ -85,6 +354,7 @@ mod tests {
         assert_eq!(default_port(), 8080);
         assert_eq!(default_socket(), "/var/run/docker.sock");
         assert_eq!(default_data_dir(), "/var/lib/nexus-wings/data");
+        assert_eq!(default_backup_dir(), "/var/lib/nexus-wings/backups");
         assert_eq!(default_log_level(), "info");
     }
 
@@ -102,12 +372,51 @@ port = 9090
 
 [docker]
 socket = "/var/run/docker.sock"
+pull_policy = "if-not-present"
+
+[docker.user]
+uid = 988
+gid = 988
+
+[docker.hardening]
+drop_capabilities = false
+capabilities = ["NET_BIND_SERVICE"]
+read_only_rootfs = false
+seccomp_profile = "/etc/nexus-wings/seccomp.json"
+
+[docker.network]
+subnet = "172.28.0.0/16"
+mtu = 1450
+icc = false
+dns = ["1.1.1.1"]
+
+[[docker.registries]]
+host = "ghcr.io"
+username = "nexus-bot"
+token = "ghp_example"
+
+[[docker.registries]]
+host = "harbor.example.com:8443"
+username = "robot$nexus"
+password = "secret"
 
 [storage]
 data_dir = "/data"
+backup_dir = "/backups"
+
+[storage.s3]
+endpoint = "http://minio:9000"
+bucket = "nexus"
+access_key = "minio"
+secret_key = "minio123"
 
 [logging]
 level = "debug"
+
+[sftp]
+enabled = true
+port = 2222
+auth_url = "http://localhost:4000/sftp/auth"
 "#;
         let config: Config = toml::from_str(toml_str).unwrap();
 
@@ -117,8 +426,36 @@ level = "debug"
         assert_eq!(config.api.host, "127.0.0.1");
         assert_eq!(config.api.port, 9090);
         assert_eq!(config.docker.socket, "/var/run/docker.sock");
+        assert_eq!(config.docker.user, Some(ContainerUser { uid: 988, gid: 988 }));
+        assert_eq!(config.docker.pull_policy, PullPolicy::IfNotPresent);
+        let hardening = &config.docker.hardening;
+        assert!(!hardening.drop_capabilities);
+        assert_eq!(hardening.capabilities, ["NET_BIND_SERVICE"]);
+        assert!(!hardening.read_only_rootfs);
+        assert!(hardening.no_new_privileges);
+        assert_eq!(hardening.tmpfs_size_mb, 100);
+        assert_eq!(hardening.seccomp_profile.as_deref(), Some("/etc/nexus-wings/seccomp.json"));
+        let network = &config.docker.network;
+        assert_eq!(network.name, "nexus0");
+        assert_eq!(network.subnet.as_deref(), Some("172.28.0.0/16"));
+        assert_eq!(network.mtu, Some(1450));
+        assert!(!network.icc && !network.ipv6);
+        assert_eq!(network.dns, ["1.1.1.1"]);
+        assert_eq!(config.docker.registries.len(), 2);
+        assert_eq!(config.docker.registries[0].host, "ghcr.io");
+        assert_eq!(config.docker.registries[0].password, "ghp_example");
+        assert_eq!(config.docker.registries[1].username, "robot$nexus");
         assert_eq!(config.storage.data_dir, "/data");
+        assert_eq!(config.storage.backup_dir, "/backups");
+        let s3 = config.storage.s3.unwrap();
+        assert_eq!(s3.bucket, "nexus");
+        assert_eq!(s3.region, "us-east-1");
+        assert!(s3.path_style);
+        assert_eq!(s3.part_size_mb, 16);
         assert_eq!(config.logging.level, "debug");
+        assert!(config.sftp.enabled);
+        assert_eq!(config.sftp.port, 2222);
+        assert_eq!(config.sftp.auth_url.as_deref(), Some("http://localhost:4000/sftp/auth"));
     }
 
     #[test]
@@ -141,7 +478,57 @@ token = "token123"
         assert_eq!(config.api.host, "0.0.0.0");
         assert_eq!(config.api.port, 8080);
         assert_eq!(config.docker.socket, "/var/run/docker.sock");
+        assert_eq!(config.docker.user, None);
+        assert!(config.docker.registries.is_empty());
+        assert_eq!(config.docker.pull_policy, PullPolicy::Always);
+        let hardening = &config.docker.hardening;
+        assert!(hardening.capabilities.is_empty());
+        assert!(hardening.no_new_privileges && hardening.drop_capabilities);
+        assert!(hardening.read_only_rootfs && hardening.non_root);
+        assert!(hardening.seccomp_profile.is_none());
+        assert_eq!(config.docker.network.name, "nexus0");
+        assert!(config.docker.network.icc);
+        assert_eq!(
+            config.docker.network.isolated_pool(),
+            Some((Ipv4Addr::new(10, 210, 0, 0), 16))
+        );
+        assert_eq!(config.docker.network.isolated_prefix, 28);
         assert_eq!(config.storage.data_dir, "/var/lib/nexus-wings/data");
+        assert!(config.storage.s3.is_none());
         assert_eq!(config.logging.level, "info");
+        assert!(!config.sftp.enabled);
+        assert_eq!(config.sftp.port, 2022);
+        assert_eq!(config.sftp.host_key, "/var/lib/nexus-wings/sftp_host_key");
+        assert!(config.sftp.auth_url.is_none());
+    }
+
+    #[test]
+    fn test_network_check() {
+        assert!(NetworkConfig::default().check().is_ok());
+
+        let network = NetworkConfig {
+            ipv6: true,
+            ..Default::default()
+        };
+        assert!(matches!(network.check(), Err(WingsError::Config(_))));
+        let network = NetworkConfig {
+            ipv6_subnet: Some("fd00:28::/64".to_string()),
+            ..network
+        };
+        assert!(network.check().is_ok());
+
+        for (pool, prefix) in [
+            ("10.210.0.0", 28),
+            ("10.210.0.0/33", 28),
+            ("10.210.0.0/16", 12),
+            ("10.210.0.0/16", 31),
+        ] {
+            let network = NetworkConfig {
+                isolated_pool: pool.to_string(),
+                isolated_prefix: prefix,
+                ..Default::default()
+            };
+            assert!(network.check().is_err(), "{pool} /{prefix}");
+        }
     }
 }
@@ -0,0 +1,141 @@
+//! Keeping server containers in line with their stored config.
+//!
+//! Config synced from the Panel is diffed against the existing container.
+//! Limits Docker can change live are applied in place; anything else needs
+//! the container recreated, which happens right away for stopped servers
+//! and on the next start for running ones. Server files are kept either way.
+
+use crate::bandwidth;
+use crate::docker::{self, ServerConfig};
+use crate::error::WingsError;
+use crate::images;
+use crate::ports;
+use crate::state::AppState;
+
+/// Grace period for a server to stop before a rebuild on restart.
+const STOP_TIMEOUT_SECS: u64 = 30;
+
+/// Directory on the node holding a server's files.
+pub fn volume_path(state: &AppState, uuid: &str) -> String {
+    std::path::Path::new(&state.config.storage.data_dir)
+        .join(uuid)
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Store a config synced from the Panel and bring the container in line
+/// with it. Returns whether a rebuild is waiting for the next start.
+pub async fn sync_config(state: &AppState, mut config: ServerConfig) -> Result<bool, WingsError> {
+    // Server files live where this node keeps them, whatever the Panel says
+    config.volume_path = volume_path(state, &config.uuid);
+    let mut bandwidth_changed = config.egress_limit > 0 || config.ingress_limit > 0;
+    if let Some(stored) = state.get_server_config(&config.uuid).await {
+        bandwidth_changed = (stored.egress_limit, stored.ingress_limit)
+            != (config.egress_limit, config.ingress_limit);
+        // The container still runs what it was created from
+        if stored.docker_image == config.docker_image {
+            config.image_digest = stored.image_digest;
+        }
+        config.rebuild_pending = stored.rebuild_pending;
+    }
+    ports::claim(state, &config).await?;
+
+    let Some(container) = state.docker.inspect_server(&config.uuid).await? else {
+        // Created from the stored config on the next start
+        return Ok(false);
+    };
+    let image = match &container.image {
+        Some(id) => state
+            .docker
+            .local_image(id)
+            .await?
+            .and_then(|image| image.config)
+            .unwrap_or_default(),
+        None => Default::default(),
+    };
+    let running = container.state.as_ref().and_then(|s| s.running).unwrap_or(false);
+    if running && bandwidth_changed {
+        // Shaping is best effort; the rest of the config still applies
+        if let Err(e) = bandwidth::apply(state, &config).await {
+            tracing::warn!(uuid = %config.uuid, error = %e, "Failed to apply bandwidth limits");
+        }
+    }
+
+    let network = state.docker.server_network(&config);
+    let hardening = state.docker.hardening().await?;
+    let cgroup_v1 = state.docker.cgroup_v1().await?;
+    let changes =
+        docker::structural_changes(&config, &container, &image, &network, &hardening, cgroup_v1);
+    if changes.is_empty() {
+        if config.rebuild_pending {
+            // Synced back to what the container already runs
+            config.rebuild_pending = false;
+            state.store_server_config(&config).await;
+        }
+        if docker::limits_changed(&config, &container) {
+            state.docker.update_limits(&config).await?;
+        }
+        return Ok(false);
+    }
+
+    tracing::info!(uuid = %config.uuid, ?changes, "Server config needs a container rebuild");
+    if running {
+        if !config.rebuild_pending {
+            config.rebuild_pending = true;
+            state.store_server_config(&config).await;
+        }
+        state.docker.update_limits(&config).await?;
+        Ok(true)
+    } else {
+        rebuild(state, &mut config, true).await?;
+        Ok(false)
+    }
+}
+
+/// (Re)create a server's container from its config, keeping its files.
+async fn rebuild(
+    state: &AppState,
+    config: &mut ServerConfig,
+    replace: bool,
+) -> Result<(), WingsError> {
+    // Fetch the image first so a failed pull leaves the old container
+    images::ensure_server_image(state, config).await?;
+    if replace {
+        state.docker.delete_server(&config.uuid, false).await?;
+    }
+    state.docker.create_container(config).await?;
+    if config.rebuild_pending {
+        config.rebuild_pending = false;
+        state.store_server_config(config).await;
+    }
+    Ok(())
+}
+
+/// Get a server's container ready to start: create it if it is missing and
+/// rebuild it if its config changed while it was running.
+pub async fn prepare_start(state: &AppState, uuid: &str) -> Result<(), WingsError> {
+    let exists = state.docker.inspect_server(uuid).await?.is_some();
+    match state.get_server_config(uuid).await {
+        Some(mut config) if !exists || config.rebuild_pending => {
+            tracing::info!(uuid = %uuid, exists, "Creating container from stored config");
+            rebuild(state, &mut config, exists).await
+        }
+        None if !exists => Err(WingsError::ServerNotFound(uuid.to_string())),
+        _ => Ok(()),
+    }
+}
+
+/// Restart a server, applying a pending rebuild in between.
+pub async fn restart(state: &AppState, uuid: &str) -> Result<(), WingsError> {
+    let pending = state
+        .get_server_config(uuid)
+        .await
+        .is_some_and(|config| config.rebuild_pending);
+    if !pending {
+        return state.docker.restart_server(uuid).await;
+    }
+    state.docker.stop_server(uuid, STOP_TIMEOUT_SECS).await?;
+    prepare_start(state, uuid).await?;
+    state.docker.start_server(uuid).await
+}
+
@@ -1,18 +1,27 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
 
 use bollard::container::{
-    Config as ContainerConfig, CreateContainerOptions, ListContainersOptions, LogOutput,
-    LogsOptions, RemoveContainerOptions, StartContainerOptions, StopContainerOptions,
-    StatsOptions,
+    CPUStats, Config as ContainerConfig, CreateContainerOptions, ListContainersOptions,
+    LogOutput, LogsOptions, RemoveContainerOptions, StartContainerOptions, Stats,
+    StopContainerOptions, StatsOptions, UpdateContainerOptions,
 };
 use bollard::exec::{CreateExecOptions, StartExecResults};
 use bollard::image::CreateImageOptions;
-use bollard::models::{HostConfig, PortBinding, PortMap};
-use bollard::network::CreateNetworkOptions;
+use bollard::models::{
+    ContainerInspectResponse, HostConfig, ImageConfig, ImageInspect, Ipam, IpamConfig, Port,
+    PortBinding, PortMap, SystemInfoCgroupVersionEnum,
+};
+use bollard::network::{ConnectNetworkOptions, CreateNetworkOptions};
 use bollard::Docker;
 use futures_util::stream::{Stream, StreamExt};
 use serde::{Deserialize, Serialize};
 
+use crate::config::{
+    ipv4_cidr, ContainerUser, DockerConfig, HardeningConfig, NetworkConfig, PullPolicy,
+    DEFAULT_CONTAINER_USER,
+};
 use crate::error::WingsError;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -29,10 +38,49 @@ pub struct ServerConfig {
     pub cpu_limit: u64,
     #[serde(alias = "diskLimit")]
     pub disk_limit: u64,
+    /// Swap on top of the memory limit in MiB; 0 disables swap and -1
+    /// allows unlimited swap
+    #[serde(default, alias = "swapLimit")]
+    pub swap_limit: i64,
+    /// Memory added to the hard limit as headroom, in MiB
+    #[serde(default, alias = "memoryOverhead")]
+    pub memory_overhead: u64,
+    /// Relative block IO weight from 10 to 1000; 0 keeps Docker's default
+    #[serde(default, alias = "ioWeight")]
+    pub io_weight: u16,
+    /// Maximum number of processes; 0 is unlimited
+    #[serde(default, alias = "pidsLimit")]
+    pub pids_limit: i64,
+    /// CPUs the server may run on, e.g. `0-3,6`; empty allows all
+    #[serde(default)]
+    pub cpuset: String,
+    /// Keep the kernel from killing the server when it runs out of memory
+    #[serde(default, alias = "oomKillDisable")]
+    pub oom_kill_disable: bool,
+    /// Put the server on a network of its own so it can't reach other
+    /// servers on the node
+    #[serde(default, alias = "networkIsolated")]
+    pub network_isolated: bool,
+    /// Outbound bandwidth in Mbit/s; 0 is unlimited
+    #[serde(default, alias = "egressLimit")]
+    pub egress_limit: u64,
+    /// Inbound bandwidth in Mbit/s; 0 is unlimited
+    #[serde(default, alias = "ingressLimit")]
+    pub ingress_limit: u64,
     #[serde(alias = "portMappings")]
     pub port_mappings: Vec<PortMapping>,
     #[serde(alias = "volumePath")]
     pub volume_path: String,
+    /// Overrides the node's pull policy
+    #[serde(default, alias = "pullPolicy")]
+    pub pull_policy: Option<PullPolicy>,
+    /// Digest the image resolved to when the container was last created
+    #[serde(default, alias = "imageDigest")]
+    pub image_digest: Option<String>,
+    /// Changed while running in ways that need the container recreated,
+    /// which happens on the next start
+    #[serde(default)]
+    pub rebuild_pending: bool,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -41,85 +89,806 @@ pub struct PortMapping {
     pub host_port: u16,
     #[serde(alias = "containerPort")]
     pub container_port: u16,
+    #[serde(default)]
+    pub protocol: PortProtocol,
+    /// Host address to bind; all addresses when unset
+    #[serde(default, alias = "bindIp")]
+    pub bind_ip: Option<String>,
+    /// The server's main allocation, exposed as SERVER_IP and SERVER_PORT.
+    /// The first mapping is used when none is marked.
+    #[serde(default)]
+    pub primary: bool,
+}
+
+impl PortMapping {
+    pub fn host_ip(&self) -> &str {
+        self.bind_ip
+            .as_deref()
+            .filter(|ip| !ip.is_empty())
+            .unwrap_or("0.0.0.0")
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PortProtocol {
+    #[default]
+    Tcp,
+    Udp,
+    Both,
+}
+
+impl PortProtocol {
+    /// Docker's protocol suffixes for the port, e.g. `25565/tcp`.
+    pub fn docker_protocols(self) -> &'static [&'static str] {
+        match self {
+            Self::Tcp => &["tcp"],
+            Self::Udp => &["udp"],
+            Self::Both => &["tcp", "udp"],
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Clone)]
 pub struct ResourceStats {
+    /// CPU use where one busy core is 100
     pub cpu_percent: f64,
+    /// CPU use as a percent of the server's CPU limit, or of the whole
+    /// node for servers without one
+    pub cpu_limit_percent: f64,
     pub memory_bytes: u64,
     pub memory_limit: u64,
     pub network_rx_bytes: u64,
     pub network_tx_bytes: u64,
+    /// Throughput since the previous sample of the server
+    pub network_rx_bytes_per_sec: u64,
+    pub network_tx_bytes_per_sec: u64,
     pub disk_bytes: u64,
     pub timestamp: String,
 }
 
+/// Network counters of a server at one point in time.
+#[derive(Debug, Clone, Copy)]
+struct NetworkSample {
+    rx_bytes: u64,
+    tx_bytes: u64,
+    at: Instant,
+    /// Rates worked out when this sample was taken
+    rates: (u64, u64),
+}
+
+/// Samples closer together than this reuse the previous rates, which would
+/// otherwise swing wildly.
+const MIN_SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+impl NetworkSample {
+    /// The sample after this one, with rates over the time in between.
+    /// Counters start over when a container restarts.
+    fn next(&self, rx_bytes: u64, tx_bytes: u64, at: Instant) -> Self {
+        let elapsed = at.saturating_duration_since(self.at);
+        if elapsed < MIN_SAMPLE_INTERVAL {
+            return *self;
+        }
+        let rate = |now: u64, before: u64| {
+            (now.saturating_sub(before) as f64 / elapsed.as_secs_f64()) as u64
+        };
+        Self {
+            rx_bytes,
+            tx_bytes,
+            at,
+            rates: (rate(rx_bytes, self.rx_bytes), rate(tx_bytes, self.tx_bytes)),
+        }
+    }
+}
+
+/// CPU time counters of a container, in nanoseconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct CpuUsage {
+    total: u64,
+    /// CPU time of the whole host
+    system: u64,
+}
+
+impl CpuUsage {
+    /// Counters of a stats payload, if it has any. Docker leaves the system
+    /// counter out of `precpu_stats` for one-shot stats.
+    fn of(stats: &CPUStats) -> Option<Self> {
+        Some(Self {
+            total: stats.cpu_usage.total_usage,
+            system: stats.system_cpu_usage.filter(|system| *system > 0)?,
+        })
+    }
+
+    /// CPU use between this reading and a later one, where one busy core is
+    /// 100. The system counter covers all `online_cpus`.
+    fn percent_until(&self, later: &CpuUsage, online_cpus: u64) -> f64 {
+        let cpu_delta = later.total.saturating_sub(self.total);
+        let system_delta = later.system.saturating_sub(self.system);
+        if system_delta == 0 {
+            return 0.0;
+        }
+        cpu_delta as f64 / system_delta as f64 * online_cpus as f64 * 100.0
+    }
+}
+
+/// CPUs the host has online. Older daemons only list per-CPU usage, which
+/// cgroup v2 doesn't have.
+fn online_cpus(stats: &CPUStats) -> u64 {
+    stats
+        .online_cpus
+        .filter(|cpus| *cpus > 0)
+        .or_else(|| {
+            let percpu = stats.cpu_usage.percpu_usage.as_ref()?;
+            Some(percpu.len() as u64).filter(|cpus| *cpus > 0)
+        })
+        .unwrap_or(1)
+}
+
+/// What is kept of a server's previous stats payload.
+#[derive(Debug, Clone, Copy)]
+struct StatsSample {
+    network: NetworkSample,
+    cpu: Option<CpuUsage>,
+}
+
+/// Resource stats from a stats payload, measured against the server's
+/// previous sample where the payload has no baseline of its own. Returns
+/// the sample to keep for next time.
+fn measure(
+    stats: &Stats,
+    previous: Option<StatsSample>,
+    at: Instant,
+    cpu_limit: u64,
+) -> (ResourceStats, StatsSample) {
+    let (network_rx, network_tx) = stats
+        .networks
+        .as_ref()
+        .map(|nets| {
+            nets.values().fold((0u64, 0u64), |(rx, tx), net| {
+                (rx + net.rx_bytes, tx + net.tx_bytes)
+            })
+        })
+        .unwrap_or((0, 0));
+    let network = match previous {
+        Some(previous) => previous.network.next(network_rx, network_tx, at),
+        None => NetworkSample {
+            rx_bytes: network_rx,
+            tx_bytes: network_tx,
+            at,
+            rates: (0, 0),
+        },
+    };
+
+    let cpus = online_cpus(&stats.cpu_stats);
+    let cpu = CpuUsage::of(&stats.cpu_stats);
+    let baseline = CpuUsage::of(&stats.precpu_stats).or(previous.and_then(|p| p.cpu));
+    let cpu_percent = match (baseline, cpu) {
+        (Some(before), Some(now)) => before.percent_until(&now, cpus),
+        _ => 0.0,
+    };
+    let limit = if cpu_limit > 0 {
+        cpu_limit as f64
+    } else {
+        cpus as f64 * 100.0
+    };
+
+    let resource_stats = ResourceStats {
+        cpu_percent,
+        cpu_limit_percent: cpu_percent / limit * 100.0,
+        memory_bytes: stats.memory_stats.usage.unwrap_or(0),
+        memory_limit: stats.memory_stats.limit.unwrap_or(0),
+        network_rx_bytes: network_rx,
+        network_tx_bytes: network_tx,
+        network_rx_bytes_per_sec: network.rates.0,
+        network_tx_bytes_per_sec: network.rates.1,
+        disk_bytes: 0, // Calculated by caller with server data dir
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    };
+    (resource_stats, StatsSample { network, cpu })
+}
+
+/// Host port bindings and exposed ports of a server's container.
+fn port_bindings(config: &ServerConfig) -> (PortMap, HashMap<String, HashMap<(), ()>>) {
+    let mut port_bindings: PortMap = HashMap::new();
+    let mut exposed_ports = HashMap::new();
+    for pm in &config.port_mappings {
+        for protocol in pm.protocol.docker_protocols() {
+            let container_port_key = format!("{}/{protocol}", pm.container_port);
+            exposed_ports.insert(container_port_key.clone(), HashMap::new());
+            // One container port may be published on several addresses
+            port_bindings
+                .entry(container_port_key)
+                .or_insert_with(|| Some(Vec::new()))
+                .get_or_insert_with(Vec::new)
+                .push(PortBinding {
+                    host_ip: Some(pm.host_ip().to_string()),
+                    host_port: Some(pm.host_port.to_string()),
+                });
+        }
+    }
+    (port_bindings, exposed_ports)
+}
+
+/// The allocation a server is reached on.
+pub fn primary_port(config: &ServerConfig) -> Option<&PortMapping> {
+    config
+        .port_mappings
+        .iter()
+        .find(|pm| pm.primary)
+        .or(config.port_mappings.first())
+}
+
+fn startup_cmd(config: &ServerConfig) -> Vec<String> {
+    config
+        .startup_command
+        .split_whitespace()
+        .map(String::from)
+        .collect()
+}
+
+fn server_env(config: &ServerConfig) -> Vec<String> {
+    let mut env: Vec<String> = config
+        .environment
+        .iter()
+        .map(|(k, v)| format!("{k}={v}"))
+        .collect();
+    // Variables from the Panel win over the allocation's
+    if let Some(primary) = primary_port(config) {
+        for (key, value) in [
+            ("SERVER_IP", primary.host_ip().to_string()),
+            ("SERVER_PORT", primary.host_port.to_string()),
+        ] {
+            if !config.environment.contains_key(key) {
+                env.push(format!("{key}={value}"));
+            }
+        }
+    }
+    env
+}
+
+fn server_binds(config: &ServerConfig) -> Vec<String> {
+    vec![format!("{}:/server", config.volume_path)]
+}
+
+const MIB: i64 = 1024 * 1024;
+
+/// Block IO weights Docker accepts; 0 leaves the weight unset.
+const IO_WEIGHTS: std::ops::RangeInclusive<u32> = 10..=1000;
+
+/// Check a requested block IO weight, which Docker rejects outside 10 to
+/// 1000 only once the container is created.
+pub fn io_weight(weight: u32) -> Result<u16, WingsError> {
+    if weight != 0 && !IO_WEIGHTS.contains(&weight) {
+        return Err(WingsError::InvalidRequest(format!(
+            "Invalid IO weight {weight}: must be 0 or between 10 and 1000"
+        )));
+    }
+    Ok(weight as u16)
+}
+
+/// Resource limits Docker can change on a running container, with 0
+/// standing for "unset" throughout so configs and containers compare.
+#[derive(Debug, PartialEq, Eq)]
+struct Limits {
+    memory: i64,
+    memory_reservation: i64,
+    memory_swap: i64,
+    nano_cpus: i64,
+    cpuset_cpus: String,
+    blkio_weight: u16,
+    pids_limit: i64,
+}
+
+impl Limits {
+    fn of(config: &ServerConfig) -> Self {
+        let memory = (config.memory_limit + config.memory_overhead) as i64 * MIB;
+        Self {
+            memory,
+            // The overhead is headroom; the configured amount is what the
+            // kernel reclaims down to under memory pressure
+            memory_reservation: if config.memory_overhead > 0 {
+                config.memory_limit as i64 * MIB
+            } else {
+                0
+            },
+            memory_swap: match config.swap_limit {
+                _ if memory == 0 => 0,
+                swap if swap < 0 => -1,
+                swap => memory + swap * MIB,
+            },
+            nano_cpus: (config.cpu_limit * 10_000_000) as i64,
+            cpuset_cpus: config.cpuset.clone(),
+            blkio_weight: config.io_weight,
+            pids_limit: config.pids_limit.max(0),
+        }
+    }
+
+    fn of_container(host_config: &HostConfig) -> Self {
+        Self {
+            memory: host_config.memory.unwrap_or(0),
+            memory_reservation: host_config.memory_reservation.unwrap_or(0),
+            memory_swap: host_config.memory_swap.unwrap_or(0),
+            nano_cpus: host_config.nano_cpus.unwrap_or(0),
+            cpuset_cpus: host_config.cpuset_cpus.clone().unwrap_or_default(),
+            blkio_weight: host_config.blkio_weight.unwrap_or(0),
+            pids_limit: host_config.pids_limit.unwrap_or(0).max(0),
+        }
+    }
+
+    fn nonzero<T: Default + PartialEq>(value: T) -> Option<T> {
+        (value != T::default()).then_some(value)
+    }
+
+    fn apply(&self, host_config: &mut HostConfig) {
+        host_config.memory = Some(self.memory);
+        host_config.memory_reservation = Self::nonzero(self.memory_reservation);
+        host_config.memory_swap = Self::nonzero(self.memory_swap);
+        host_config.nano_cpus = Some(self.nano_cpus);
+        host_config.cpuset_cpus = Self::nonzero(self.cpuset_cpus.clone());
+        host_config.blkio_weight = Self::nonzero(self.blkio_weight);
+        host_config.pids_limit = Self::nonzero(self.pids_limit);
+    }
+
+    /// Settings of the config that clear limits `container` has. Docker
+    /// treats unset update fields as "keep", so those only go away by
+    /// recreating the container.
+    fn cleared(&self, container: &Self) -> Vec<&'static str> {
+        [
+            ("memory_overhead", self.memory_reservation == 0 && container.memory_reservation != 0),
+            ("swap_limit", self.memory_swap == 0 && container.memory_swap != 0),
+            ("cpuset", self.cpuset_cpus.is_empty() && !container.cpuset_cpus.is_empty()),
+            ("io_weight", self.blkio_weight == 0 && container.blkio_weight != 0),
+        ]
+        .into_iter()
+        .filter_map(|(setting, cleared)| cleared.then_some(setting))
+        .collect()
+    }
+
+    /// Limits can be raised or lowered live; see [`Self::cleared`] for
+    /// dropping them.
+    fn update_options(&self) -> UpdateContainerOptions<String> {
+        UpdateContainerOptions {
+            memory: Some(self.memory),
+            memory_reservation: Self::nonzero(self.memory_reservation),
+            memory_swap: Self::nonzero(self.memory_swap),
+            nano_cpus: Some(self.nano_cpus),
+            cpuset_cpus: Self::nonzero(self.cpuset_cpus.clone()),
+            blkio_weight: Self::nonzero(self.blkio_weight),
+            pids_limit: Some(if self.pids_limit > 0 { self.pids_limit } else { -1 }),
+            ..Default::default()
+        }
+    }
+}
+
+/// Restrict a server container as the node's hardening config asks.
+/// `seccomp` is the contents of the custom seccomp profile, if any.
+fn apply_hardening(
+    host_config: &mut HostConfig,
+    hardening: &HardeningConfig,
+    seccomp: Option<&str>,
+) {
+    if hardening.drop_capabilities {
+        host_config.cap_drop = Some(vec!["ALL".to_string()]);
+    }
+    host_config.cap_add = Some(
+        hardening
+            .capabilities
+            .iter()
+            .map(|cap| cap.trim().to_ascii_uppercase())
+            .map(|cap| cap.strip_prefix("CAP_").map(str::to_string).unwrap_or(cap))
+            .collect(),
+    );
+    let mut security_opt = Vec::new();
+    if hardening.no_new_privileges {
+        security_opt.push("no-new-privileges:true".to_string());
+    }
+    if let Some(profile) = seccomp {
+        security_opt.push(format!("seccomp={profile}"));
+    }
+    host_config.security_opt = Some(security_opt);
+    host_config.readonly_rootfs = Some(hardening.read_only_rootfs);
+    if hardening.read_only_rootfs && hardening.tmpfs_size_mb > 0 {
+        host_config.tmpfs = Some(HashMap::from([(
+            "/tmp".to_string(),
+            format!("rw,exec,nosuid,nodev,size={}m", hardening.tmpfs_size_mb),
+        )]));
+    }
+}
+
+/// Restrictions hardening puts on a container, normalized the way Docker
+/// may report them so configs and containers compare.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Hardening {
+    user: String,
+    cap_drop: BTreeSet<String>,
+    cap_add: BTreeSet<String>,
+    security_opt: BTreeSet<String>,
+    readonly_rootfs: bool,
+    tmpfs: BTreeMap<String, String>,
+}
+
+impl Hardening {
+    fn of(host_config: &HostConfig, user: Option<&str>) -> Self {
+        // Docker reports capabilities with their CAP_ prefix
+        let caps = |caps: &Option<Vec<String>>| {
+            caps.iter()
+                .flatten()
+                .map(|cap| cap.trim().to_ascii_uppercase())
+                .map(|cap| cap.strip_prefix("CAP_").map(str::to_string).unwrap_or(cap))
+                .collect()
+        };
+        Self {
+            user: user.unwrap_or_default().to_string(),
+            cap_drop: caps(&host_config.cap_drop),
+            cap_add: caps(&host_config.cap_add),
+            security_opt: host_config.security_opt.iter().flatten().cloned().collect(),
+            readonly_rootfs: host_config.readonly_rootfs.unwrap_or(false),
+            tmpfs: host_config
+                .tmpfs
+                .iter()
+                .flatten()
+                .map(|(path, options)| (path.clone(), options.clone()))
+                .collect(),
+        }
+    }
+
+    fn of_container(container: &ContainerInspectResponse) -> Self {
+        let user = container.config.as_ref().and_then(|c| c.user.as_deref());
+        Self::of(&container.host_config.clone().unwrap_or_default(), user)
+    }
+}
+
+/// Mask of the leading `prefix` bits of an IPv4 address.
+fn ipv4_mask(prefix: u8) -> u32 {
+    u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0)
+}
+
+/// First subnet with a `prefix` long mask in `pool` that overlaps none of
+/// the `used` ones.
+fn free_subnet(pool: (Ipv4Addr, u8), prefix: u8, used: &[(Ipv4Addr, u8)]) -> Option<String> {
+    let overlaps = |base: u32, (addr, len): (Ipv4Addr, u8)| {
+        let mask = ipv4_mask(len.min(prefix));
+        base & mask == u32::from(addr) & mask
+    };
+    let start = u32::from(pool.0) & ipv4_mask(pool.1);
+    let size = 1u64 << (32 - prefix);
+    let count = 1u64 << prefix.checked_sub(pool.1)?;
+    (0..count)
+        .map(|i| start + (i * size) as u32)
+        .find(|base| !used.iter().any(|subnet| overlaps(*base, *subnet)))
+        .map(|base| format!("{}/{prefix}", Ipv4Addr::from(base)))
+}
+
+/// Options to create a server network with. Isolated networks hold a single
+/// server, so instead of the shared network's addressing they get a small
+/// `isolated` subnet.
+fn network_options(
+    network: &NetworkConfig,
+    name: &str,
+    isolated: Option<String>,
+) -> CreateNetworkOptions<String> {
+    let shared = isolated.is_none();
+    let mut options = HashMap::new();
+    if let Some(mtu) = network.mtu {
+        options.insert("com.docker.network.driver.mtu".to_string(), mtu.to_string());
+    }
+    let mut ipam = Vec::new();
+    if shared {
+        if !network.icc {
+            options.insert(
+                "com.docker.network.bridge.enable_icc".to_string(),
+                "false".to_string(),
+            );
+        }
+        if network.subnet.is_some() || network.gateway.is_some() {
+            ipam.push(IpamConfig {
+                subnet: network.subnet.clone(),
+                gateway: network.gateway.clone(),
+                ..Default::default()
+            });
+        }
+        if let Some(subnet) = network.ipv6_subnet.as_ref().filter(|_| network.ipv6) {
+            ipam.push(IpamConfig {
+                subnet: Some(subnet.clone()),
+                ..Default::default()
+            });
+        }
+    }
+    if let Some(subnet) = isolated {
+        ipam.push(IpamConfig {
+            subnet: Some(subnet),
+            ..Default::default()
+        });
+    }
+    CreateNetworkOptions {
+        name: name.to_string(),
+        driver: "bridge".to_string(),
+        enable_ipv6: shared && network.ipv6,
+        ipam: Ipam {
+            config: (!ipam.is_empty()).then_some(ipam),
+            ..Default::default()
+        },
+        options,
+        labels: HashMap::from([("nexus.managed".to_string(), "true".to_string())]),
+        ..Default::default()
+    }
+}
+
+/// Settings of a server's config that differ from its container and only
+/// take effect by recreating it. `image` is the config of the container's
+/// image, whose environment Docker merges into the container's and whose
+/// command it runs when the server has none. `network` is the network the
+/// server should be on and `hardening` what the node's hardening config
+/// asks for. `cgroup_v1` is whether the daemon runs on cgroup v1, the only
+/// version that keeps `OomKillDisable`.
+pub fn structural_changes(
+    config: &ServerConfig,
+    container: &ContainerInspectResponse,
+    image: &ImageConfig,
+    network: &str,
+    hardening: &Hardening,
+    cgroup_v1: bool,
+) -> Vec<&'static str> {
+    let container_config = container.config.clone().unwrap_or_default();
+    let host_config = container.host_config.clone().unwrap_or_default();
+    let cleared = Limits::of(config).cleared(&Limits::of_container(&host_config));
+    let mut changes = Vec::new();
+
+    if container_config.image.as_deref() != Some(config.docker_image.as_str()) {
+        changes.push("image");
+    }
+    let cmd = match startup_cmd(config) {
+        cmd if cmd.is_empty() => image.cmd.clone().unwrap_or_default(),
+        cmd => cmd,
+    };
+    if container_config.cmd.unwrap_or_default() != cmd {
+        changes.push("startup_command");
+    }
+    let image_env = image.env.clone().unwrap_or_default();
+    let own_env = |env: Vec<String>| -> std::collections::HashSet<String> {
+        env.into_iter().filter(|e| !image_env.contains(e)).collect()
+    };
+    if own_env(container_config.env.unwrap_or_default()) != own_env(server_env(config)) {
+        changes.push("environment");
+    }
+    if host_config.port_bindings.unwrap_or_default() != port_bindings(config).0 {
+        changes.push("port_mappings");
+    }
+    if host_config.binds.unwrap_or_default() != server_binds(config) {
+        changes.push("volume_path");
+    }
+    // cgroup v2 drops the setting, so the container never reports it
+    if cgroup_v1 && host_config.oom_kill_disable.unwrap_or(false) != config.oom_kill_disable {
+        changes.push("oom_kill_disable");
+    }
+    if host_config.network_mode.as_deref() != Some(network) {
+        changes.push("network");
+    }
+    if Hardening::of_container(container) != *hardening {
+        changes.push("hardening");
+    }
+    changes.extend(cleared);
+    changes
+}
+
+/// Whether a container's live-updatable limits differ from the config.
+pub fn limits_changed(config: &ServerConfig, container: &ContainerInspectResponse) -> bool {
+    let host_config = container.host_config.clone().unwrap_or_default();
+    Limits::of(config) != Limits::of_container(&host_config)
+}
+
+/// Download progress of an image pull, summed over its layers.
+#[derive(Debug, Default)]
+pub struct PullProgress {
+    /// (downloaded, total) bytes per layer id
+    layers: HashMap<String, (u64, u64)>,
+}
+
+impl PullProgress {
+    /// Account for a pull status message. Returns whether the byte counts
+    /// changed.
+    fn update(&mut self, info: &bollard::models::CreateImageInfo) -> bool {
+        let (Some(id), Some(status)) = (&info.id, &info.status) else {
+            return false;
+        };
+        let detail = info.progress_detail.as_ref();
+        let bytes = |value: Option<i64>| value.unwrap_or(0).max(0) as u64;
+        let layer = self.layers.entry(id.clone()).or_default();
+        let before = *layer;
+        match status.as_str() {
+            "Downloading" => {
+                layer.0 = bytes(detail.and_then(|d| d.current));
+                layer.1 = bytes(detail.and_then(|d| d.total)).max(layer.0);
+            }
+            // Extraction reports its own byte counts; the download is done
+            "Download complete" | "Extracting" | "Pull complete" => layer.0 = layer.1,
+            _ => {}
+        }
+        *layer != before
+    }
+
+    pub fn downloaded(&self) -> u64 {
+        self.layers.values().map(|(done, _)| done).sum()
+    }
+
+    pub fn total(&self) -> u64 {
+        self.layers.values().map(|(_, total)| total).sum()
+    }
+}
+
 pub struct DockerManager {
     client: Docker,
+    config: DockerConfig,
+    /// Latest stats sample per server, to measure CPU use and throughput
+    samples: std::sync::Mutex<HashMap<String, StatsSample>>,
+    /// Held while a network is created and given a subnet
+    allocating: tokio::sync::Mutex<()>,
 }
 
 impl DockerManager {
-    pub fn new(socket_path: &str) -> Result<Self, WingsError> {
-        let client = Docker::connect_with_local(socket_path, 120, bollard::API_DEFAULT_VERSION)
+    pub fn new(config: &DockerConfig) -> Result<Self, WingsError> {
+        let client = Docker::connect_with_local(&config.socket, 120, bollard::API_DEFAULT_VERSION)
             .map_err(WingsError::Docker)?;
-        Ok(Self { client })
+        Ok(Self {
+            client,
+            config: config.clone(),
+            samples: Default::default(),
+            allocating: Default::default(),
+        })
     }
 
     pub fn client(&self) -> &Docker {
         &self.client
     }
 
-    fn container_name(uuid: &str) -> String {
+    /// User server containers run as and server files are owned by.
+    pub fn container_user(&self) -> Option<ContainerUser> {
+        let fallback = self.config.hardening.non_root.then_some(DEFAULT_CONTAINER_USER);
+        self.config.user.or(fallback)
+    }
+
+    fn container_user_spec(&self) -> Option<String> {
+        self.container_user().map(|u| format!("{}:{}", u.uid, u.gid))
+    }
+
+    /// Restrictions new server containers get.
+    pub async fn hardening(&self) -> Result<Hardening, WingsError> {
+        let seccomp = self.seccomp_profile().await?;
+        let mut host_config = HostConfig::default();
+        apply_hardening(&mut host_config, &self.config.hardening, seccomp.as_deref());
+        Ok(Hardening::of(&host_config, self.container_user_spec().as_deref()))
+    }
+
+    /// Contents of the configured seccomp profile. Docker takes the profile
+    /// itself rather than a path to it.
+    async fn seccomp_profile(&self) -> Result<Option<String>, WingsError> {
+        let Some(path) = &self.config.hardening.seccomp_profile else {
+            return Ok(None);
+        };
+        let profile = tokio::fs::read_to_string(path).await.map_err(|e| {
+            WingsError::Config(format!("Failed to read seccomp profile {path}: {e}"))
+        })?;
+        serde_json::from_str::<serde_json::Value>(&profile).map_err(|e| {
+            WingsError::Config(format!("Seccomp profile {path} is not valid JSON: {e}"))
+        })?;
+        Ok(Some(profile))
+    }
+
+    fn short_id(uuid: &str) -> String {
         let short = uuid.replace('-', "");
-        let short = &short[..std::cmp::min(8, short.len())];
-        format!("nexus-{short}")
+        short[..std::cmp::min(8, short.len())].to_string()
+    }
+
+    fn container_name(uuid: &str) -> String {
+        format!("nexus-{}", Self::short_id(uuid))
+    }
+
+    fn isolated_network(&self, uuid: &str) -> String {
+        format!("{}-{}", self.config.network.name, Self::short_id(uuid))
     }
 
-    pub async fn create_server(&self, config: &ServerConfig) -> Result<String, WingsError> {
-        // Pull image
+    /// Network a server's container joins.
+    pub fn server_network(&self, config: &ServerConfig) -> String {
+        if config.network_isolated {
+            self.isolated_network(&config.uuid)
+        } else {
+            self.config.network.name.clone()
+        }
+    }
+
+    /// Pull an image, calling `on_progress` as layer downloads advance.
+    pub async fn pull_image(
+        &self,
+        image: &str,
+        mut on_progress: impl FnMut(&PullProgress),
+    ) -> Result<(), WingsError> {
+        let mut progress = PullProgress::default();
         let mut pull_stream = self.client.create_image(
             Some(CreateImageOptions {
-                from_image: config.docker_image.clone(),
+                from_image: image.to_string(),
                 ..Default::default()
             }),
             None,
-            None,
+            crate::registry::credentials(&self.config.registries, image),
         );
         while let Some(result) = pull_stream.next().await {
-            result.map_err(WingsError::Docker)?;
+            let info = result.map_err(WingsError::Docker)?;
+            if let Some(error) = info.error {
+                return Err(WingsError::Io(std::io::Error::other(format!(
+                    "Failed to pull {image}: {error}"
+                ))));
+            }
+            if progress.update(&info) {
+                on_progress(&progress);
+            }
         }
+        Ok(())
+    }
 
-        // Build port bindings
-        let mut port_bindings: PortMap = HashMap::new();
-        let mut exposed_ports: HashMap<String, HashMap<(), ()>> = HashMap::new();
-        for pm in &config.port_mappings {
-            let container_port_key = format!("{}/tcp", pm.container_port);
-            exposed_ports.insert(container_port_key.clone(), HashMap::new());
-            port_bindings.insert(
-                container_port_key,
-                Some(vec![PortBinding {
-                    host_ip: Some("0.0.0.0".to_string()),
-                    host_port: Some(pm.host_port.to_string()),
-                }]),
-            );
+    /// Whether the daemon runs on cgroup v1.
+    pub async fn cgroup_v1(&self) -> Result<bool, WingsError> {
+        let info = self.client.info().await.map_err(WingsError::Docker)?;
+        Ok(info.cgroup_version == Some(SystemInfoCgroupVersionEnum::_1))
+    }
+
+    /// Inspect a local image, or `None` if it isn't present.
+    pub async fn local_image(&self, image: &str) -> Result<Option<ImageInspect>, WingsError> {
+        match self.client.inspect_image(image).await {
+            Ok(inspect) => Ok(Some(inspect)),
+            Err(bollard::errors::Error::DockerResponseServerError {
+                status_code: 404, ..
+            }) => Ok(None),
+            Err(e) => Err(WingsError::Docker(e)),
         }
+    }
 
-        // Build environment variables
-        let env: Vec<String> = config
-            .environment
-            .iter()
-            .map(|(k, v)| format!("{k}={v}"))
-            .collect();
+    /// Inspect a server's container, or `None` if it doesn't exist.
+    pub async fn inspect_server(
+        &self,
+        uuid: &str,
+    ) -> Result<Option<ContainerInspectResponse>, WingsError> {
+        match self
+            .client
+            .inspect_container(&Self::container_name(uuid), None)
+            .await
+        {
+            Ok(inspect) => Ok(Some(inspect)),
+            Err(bollard::errors::Error::DockerResponseServerError {
+                status_code: 404, ..
+            }) => Ok(None),
+            Err(e) => Err(WingsError::Docker(e)),
+        }
+    }
+
+    /// Apply a server's resource limits to its running container.
+    pub async fn update_limits(&self, config: &ServerConfig) -> Result<(), WingsError> {
+        self.client
+            .update_container(
+                &Self::container_name(&config.uuid),
+                Limits::of(config).update_options(),
+            )
+            .await
+            .map_err(WingsError::Docker)
+    }
 
-        let host_config = HostConfig {
-            memory: Some((config.memory_limit * 1024 * 1024) as i64),
-            nano_cpus: Some((config.cpu_limit * 10_000_000) as i64),
+    /// Create the container for a server whose image is already present.
+    pub async fn create_container(&self, config: &ServerConfig) -> Result<String, WingsError> {
+        let (port_bindings, exposed_ports) = port_bindings(config);
+        let mut host_config = HostConfig {
             port_bindings: Some(port_bindings),
-            binds: Some(vec![format!("{}:/server", config.volume_path)]),
+            binds: Some(server_binds(config)),
+            oom_kill_disable: Some(config.oom_kill_disable),
             ..Default::default()
         };
-
-        let cmd: Vec<&str> = config.startup_command.split_whitespace().collect();
+        Limits::of(config).apply(&mut host_config);
+        let network = self.server_network(config);
+        if config.network_isolated {
+            self.create_network(&network, false).await?;
+        }
+        host_config.network_mode = Some(network);
+        host_config.dns = Some(self.config.network.dns.clone()).filter(|dns| !dns.is_empty());
+        let seccomp = self.seccomp_profile().await?;
+        apply_hardening(&mut host_config, &self.config.hardening, seccomp.as_deref());
 
         let mut labels = HashMap::new();
         labels.insert("nexus.managed".to_string(), "true".to_string());
@@ -127,8 +896,8 @@ impl DockerManager {
 
         let container_config = ContainerConfig {
             image: Some(config.docker_image.clone()),
-            cmd: Some(cmd.into_iter().map(String::from).collect()),
-            env: Some(env),
+            cmd: Some(startup_cmd(config)),
+            env: Some(server_env(config)),
             exposed_ports: Some(exposed_ports),
             host_config: Some(host_config),
             open_stdin: Some(true),
@@ -138,6 +907,7 @@ impl DockerManager {
             tty: Some(true),
             working_dir: Some("/server".to_string()),
             labels: Some(labels),
+            user: self.container_user_spec(),
             ..Default::default()
         };
 
@@ -220,10 +990,45 @@ impl DockerManager {
             )
             .await
             .map_err(WingsError::Docker)?;
+
+        self.samples
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(uuid);
+
+        // Only ever used by this server's container
+        match self.client.remove_network(&self.isolated_network(uuid)).await {
+            Ok(()) | Err(bollard::errors::Error::DockerResponseServerError {
+                status_code: 404, ..
+            }) => {}
+            Err(e) => tracing::warn!(uuid, "Failed to remove isolated network: {e}"),
+        }
         Ok(())
     }
 
-    pub async fn get_stats(&self, uuid: &str) -> Result<ResourceStats, WingsError> {
+    /// Resource stats of a server from a stats payload, keeping it as the
+    /// server's latest sample. `cpu_limit` is its CPU limit, 100 per core.
+    pub fn resource_stats(&self, uuid: &str, stats: &Stats, cpu_limit: u64) -> ResourceStats {
+        let mut samples = self.samples.lock().unwrap_or_else(|e| e.into_inner());
+        let previous = samples.get(uuid).copied();
+        let (resource_stats, sample) = measure(stats, previous, Instant::now(), cpu_limit);
+        samples.insert(uuid.to_string(), sample);
+        resource_stats
+    }
+
+    /// PID of a server's container process, if it is running.
+    pub async fn container_pid(&self, uuid: &str) -> Result<Option<i64>, WingsError> {
+        Ok(self
+            .inspect_server(uuid)
+            .await?
+            .and_then(|c| c.state)
+            .and_then(|s| s.pid)
+            .filter(|pid| *pid > 0))
+    }
+
+    /// Current resource use of a server. `cpu_limit` is its CPU limit, 100
+    /// per core.
+    pub async fn get_stats(&self, uuid: &str, cpu_limit: u64) -> Result<ResourceStats, WingsError> {
         let name = Self::container_name(uuid);
         let mut stream = self.client.stats(
             &name,
@@ -238,71 +1043,22 @@ impl DockerManager {
             .await
             .ok_or_else(|| WingsError::ServerNotFound(uuid.to_string()))?
             .map_err(WingsError::Docker)?;
-
-        let cpu_percent = calculate_cpu_percent(&stats);
-        let memory_bytes = stats.memory_stats.usage.unwrap_or(0);
-        let memory_limit = stats.memory_stats.limit.unwrap_or(0);
-
-        let (network_rx, network_tx) = stats
-            .networks
-            .as_ref()
-            .map(|nets| {
-                nets.values().fold((0u64, 0u64), |(rx, tx), net| {
-                    (rx + net.rx_bytes, tx + net.tx_bytes)
-                })
-            })
-            .unwrap_or((0, 0));
-
-        Ok(ResourceStats {
-            cpu_percent,
-            memory_bytes,
-            memory_limit,
-            network_rx_bytes: network_rx,
-            network_tx_bytes: network_tx,
-            disk_bytes: 0, // Calculated by caller with server data dir
-            timestamp: chrono::Utc::now().to_rfc3339(),
-        })
+        Ok(self.resource_stats(uuid, &stats, cpu_limit))
     }
 
-    pub fn stream_stats(
-        &self,
-        uuid: &str,
-    ) -> impl Stream<Item = Result<ResourceStats, WingsError>> + '_ {
+    /// Stats payloads of a server's container as Docker samples them, to
+    /// turn into resource stats with [`Self::resource_stats`].
+    pub fn stream_stats(&self, uuid: &str) -> impl Stream<Item = Result<Stats, WingsError>> {
         let name = Self::container_name(uuid);
-        let stream = self.client.stats(
-            &name,
-            Some(StatsOptions {
-                stream: true,
-                one_shot: false,
-            }),
-        );
-
-        stream.map(|result| {
-            let stats = result.map_err(WingsError::Docker)?;
-            let cpu_percent = calculate_cpu_percent(&stats);
-            let memory_bytes = stats.memory_stats.usage.unwrap_or(0);
-            let memory_limit = stats.memory_stats.limit.unwrap_or(0);
-
-            let (network_rx, network_tx) = stats
-                .networks
-                .as_ref()
-                .map(|nets| {
-                    nets.values().fold((0u64, 0u64), |(rx, tx), net| {
-                        (rx + net.rx_bytes, tx + net.tx_bytes)
-                    })
-                })
-                .unwrap_or((0, 0));
-
-            Ok(ResourceStats {
-                cpu_percent,
-                memory_bytes,
-                memory_limit,
-                network_rx_bytes: network_rx,
-                network_tx_bytes: network_tx,
-                disk_bytes: 0,
-                timestamp: chrono::Utc::now().to_rfc3339(),
-            })
-        })
+        self.client
+            .stats(
+                &name,
+                Some(StatsOptions {
+                    stream: true,
+                    one_shot: false,
+                }),
+            )
+            .map(|result| result.map_err(WingsError::Docker))
     }
 
     pub async fn attach_console(
@@ -456,38 +1212,97 @@ impl DockerManager {
         }
     }
 
+    /// Host ports published by server containers, keyed by server UUID.
+    /// Docker only reports them for running containers.
+    pub async fn published_ports(&self) -> Result<HashMap<String, Vec<Port>>, WingsError> {
+        let filters: HashMap<String, Vec<String>> =
+            [("label".to_string(), vec!["nexus.managed=true".to_string()])].into();
+        let containers = self
+            .client
+            .list_containers(Some(ListContainersOptions {
+                all: true,
+                filters,
+                ..Default::default()
+            }))
+            .await
+            .map_err(WingsError::Docker)?;
+        Ok(containers
+            .into_iter()
+            .filter_map(|container| {
+                let uuid = container.labels?.remove("nexus.server_uuid")?;
+                Some((uuid, container.ports.unwrap_or_default()))
+            })
+            .collect())
+    }
+
     pub async fn docker_version(&self) -> Result<String, WingsError> {
         let version = self.client.version().await.map_err(WingsError::Docker)?;
         Ok(version.version.unwrap_or_else(|| "unknown".to_string()))
     }
 
-    /// Ensure the `nexus0` bridge network exists, creating it if necessary.
-    pub async fn ensure_network(&self) -> Result<(), WingsError> {
-        const NETWORK_NAME: &str = "nexus0";
-        match self.client.inspect_network::<String>(NETWORK_NAME, None).await {
+    /// Create a network unless one of that name exists.
+    async fn create_network(&self, name: &str, shared: bool) -> Result<(), WingsError> {
+        match self.client.inspect_network::<String>(name, None).await {
             Ok(_) => {
-                tracing::debug!("Docker network '{NETWORK_NAME}' already exists");
+                tracing::debug!("Docker network '{name}' already exists");
+                Ok(())
             }
-            Err(_) => {
-                tracing::info!("Creating Docker network '{NETWORK_NAME}'");
+            Err(bollard::errors::Error::DockerResponseServerError {
+                status_code: 404, ..
+            }) => {
+                tracing::info!("Creating Docker network '{name}'");
+                // One at a time, so two isolated networks never get the same subnet
+                let _allocating = self.allocating.lock().await;
+                let isolated = match shared {
+                    true => None,
+                    false => Some(self.isolated_subnet().await?),
+                };
                 self.client
-                    .create_network(CreateNetworkOptions {
-                        name: NETWORK_NAME,
-                        driver: "bridge",
-                        ..Default::default()
-                    })
+                    .create_network(network_options(&self.config.network, name, isolated))
                     .await
                     .map_err(WingsError::Docker)?;
+                Ok(())
             }
+            Err(e) => Err(WingsError::Docker(e)),
         }
-        Ok(())
     }
 
-    /// Attach all running nexus server containers to the nexus0 network.
+    /// Subnet from the isolated pool that no Docker network uses yet.
+    async fn isolated_subnet(&self) -> Result<String, WingsError> {
+        let network = &self.config.network;
+        let pool = network.isolated_pool().ok_or_else(|| {
+            WingsError::Config(format!("Invalid isolated network pool {}", network.isolated_pool))
+        })?;
+        let networks = self
+            .client
+            .list_networks::<String>(None)
+            .await
+            .map_err(WingsError::Docker)?;
+        let used: Vec<_> = networks
+            .iter()
+            .filter_map(|n| n.ipam.as_ref()?.config.as_ref())
+            .flatten()
+            .filter_map(|c| ipv4_cidr(c.subnet.as_deref()?))
+            .collect();
+        free_subnet(pool, network.isolated_prefix, &used).ok_or_else(|| {
+            WingsError::Config(format!(
+                "No free subnet left in the isolated network pool {}",
+                network.isolated_pool
+            ))
+        })
+    }
+
+    /// Ensure the shared server network exists, creating it if necessary.
+    pub async fn ensure_network(&self) -> Result<(), WingsError> {
+        self.create_network(&self.config.network.name, true).await
+    }
+
+    /// Attach server containers created before they joined a network at
+    /// creation to the shared network. Isolated servers are left alone.
     pub async fn attach_containers_to_network(&self) -> Result<(), WingsError> {
-        const NETWORK_NAME: &str = "nexus0";
+        let network = &self.config.network.name;
         let filters: HashMap<String, Vec<String>> =
-            [("name".to_string(), vec!["nexus-".to_string()])].into();
+            [("label".to_string(), vec!["nexus.managed=true".to_string()])].into();
 
         let containers = self
             .client
@@ -500,54 +1315,725 @@ impl DockerManager {
             .map_err(WingsError::Docker)?;
 
         for container in &containers {
-            let id = match &container.id {
-                Some(id) => id,
-                None => continue,
+            let (Some(id), Some(uuid)) = (
+                &container.id,
+                container
+                    .labels
+                    .as_ref()
+                    .and_then(|labels| labels.get("nexus.server_uuid")),
+            ) else {
+                continue;
             };
 
-            // Check if already connected
-            let already_connected = container
+            let networks = container
                 .network_settings
                 .as_ref()
-                .and_then(|ns| ns.networks.as_ref())
-                .map(|nets| nets.contains_key(NETWORK_NAME))
-                .unwrap_or(false);
-
-            if already_connected {
+                .and_then(|ns| ns.networks.as_ref());
+            let connected = |name: &str| networks.is_some_and(|nets| nets.contains_key(name));
+            if connected(network) || connected(&self.isolated_network(uuid)) {
                 continue;
             }
 
-            tracing::info!("Attaching container {id} to network '{NETWORK_NAME}'");
-            let connect_opts = bollard::network::ConnectNetworkOptions {
+            tracing::info!("Attaching container {id} to network '{network}'");
+            let connect_opts = ConnectNetworkOptions {
                 container: id.as_str(),
                 ..Default::default()
             };
-            if let Err(e) = self.client.connect_network(NETWORK_NAME, connect_opts).await {
-                tracing::warn!("Failed to attach container {id} to '{NETWORK_NAME}': {e}");
+            if let Err(e) = self.client.connect_network(network, connect_opts).await {
+                tracing::warn!("Failed to attach container {id} to '{network}': {e}");
             }
         }
         Ok(())
     }
 }
 
-fn calculate_cpu_percent(stats: &bollard::container::Stats) -> f64 {
-    let cpu_stats = &stats.cpu_stats;
-    let precpu_stats = &stats.precpu_stats;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bollard::models::{CreateImageInfo, ProgressDetail};
 
-    let cpu_delta = cpu_stats.cpu_usage.total_usage as f64
-        - precpu_stats.cpu_usage.total_usage as f64;
-    let system_delta = cpu_stats.system_cpu_usage.unwrap_or(0) as f64
-        - precpu_stats.system_cpu_usage.unwrap_or(0) as f64;
+    const NETWORK: &str = "nexus0";
 
-    if system_delta > 0.0 && cpu_delta > 0.0 {
-        let num_cpus = cpu_stats
-            .cpu_usage
-            .percpu_usage
-            .as_ref()
-            .map(|v| v.len())
-            .unwrap_or(1) as f64;
-        (cpu_delta / system_delta) * num_cpus * 100.0
-    } else {
-        0.0
+    fn server() -> ServerConfig {
+        ServerConfig {
+            uuid: "5c0ffee0-0000-4000-8000-000000000000".to_string(),
+            docker_image: "ghcr.io/nexus/java:21".to_string(),
+            startup_command: "java -Xmx1G -jar server.jar".to_string(),
+            environment: HashMap::from([("EULA".to_string(), "true".to_string())]),
+            memory_limit: 1024,
+            cpu_limit: 100,
+            disk_limit: 0,
+            swap_limit: 0,
+            memory_overhead: 0,
+            io_weight: 0,
+            pids_limit: 0,
+            cpuset: String::new(),
+            oom_kill_disable: false,
+            network_isolated: false,
+            egress_limit: 0,
+            ingress_limit: 0,
+            port_mappings: vec![PortMapping {
+                host_port: 25565,
+                container_port: 25565,
+                protocol: PortProtocol::Tcp,
+                bind_ip: None,
+                primary: false,
+            }],
+            volume_path: "/data/5c0ffee0".to_string(),
+            pull_policy: None,
+            image_digest: None,
+            rebuild_pending: false,
+        }
+    }
+
+    /// `docker inspect` of the container Wings creates for `server()` on
+    /// Docker 26 with cgroup v2 and only `no_new_privileges` hardening,
+    /// trimmed to the fields Wings reads. Docker merged the image's
+    /// environment in and reports `PidsLimit` and `OomKillDisable` as null.
+    const CONTAINER_INSPECT: &str = r#"{
+        "Id": "9f3c1e0d7b2a64c1d5e8f0a3b6c9d2e5f8a1b4c7d0e3f6a9b2c5d8e1f4a7b0c3",
+        "Created": "2024-05-14T09:10:02.183417562Z",
+        "Path": "java",
+        "Args": ["-Xmx1G", "-jar", "server.jar"],
+        "State": {"Status": "exited", "Running": false, "Pid": 0, "ExitCode": 0},
+        "Image": "sha256:3b1d2f6a5c4e7d8b9a0f1e2d3c4b5a69788796a5b4c3d2e1f0a9b8c7d6e5f4a3",
+        "Name": "/nexus-5c0ffee0",
+        "HostConfig": {
+            "Binds": ["/data/5c0ffee0:/server"],
+            "NetworkMode": "nexus0",
+            "PortBindings": {"25565/tcp": [{"HostIp": "0.0.0.0", "HostPort": "25565"}]},
+            "CapAdd": null,
+            "CapDrop": null,
+            "ReadonlyRootfs": false,
+            "SecurityOpt": ["no-new-privileges:true"],
+            "Memory": 1073741824,
+            "NanoCpus": 1000000000,
+            "BlkioWeight": 0,
+            "CpusetCpus": "",
+            "MemoryReservation": 0,
+            "MemorySwap": 1073741824,
+            "MemorySwappiness": null,
+            "OomKillDisable": null,
+            "PidsLimit": null
+        },
+        "Config": {
+            "Hostname": "9f3c1e0d7b2a",
+            "User": "",
+            "AttachStdin": true,
+            "AttachStdout": true,
+            "AttachStderr": true,
+            "ExposedPorts": {"25565/tcp": {}},
+            "Tty": true,
+            "OpenStdin": true,
+            "Env": [
+                "PATH=/opt/java/openjdk/bin:/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin",
+                "JAVA_HOME=/opt/java/openjdk",
+                "LANG=en_US.UTF-8",
+                "EULA=true",
+                "SERVER_IP=0.0.0.0",
+                "SERVER_PORT=25565"
+            ],
+            "Cmd": ["java", "-Xmx1G", "-jar", "server.jar"],
+            "Image": "ghcr.io/nexus/java:21",
+            "WorkingDir": "/server",
+            "Entrypoint": null,
+            "Labels": {
+                "nexus.managed": "true",
+                "nexus.server_uuid": "5c0ffee0-0000-4000-8000-000000000000",
+                "org.opencontainers.image.source": "https://github.com/nexus/images"
+            }
+        }
+    }"#;
+
+    /// `Config` of `docker image inspect ghcr.io/nexus/java:21`.
+    const IMAGE_CONFIG: &str = r#"{
+        "Hostname": "",
+        "User": "",
+        "Env": [
+            "PATH=/opt/java/openjdk/bin:/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin",
+            "JAVA_HOME=/opt/java/openjdk",
+            "LANG=en_US.UTF-8"
+        ],
+        "Cmd": ["java", "-jar", "server.jar"],
+        "WorkingDir": "/server",
+        "Entrypoint": null,
+        "Labels": {"org.opencontainers.image.source": "https://github.com/nexus/images"}
+    }"#;
+
+    /// Restrictions the node asks for under `config`.
+    fn hardened(config: &HardeningConfig, user: Option<&str>) -> Hardening {
+        let mut host_config = HostConfig::default();
+        apply_hardening(&mut host_config, config, None);
+        Hardening::of(&host_config, user)
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut config = server();
+        let limits = Limits::of(&config);
+        assert_eq!(limits.memory, 1024 * MIB);
+        // No swap unless configured
+        assert_eq!(limits.memory_swap, 1024 * MIB);
+        assert_eq!(limits.memory_reservation, 0);
+        assert_eq!(limits.update_options().pids_limit, Some(-1));
+
+        config.memory_overhead = 256;
+        config.swap_limit = 512;
+        config.pids_limit = 512;
+        config.io_weight = 300;
+        config.cpuset = "0-3".to_string();
+        let limits = Limits::of(&config);
+        assert_eq!(limits.memory, 1280 * MIB);
+        assert_eq!(limits.memory_reservation, 1024 * MIB);
+        assert_eq!(limits.memory_swap, 1792 * MIB);
+
+        let mut host_config = HostConfig::default();
+        limits.apply(&mut host_config);
+        assert_eq!(host_config.pids_limit, Some(512));
+        assert_eq!(host_config.blkio_weight, Some(300));
+        assert_eq!(host_config.cpuset_cpus.as_deref(), Some("0-3"));
+        assert_eq!(Limits::of_container(&host_config), limits);
+
+        config.swap_limit = -1;
+        assert_eq!(Limits::of(&config).memory_swap, -1);
+        // Unlimited memory leaves swap to Docker
+        config.memory_limit = 0;
+        config.memory_overhead = 0;
+        assert_eq!(Limits::of(&config).memory_swap, 0);
+    }
+
+    #[test]
+    fn test_io_weight() {
+        assert_eq!(io_weight(0).unwrap(), 0);
+        assert_eq!(io_weight(10).unwrap(), 10);
+        assert_eq!(io_weight(1000).unwrap(), 1000);
+        for weight in [1, 9, 1001, 70_000] {
+            assert!(matches!(io_weight(weight), Err(WingsError::InvalidRequest(_))));
+        }
+    }
+
+    #[test]
+    fn test_port_bindings() {
+        let mut config = server();
+        let mapping = |host_port, container_port, protocol, bind_ip: Option<&str>| PortMapping {
+            host_port,
+            container_port,
+            protocol,
+            bind_ip: bind_ip.map(str::to_string),
+            primary: false,
+        };
+        config.port_mappings = vec![
+            mapping(2456, 2456, PortProtocol::Udp, None),
+            mapping(27015, 27015, PortProtocol::Both, Some("10.0.0.2")),
+            mapping(27016, 27015, PortProtocol::Tcp, Some("10.0.0.3")),
+        ];
+        let (bindings, exposed) = port_bindings(&config);
+
+        let mut keys: Vec<&String> = exposed.keys().collect();
+        keys.sort();
+        assert_eq!(keys, ["2456/udp", "27015/tcp", "27015/udp"]);
+        let binding = |ip: &str, port: &str| PortBinding {
+            host_ip: Some(ip.to_string()),
+            host_port: Some(port.to_string()),
+        };
+        assert_eq!(bindings["2456/udp"], Some(vec![binding("0.0.0.0", "2456")]));
+        assert_eq!(
+            bindings["27015/tcp"],
+            Some(vec![
+                binding("10.0.0.2", "27015"),
+                binding("10.0.0.3", "27016")
+            ])
+        );
+        assert_eq!(bindings["27015/udp"], Some(vec![binding("10.0.0.2", "27015")]));
+    }
+
+    #[test]
+    fn test_server_env_exposes_primary_allocation() {
+        let mut config = server();
+        let env = server_env(&config);
+        assert!(env.contains(&"SERVER_IP=0.0.0.0".to_string()));
+        assert!(env.contains(&"SERVER_PORT=25565".to_string()));
+
+        config.port_mappings.push(PortMapping {
+            host_port: 25570,
+            container_port: 25565,
+            protocol: PortProtocol::Tcp,
+            bind_ip: Some("10.0.0.2".to_string()),
+            primary: true,
+        });
+        config
+            .environment
+            .insert("SERVER_PORT".to_string(), "1".to_string());
+        let env = server_env(&config);
+        assert!(env.contains(&"SERVER_IP=10.0.0.2".to_string()));
+        assert!(env.contains(&"SERVER_PORT=1".to_string()));
+        assert!(!env.contains(&"SERVER_PORT=25570".to_string()));
+
+        config.port_mappings.clear();
+        assert!(!server_env(&config).iter().any(|e| e.starts_with("SERVER_IP=")));
+    }
+
+    #[test]
+    fn test_apply_hardening() {
+        let mut hardening = HardeningConfig {
+            drop_capabilities: true,
+            capabilities: vec!["net_bind_service".to_string(), "CAP_CHOWN".to_string()],
+            read_only_rootfs: true,
+            ..Default::default()
+        };
+        let mut host_config = HostConfig::default();
+        apply_hardening(&mut host_config, &hardening, Some(r#"{"defaultAction":"SCMP_ACT_ERRNO"}"#));
+        assert_eq!(host_config.cap_drop, Some(vec!["ALL".to_string()]));
+        assert_eq!(
+            host_config.cap_add,
+            Some(vec!["NET_BIND_SERVICE".to_string(), "CHOWN".to_string()])
+        );
+        assert_eq!(
+            host_config.security_opt,
+            Some(vec![
+                "no-new-privileges:true".to_string(),
+                r#"seccomp={"defaultAction":"SCMP_ACT_ERRNO"}"#.to_string(),
+            ])
+        );
+        assert_eq!(host_config.readonly_rootfs, Some(true));
+        assert_eq!(
+            host_config.tmpfs.as_ref().and_then(|t| t.get("/tmp")).map(String::as_str),
+            Some("rw,exec,nosuid,nodev,size=100m")
+        );
+
+        // A writable root needs no tmpfs; Docker's capabilities stay
+        hardening.drop_capabilities = false;
+        hardening.no_new_privileges = false;
+        hardening.read_only_rootfs = false;
+        let mut host_config = HostConfig::default();
+        apply_hardening(&mut host_config, &hardening, None);
+        assert_eq!(host_config.cap_drop, None);
+        assert_eq!(host_config.security_opt, Some(Vec::new()));
+        assert_eq!(host_config.readonly_rootfs, Some(false));
+        assert_eq!(host_config.tmpfs, None);
+    }
+
+    #[test]
+    fn test_structural_changes() {
+        let current: ContainerInspectResponse = serde_json::from_str(CONTAINER_INSPECT).unwrap();
+        let image: ImageConfig = serde_json::from_str(IMAGE_CONFIG).unwrap();
+        // The fixture runs with Docker's capabilities, as root, on a writable root
+        let relaxed = HardeningConfig {
+            drop_capabilities: false,
+            read_only_rootfs: false,
+            non_root: false,
+            ..Default::default()
+        };
+        let hardening = hardened(&relaxed, None);
+        let changes = |config: &ServerConfig| {
+            structural_changes(config, &current, &image, NETWORK, &hardening, false)
+        };
+        assert!(changes(&server()).is_empty());
+        assert!(!limits_changed(&server(), &current));
+
+        let mut config = server();
+        config.memory_limit = 2048;
+        config.cpu_limit = 200;
+        config.pids_limit = 256;
+        assert!(changes(&config).is_empty());
+        assert!(limits_changed(&config, &current));
+
+        // Limits Docker can't clear live
+        let mut pinned = current.clone();
+        let host_config = pinned.host_config.as_mut().unwrap();
+        host_config.cpuset_cpus = Some("0-3".to_string());
+        host_config.blkio_weight = Some(300);
+        host_config.memory_reservation = Some(1024 * MIB);
+        let on_pinned = |config: &ServerConfig| {
+            structural_changes(config, &pinned, &image, NETWORK, &hardening, false)
+        };
+        assert_eq!(on_pinned(&server()), ["memory_overhead", "cpuset", "io_weight"]);
+        let mut config = server();
+        config.cpuset = "4-7".to_string();
+        config.io_weight = 500;
+        config.memory_overhead = 256;
+        assert!(on_pinned(&config).is_empty());
+        assert!(limits_changed(&config, &pinned));
+
+        // Only cgroup v1 keeps the setting
+        let mut config = server();
+        config.oom_kill_disable = true;
+        assert!(changes(&config).is_empty());
+        let on_v1 = |config: &ServerConfig| {
+            structural_changes(config, &current, &image, NETWORK, &hardening, true)
+        };
+        assert_eq!(on_v1(&config), ["oom_kill_disable"]);
+        assert!(on_v1(&server()).is_empty());
+
+        let mut config = server();
+        config.docker_image = "ghcr.io/nexus/java:17".to_string();
+        config.startup_command = "java -jar server.jar".to_string();
+        config.port_mappings[0].host_port = 25566;
+        // SERVER_PORT follows the primary allocation
+        assert_eq!(
+            changes(&config),
+            ["image", "startup_command", "environment", "port_mappings"]
+        );
+
+        let mut config = server();
+        config.port_mappings[0].protocol = PortProtocol::Both;
+        assert_eq!(changes(&config), ["port_mappings"]);
+
+        // Added, removed and changed variables all count; the image's own don't
+        let mut config = server();
+        config.environment.insert("MOTD".to_string(), "hi".to_string());
+        assert_eq!(changes(&config), ["environment"]);
+        config.environment.clear();
+        assert_eq!(changes(&config), ["environment"]);
+        config.environment.insert("EULA".to_string(), "false".to_string());
+        assert_eq!(changes(&config), ["environment"]);
+
+        // Dropping the command falls back to the image's
+        let mut config = server();
+        config.startup_command = String::new();
+        assert_eq!(changes(&config), ["startup_command"]);
+        let mut created_without = current.clone();
+        created_without.config.as_mut().unwrap().cmd = image.cmd.clone();
+        let changes =
+            structural_changes(&config, &created_without, &image, NETWORK, &hardening, false);
+        assert!(changes.is_empty());
+
+        // Created before servers joined a network, or isolated since
+        assert_eq!(
+            structural_changes(&server(), &current, &image, "nexus0-5c0ffee0", &hardening, false),
+            ["network"]
+        );
+
+        // Hardening the node config asks for differently
+        let stricter = HardeningConfig {
+            drop_capabilities: true,
+            ..relaxed.clone()
+        };
+        let stricter = hardened(&stricter, None);
+        assert_eq!(
+            structural_changes(&server(), &current, &image, NETWORK, &stricter, false),
+            ["hardening"]
+        );
+        let non_root = hardened(&relaxed, Some("988:988"));
+        assert_eq!(
+            structural_changes(&server(), &current, &image, NETWORK, &non_root, false),
+            ["hardening"]
+        );
+    }
+
+    #[test]
+    fn test_hardening_matches_docker_spelling() {
+        let config = HardeningConfig {
+            drop_capabilities: true,
+            capabilities: vec!["net_bind_service".to_string()],
+            ..Default::default()
+        };
+        let expected = hardened(&config, Some("988:988"));
+
+        // What Docker reports back for the container
+        let mut host_config = HostConfig::default();
+        apply_hardening(&mut host_config, &config, None);
+        host_config.cap_add = Some(vec!["CAP_NET_BIND_SERVICE".to_string()]);
+        assert_eq!(Hardening::of(&host_config, Some("988:988")), expected);
+        assert_ne!(Hardening::of(&host_config, Some("")), expected);
+    }
+
+    /// One-shot stats from Docker 26 on a cgroup v2 host with 8 CPUs.
+    /// One-shot payloads carry no previous CPU reading.
+    const STATS_CGROUP_V2: &str = r#"{
+        "read": "2024-05-14T09:12:31.412783505Z",
+        "preread": "0001-01-01T00:00:00Z",
+        "pids_stats": {"current": 38, "limit": 4915},
+        "blkio_stats": {
+            "io_service_bytes_recursive": [
+                {"major": 259, "minor": 0, "op": "read", "value": 2334720},
+                {"major": 259, "minor": 0, "op": "write", "value": 0}
+            ],
+            "io_serviced_recursive": null, "io_queue_recursive": null,
+            "io_service_time_recursive": null, "io_wait_time_recursive": null,
+            "io_merged_recursive": null, "io_time_recursive": null, "sectors_recursive": null
+        },
+        "num_procs": 0,
+        "storage_stats": {},
+        "cpu_stats": {
+            "cpu_usage": {
+                "total_usage": 86000000000,
+                "usage_in_kernelmode": 9120000000,
+                "usage_in_usermode": 76880000000
+            },
+            "system_cpu_usage": 1543200000000000,
+            "online_cpus": 8,
+            "throttling_data": {"periods": 1280, "throttled_periods": 12, "throttled_time": 402000000}
+        },
+        "precpu_stats": {
+            "cpu_usage": {"total_usage": 0, "usage_in_kernelmode": 0, "usage_in_usermode": 0},
+            "throttling_data": {"periods": 0, "throttled_periods": 0, "throttled_time": 0}
+        },
+        "memory_stats": {
+            "usage": 1073741824,
+            "stats": {
+                "active_anon": 0, "active_file": 4096, "anon": 1002438656, "anon_thp": 0,
+                "file": 65536000, "file_dirty": 0, "file_mapped": 4096, "file_writeback": 0,
+                "inactive_anon": 1002438656, "inactive_file": 65531904, "kernel_stack": 622592,
+                "pgactivate": 1, "pgdeactivate": 0, "pgfault": 283947, "pglazyfree": 0,
+                "pglazyfreed": 0, "pgmajfault": 0, "pgrefill": 0, "pgscan": 0, "pgsteal": 0,
+                "shmem": 0, "slab": 1320880, "slab_reclaimable": 941112,
+                "slab_unreclaimable": 379768, "sock": 0, "thp_collapse_alloc": 0,
+                "thp_fault_alloc": 0, "unevictable": 0, "workingset_activate": 0,
+                "workingset_nodereclaim": 0, "workingset_refault": 0
+            },
+            "limit": 2147483648
+        },
+        "name": "/nexus-5c0ffee0",
+        "id": "b1e2a1c3f8d94b5a8e2c7d6f9a0b1c2d3e4f5a6b7c8d9e0f1a2b3c4d5e6f7a8b",
+        "networks": {
+            "eth0": {
+                "rx_bytes": 18350080, "rx_packets": 21044, "rx_errors": 0, "rx_dropped": 0,
+                "tx_bytes": 5242880, "tx_packets": 9120, "tx_errors": 0, "tx_dropped": 0
+            }
+        }
+    }"#;
+
+    /// Streamed stats from Docker 20.10 on a cgroup v1 host with 4 CPUs,
+    /// which list per-CPU usage and carry the previous reading.
+    const STATS_CGROUP_V1: &str = r#"{
+        "read": "2023-02-03T14:01:12.918231845Z",
+        "preread": "2023-02-03T14:01:11.914782271Z",
+        "pids_stats": {"current": 21},
+        "blkio_stats": {
+            "io_service_bytes_recursive": [], "io_serviced_recursive": [],
+            "io_queue_recursive": [], "io_service_time_recursive": [],
+            "io_wait_time_recursive": [], "io_merged_recursive": [],
+            "io_time_recursive": [], "sectors_recursive": []
+        },
+        "num_procs": 0,
+        "storage_stats": {},
+        "cpu_stats": {
+            "cpu_usage": {
+                "total_usage": 40500000000,
+                "percpu_usage": [10200000000, 10100000000, 10150000000, 10050000000],
+                "usage_in_kernelmode": 3500000000,
+                "usage_in_usermode": 36900000000
+            },
+            "system_cpu_usage": 9004000000000,
+            "online_cpus": 4,
+            "throttling_data": {"periods": 0, "throttled_periods": 0, "throttled_time": 0}
+        },
+        "precpu_stats": {
+            "cpu_usage": {
+                "total_usage": 40000000000,
+                "percpu_usage": [10075000000, 9975000000, 10025000000, 9925000000],
+                "usage_in_kernelmode": 3450000000,
+                "usage_in_usermode": 36450000000
+            },
+            "system_cpu_usage": 9000000000000,
+            "online_cpus": 4,
+            "throttling_data": {"periods": 0, "throttled_periods": 0, "throttled_time": 0}
+        },
+        "memory_stats": {
+            "usage": 734003200,
+            "max_usage": 801112064,
+            "stats": {
+                "active_anon": 681574400, "active_file": 12288000, "cache": 40960000,
+                "dirty": 0, "hierarchical_memory_limit": 1073741824, "inactive_anon": 0,
+                "inactive_file": 28672000, "mapped_file": 8192000, "pgfault": 190011,
+                "pgmajfault": 33, "pgpgin": 206221, "pgpgout": 26471, "rss": 681574400,
+                "rss_huge": 0, "total_active_anon": 681574400, "total_active_file": 12288000,
+                "total_cache": 40960000, "total_dirty": 0, "total_inactive_anon": 0,
+                "total_inactive_file": 28672000, "total_mapped_file": 8192000,
+                "total_pgfault": 190011, "total_pgmajfault": 33, "total_pgpgin": 206221,
+                "total_pgpgout": 26471, "total_rss": 681574400, "total_rss_huge": 0,
+                "total_unevictable": 0, "total_writeback": 0, "unevictable": 0, "writeback": 0
+            },
+            "failcnt": 0,
+            "limit": 1073741824
+        },
+        "name": "/nexus-5c0ffee0",
+        "id": "4f1d0c6b2a9e8d7c6b5a4f3e2d1c0b9a8f7e6d5c4b3a2f1e0d9c8b7a6f5e4d3c",
+        "networks": {
+            "eth0": {
+                "rx_bytes": 1048576, "rx_packets": 812, "rx_errors": 0, "rx_dropped": 0,
+                "tx_bytes": 2097152, "tx_packets": 1630, "tx_errors": 0, "tx_dropped": 0
+            }
+        }
+    }"#;
+
+    fn stats(payload: &str) -> Stats {
+        serde_json::from_str(payload).unwrap()
+    }
+
+    #[test]
+    fn test_cpu_percent_cgroup_v2_one_shot() {
+        let first = stats(STATS_CGROUP_V2);
+        let start = Instant::now();
+        let (resources, sample) = measure(&first, None, start, 200);
+        // Nothing to compare the first one-shot reading with
+        assert_eq!(resources.cpu_percent, 0.0);
+        assert_eq!(resources.memory_bytes, 1 << 30);
+
+        // 2s later: 3s of CPU time out of the 16s the 8 CPUs had
+        let mut second = stats(STATS_CGROUP_V2);
+        second.cpu_stats.cpu_usage.total_usage += 3_000_000_000;
+        second.cpu_stats.system_cpu_usage = Some(1_543_200_000_000_000 + 16_000_000_000);
+        let (resources, _) = measure(&second, Some(sample), start + Duration::from_secs(2), 200);
+        assert!((resources.cpu_percent - 150.0).abs() < 1e-9);
+        assert!((resources.cpu_limit_percent - 75.0).abs() < 1e-9);
+
+        // Without a CPU limit the server is measured against the node
+        let (resources, _) = measure(&second, Some(sample), start + Duration::from_secs(2), 0);
+        assert!((resources.cpu_limit_percent - 18.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_cpu_percent_cgroup_v1_stream() {
+        let payload = stats(STATS_CGROUP_V1);
+        let (resources, _) = measure(&payload, None, Instant::now(), 100);
+        // 0.5s of CPU time out of the 4s the 4 CPUs had
+        assert!((resources.cpu_percent - 50.0).abs() < 1e-9);
+        assert!((resources.cpu_limit_percent - 50.0).abs() < 1e-9);
+        assert_eq!(resources.network_tx_bytes, 2 << 20);
+
+        // Daemons without online_cpus: count the per-CPU usage instead
+        let mut payload = stats(STATS_CGROUP_V1);
+        payload.cpu_stats.online_cpus = None;
+        assert_eq!(online_cpus(&payload.cpu_stats), 4);
+        let (resources, _) = measure(&payload, None, Instant::now(), 100);
+        assert!((resources.cpu_percent - 50.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_cpu_percent_after_restart() {
+        let before = CpuUsage {
+            total: 5_000_000_000,
+            system: 1_000_000_000_000,
+        };
+        // CPU time starts over with the container
+        let after = CpuUsage {
+            total: 100_000_000,
+            system: 1_002_000_000_000,
+        };
+        assert_eq!(before.percent_until(&after, 4), 0.0);
+        assert_eq!(before.percent_until(&before, 4), 0.0);
+    }
+
+    #[test]
+    fn test_network_sample_rates() {
+        let start = Instant::now();
+        let first = NetworkSample {
+            rx_bytes: 1_000,
+            tx_bytes: 5_000,
+            at: start,
+            rates: (0, 0),
+        };
+        let second = first.next(3_000, 6_000, start + Duration::from_secs(2));
+        assert_eq!(second.rates, (1_000, 500));
+
+        // Too soon after the last sample to say anything new
+        let third = second.next(90_000, 90_000, start + Duration::from_millis(2100));
+        assert_eq!(third.rates, (1_000, 500));
+        assert_eq!(third.rx_bytes, 3_000);
+
+        // Counters restarted with the container
+        let fourth = second.next(100, 100, start + Duration::from_secs(3));
+        assert_eq!(fourth.rates, (0, 0));
+    }
+
+    #[test]
+    fn test_network_options() {
+        let network = NetworkConfig {
+            subnet: Some("172.28.0.0/16".to_string()),
+            gateway: Some("172.28.0.1".to_string()),
+            ipv6: true,
+            ipv6_subnet: Some("fd00:28::/64".to_string()),
+            mtu: Some(1450),
+            icc: false,
+            ..Default::default()
+        };
+        let shared = network_options(&network, "nexus0", None);
+        assert!(shared.enable_ipv6);
+        let subnets: Vec<_> = shared
+            .ipam
+            .config
+            .unwrap()
+            .into_iter()
+            .map(|c| (c.subnet.unwrap(), c.gateway))
+            .collect();
+        assert_eq!(
+            subnets,
+            [
+                ("172.28.0.0/16".to_string(), Some("172.28.0.1".to_string())),
+                ("fd00:28::/64".to_string(), None),
+            ]
+        );
+        assert_eq!(shared.options["com.docker.network.driver.mtu"], "1450");
+        assert_eq!(shared.options["com.docker.network.bridge.enable_icc"], "false");
+
+        // Isolated networks would clash with the shared subnet
+        let subnet = Some("10.210.0.16/28".to_string());
+        let isolated = network_options(&network, "nexus0-5c0ffee0", subnet);
+        assert_eq!(isolated.name, "nexus0-5c0ffee0");
+        assert!(!isolated.enable_ipv6);
+        let subnets: Vec<_> = isolated
+            .ipam
+            .config
+            .unwrap()
+            .into_iter()
+            .map(|c| (c.subnet.unwrap(), c.gateway))
+            .collect();
+        assert_eq!(subnets, [("10.210.0.16/28".to_string(), None)]);
+        assert_eq!(isolated.options.len(), 1);
+
+        let defaults = network_options(&NetworkConfig::default(), "nexus0", None);
+        assert!(defaults.ipam.config.is_none() && defaults.options.is_empty());
+    }
+
+    #[test]
+    fn test_free_subnet() {
+        let pool = (Ipv4Addr::new(10, 210, 0, 0), 16);
+        let cidr = |cidr: &str| ipv4_cidr(cidr).unwrap();
+        assert_eq!(free_subnet(pool, 28, &[]).as_deref(), Some("10.210.0.0/28"));
+
+        // Skips subnets taken by smaller, equal and larger networks
+        let used = [cidr("10.210.0.4/30"), cidr("10.210.0.16/28"), cidr("172.28.0.0/16")];
+        assert_eq!(free_subnet(pool, 28, &used).as_deref(), Some("10.210.0.32/28"));
+        let used = [cidr("10.210.0.0/24")];
+        assert_eq!(free_subnet(pool, 28, &used).as_deref(), Some("10.210.1.0/28"));
+
+        // A pool Docker already uses whole has nothing left
+        assert_eq!(free_subnet(pool, 28, &[cidr("10.0.0.0/8")]), None);
+        let small = (Ipv4Addr::new(10, 210, 0, 0), 27);
+        let used = [cidr("10.210.0.0/28"), cidr("10.210.0.16/28")];
+        assert_eq!(free_subnet(small, 28, &used), None);
+    }
+
+    fn info(id: &str, status: &str, current: Option<i64>, total: Option<i64>) -> CreateImageInfo {
+        CreateImageInfo {
+            id: Some(id.to_string()),
+            status: Some(status.to_string()),
+            progress_detail: Some(ProgressDetail { current, total }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_pull_progress_sums_layers() {
+        let mut progress = PullProgress::default();
+        assert!(!progress.update(&info("a", "Pulling fs layer", None, None)));
+        assert!(progress.update(&info("a", "Downloading", Some(100), Some(1000))));
+        assert!(progress.update(&info("b", "Downloading", Some(50), Some(500))));
+        assert_eq!((progress.downloaded(), progress.total()), (150, 1500));
+
+        assert!(progress.update(&info("a", "Download complete", None, None)));
+        // Extraction byte counts don't move the download total
+        assert!(!progress.update(&info("a", "Extracting", Some(10), Some(2000))));
+        assert!(!progress.update(&info("a", "Pull complete", None, None)));
+        assert_eq!((progress.downloaded(), progress.total()), (1050, 1500));
+
+        // Status lines without a layer are ignored
+        let digest = CreateImageInfo {
+            status: Some("Digest: sha256:abc".to_string()),
+            ..Default::default()
+        };
+        assert!(!progress.update(&digest));
     }
 }
@@ -18,6 +18,25 @@ pub enum WingsError {
     Config(String),
     #[error("Authentication failed")]
     AuthFailed,
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("Disk quota exceeded")]
+    QuotaExceeded,
+    #[error("Operation cancelled")]
+    Cancelled,
+    #[error("Archive rejected: {0}")]
+    ArchiveRejected(String),
+    #[error("Backup not found: {0}")]
+    BackupNotFound(String),
+    #[error("Remote storage error: {0}")]
+    RemoteStorage(String),
+    #[error("Transfer failed: {0}")]
+    Transfer(String),
+    #[error("Port {}:{}/{} is already used by server {server}", port.ip, port.port, port.protocol)]
+    PortConflict {
+        port: crate::ports::UsedPort,
+        server: String,
+    },
 }
 
 impl IntoResponse for WingsError {
@@ -30,6 +49,14 @@ impl IntoResponse for WingsError {
             WingsError::FileTooLarge => (StatusCode::PAYLOAD_TOO_LARGE, self.to_string()),
             WingsError::Config(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
             WingsError::AuthFailed => (StatusCode::UNAUTHORIZED, self.to_string()),
+            WingsError::InvalidRequest(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            WingsError::QuotaExceeded => (StatusCode::INSUFFICIENT_STORAGE, self.to_string()),
+            WingsError::Cancelled => (StatusCode::CONFLICT, self.to_string()),
+            WingsError::ArchiveRejected(_) => (StatusCode::UNPROCESSABLE_ENTITY, self.to_string()),
+            WingsError::BackupNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
+            WingsError::RemoteStorage(_) => (StatusCode::BAD_GATEWAY, self.to_string()),
+            WingsError::Transfer(_) => (StatusCode::BAD_GATEWAY, self.to_string()),
+            WingsError::PortConflict { .. } => (StatusCode::CONFLICT, self.to_string()),
         };
 
         let body = json!({ "error": message });
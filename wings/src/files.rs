@@ -1,20 +1,106 @@
+use std::collections::HashSet;
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use chrono::{DateTime, Utc};
-use serde::Serialize;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
 
+use crate::config::ContainerUser;
 use crate::error::WingsError;
+use crate::jobs::JobProgress;
 
-const MAX_FILE_SIZE: u64 = 10 * 1024 * 1024; // 10MB
+pub const MAX_FILE_SIZE: u64 = 10 * 1024 * 1024; // 10MB
+const SNIFF_LEN: usize = 8192;
 
 #[derive(Debug, Serialize)]
 pub struct FileEntry {
     pub name: String,
+    /// Path relative to the listed directory; differs from `name` in
+    /// recursive listings
+    pub path: String,
     pub is_directory: bool,
+    pub is_symlink: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symlink_target: Option<String>,
     pub size: u64,
     pub modified: DateTime<Utc>,
     pub mime_type: String,
+    /// Octal permission bits, e.g. "644"
+    pub mode: String,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    #[default]
+    Name,
+    Size,
+    Modified,
+}
+
+/// Controls how [`list_directory`] filters, orders and pages entries.
+/// Directories always sort before files.
+#[derive(Debug)]
+pub struct ListOptions {
+    pub sort: SortKey,
+    pub descending: bool,
+    /// Only entries whose name matches are returned
+    pub filter: Option<globset::GlobMatcher>,
+    /// 1 lists only the directory itself, larger values recurse
+    pub depth: usize,
+    /// Resume after the entry this cursor was issued for
+    pub cursor: Option<ListCursor>,
+    pub limit: usize,
+}
+
+impl Default for ListOptions {
+    fn default() -> Self {
+        Self {
+            sort: SortKey::Name,
+            descending: false,
+            filter: None,
+            depth: 1,
+            cursor: None,
+            limit: usize::MAX,
+        }
+    }
+}
+
+/// Position of the last entry of a page. Keyed on the sort fields rather
+/// than an offset so pages stay consistent while files come and go.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListCursor {
+    #[serde(rename = "d")]
+    is_dir: bool,
+    #[serde(rename = "k")]
+    key: i64,
+    #[serde(rename = "p")]
+    path: String,
+}
+
+impl ListCursor {
+    pub fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(json)
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, WingsError> {
+        base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or_else(|| WingsError::InvalidRequest("invalid cursor".into()))
+    }
+}
+
+#[derive(Debug)]
+pub struct ListPage {
+    pub entries: Vec<FileEntry>,
+    pub next_cursor: Option<String>,
 }
 
 /// Validates and resolves a requested path to ensure it stays within server_root.
@@ -76,56 +162,499 @@ pub fn validate_path(server_root: &Path, requested_path: &str) -> Result<PathBuf
     Ok(canonical)
 }
 
-pub fn list_directory(path: &Path) -> Result<Vec<FileEntry>, WingsError> {
-    let mut entries = Vec::new();
-    for entry in std::fs::read_dir(path).map_err(WingsError::Io)? {
-        let entry = entry.map_err(WingsError::Io)?;
-        let metadata = entry.metadata().map_err(WingsError::Io)?;
-        let name = entry.file_name().to_string_lossy().to_string();
-        let modified: DateTime<Utc> = metadata
-            .modified()
-            .map_err(WingsError::Io)?
-            .into();
-        let mime = if metadata.is_dir() {
-            "directory".to_string()
-        } else {
-            mime_guess::from_path(&name)
-                .first_or_octet_stream()
-                .to_string()
+struct ListCandidate {
+    full_path: PathBuf,
+    path: String,
+    lowercase: String,
+    is_dir: bool,
+    key: i64,
+}
+
+impl ListCandidate {
+    fn order(
+        &self,
+        other_dir: bool,
+        other_key: i64,
+        other_lower: &str,
+        other_path: &str,
+        options: &ListOptions,
+    ) -> std::cmp::Ordering {
+        let primary = match options.sort {
+            SortKey::Name => self.lowercase.as_str().cmp(other_lower),
+            SortKey::Size | SortKey::Modified => self.key.cmp(&other_key),
+        }
+        .then_with(|| self.path.as_str().cmp(other_path));
+        let primary = if options.descending { primary.reverse() } else { primary };
+        other_dir.cmp(&self.is_dir).then(primary)
+    }
+
+    fn cmp(&self, other: &Self, options: &ListOptions) -> std::cmp::Ordering {
+        self.order(other.is_dir, other.key, &other.lowercase, &other.path, options)
+    }
+}
+
+fn file_entry(full_path: &Path, path: String) -> std::io::Result<FileEntry> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = std::fs::symlink_metadata(full_path)?;
+    let name = full_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let is_symlink = metadata.file_type().is_symlink();
+    let symlink_target = if is_symlink {
+        std::fs::read_link(full_path)
+            .ok()
+            .map(|target| target.to_string_lossy().to_string())
+    } else {
+        None
+    };
+    let mime = if metadata.is_dir() {
+        "directory".to_string()
+    } else {
+        mime_guess::from_path(&name)
+            .first_or_octet_stream()
+            .to_string()
+    };
+    Ok(FileEntry {
+        name,
+        path,
+        is_directory: metadata.is_dir(),
+        is_symlink,
+        symlink_target,
+        size: metadata.len(),
+        modified: metadata.modified()?.into(),
+        mime_type: mime,
+        mode: format!("{:o}", metadata.mode() & 0o7777),
+        uid: metadata.uid(),
+        gid: metadata.gid(),
+    })
+}
+
+/// List the entries of a directory, one page at a time.
+///
+/// Entries are only stat'ed once they make it into the page, unless they
+/// are sorted by size or modification time.
+pub fn list_directory(path: &Path, options: &ListOptions) -> Result<ListPage, WingsError> {
+    let mut candidates = Vec::new();
+    let walker = walkdir::WalkDir::new(path)
+        .min_depth(1)
+        .max_depth(options.depth.max(1));
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            // Unreadable subdirectories are skipped, an unreadable root is an error
+            Err(e) if e.depth() == 0 => return Err(WingsError::Io(e.into())),
+            Err(_) => continue,
+        };
+        if let Some(filter) = &options.filter {
+            if !filter.is_match(entry.file_name()) {
+                continue;
+            }
+        }
+        let key = match options.sort {
+            SortKey::Name => 0,
+            SortKey::Size => match entry.metadata() {
+                Ok(m) => m.len() as i64,
+                Err(_) => continue,
+            },
+            SortKey::Modified => match entry.metadata().ok().and_then(|m| m.modified().ok()) {
+                Some(modified) => DateTime::<Utc>::from(modified).timestamp_millis(),
+                None => continue,
+            },
         };
-        entries.push(FileEntry {
-            name,
-            is_directory: metadata.is_dir(),
-            size: metadata.len(),
-            modified,
-            mime_type: mime,
+        let rel = entry
+            .path()
+            .strip_prefix(path)
+            .unwrap_or(entry.path())
+            .to_string_lossy()
+            .to_string();
+        candidates.push(ListCandidate {
+            full_path: entry.path().to_path_buf(),
+            lowercase: rel.to_lowercase(),
+            path: rel,
+            is_dir: entry.file_type().is_dir(),
+            key,
         });
     }
-    entries.sort_by(|a, b| {
-        b.is_directory
-            .cmp(&a.is_directory)
-            .then(a.name.to_lowercase().cmp(&b.name.to_lowercase()))
-    });
-    Ok(entries)
+
+    if let Some(cursor) = &options.cursor {
+        let lower = cursor.path.to_lowercase();
+        candidates.retain(|c| {
+            c.order(cursor.is_dir, cursor.key, &lower, &cursor.path, options)
+                == std::cmp::Ordering::Greater
+        });
+    }
+
+    // Only the page itself needs to be fully sorted
+    let has_more = candidates.len() > options.limit;
+    if has_more {
+        candidates.select_nth_unstable_by(options.limit, |a, b| a.cmp(b, options));
+        candidates.truncate(options.limit);
+    }
+    candidates.sort_by(|a, b| a.cmp(b, options));
+
+    let next_cursor = if has_more {
+        candidates.last().map(|last| {
+            ListCursor {
+                is_dir: last.is_dir,
+                key: last.key,
+                path: last.path.clone(),
+            }
+            .encode()
+        })
+    } else {
+        None
+    };
+
+    let entries = candidates
+        .into_iter()
+        // Entries removed since the walk are dropped from the page
+        .filter_map(|c| file_entry(&c.full_path, c.path).ok())
+        .collect();
+    Ok(ListPage {
+        entries,
+        next_cursor,
+    })
+}
+
+/// Encodings a text file can be detected as and written back in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding {
+    Utf8,
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    Windows1252,
+}
+
+impl TextEncoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TextEncoding::Utf8 => "utf-8",
+            TextEncoding::Utf8Bom => "utf-8-bom",
+            TextEncoding::Utf16Le => "utf-16le",
+            TextEncoding::Utf16Be => "utf-16be",
+            TextEncoding::Windows1252 => "windows-1252",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(TextEncoding::Utf8),
+            "utf-8-bom" | "utf8-bom" => Some(TextEncoding::Utf8Bom),
+            "utf-16le" | "utf16le" => Some(TextEncoding::Utf16Le),
+            "utf-16be" | "utf16be" => Some(TextEncoding::Utf16Be),
+            "windows-1252" | "cp1252" | "latin-1" | "latin1" | "iso-8859-1" => {
+                Some(TextEncoding::Windows1252)
+            }
+            _ => None,
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> String {
+        let encoding = match self {
+            TextEncoding::Utf8 | TextEncoding::Utf8Bom => encoding_rs::UTF_8,
+            TextEncoding::Utf16Le => encoding_rs::UTF_16LE,
+            TextEncoding::Utf16Be => encoding_rs::UTF_16BE,
+            TextEncoding::Windows1252 => encoding_rs::WINDOWS_1252,
+        };
+        encoding.decode_with_bom_removal(bytes).0.into_owned()
+    }
+
+    /// Encode `text` back into this encoding, failing if it contains
+    /// characters the encoding cannot represent.
+    pub fn encode(self, text: &str) -> Result<Vec<u8>, WingsError> {
+        let bytes = match self {
+            TextEncoding::Utf8 => text.as_bytes().to_vec(),
+            TextEncoding::Utf8Bom => [&[0xEF, 0xBB, 0xBF], text.as_bytes()].concat(),
+            TextEncoding::Utf16Le => [0xFF, 0xFE]
+                .into_iter()
+                .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+                .collect(),
+            TextEncoding::Utf16Be => [0xFE, 0xFF]
+                .into_iter()
+                .chain(text.encode_utf16().flat_map(u16::to_be_bytes))
+                .collect(),
+            TextEncoding::Windows1252 => {
+                let (bytes, _, had_errors) = encoding_rs::WINDOWS_1252.encode(text);
+                if had_errors {
+                    return Err(WingsError::InvalidRequest(
+                        "Content contains characters not representable in windows-1252".into(),
+                    ));
+                }
+                bytes.into_owned()
+            }
+        };
+        Ok(bytes)
+    }
+}
+
+/// A file read through the API, decoded to UTF-8 when it is text.
+#[derive(Debug)]
+pub enum FileContents {
+    Text {
+        encoding: TextEncoding,
+        content: String,
+    },
+    Binary(Vec<u8>),
+}
+
+/// Detect the text encoding of `bytes`, or `None` if the data looks binary.
+pub fn detect_encoding(bytes: &[u8]) -> Option<TextEncoding> {
+    if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        return Some(TextEncoding::Utf8Bom);
+    }
+    if bytes.starts_with(&[0xFF, 0xFE]) {
+        return Some(TextEncoding::Utf16Le);
+    }
+    if bytes.starts_with(&[0xFE, 0xFF]) {
+        return Some(TextEncoding::Utf16Be);
+    }
+
+    let sample = &bytes[..bytes.len().min(SNIFF_LEN)];
+    if looks_binary(sample) {
+        return None;
+    }
+    if std::str::from_utf8(bytes).is_ok() {
+        Some(TextEncoding::Utf8)
+    } else {
+        // Any byte sequence decodes as windows-1252, which covers the
+        // Latin-1 configs older plugins write.
+        Some(TextEncoding::Windows1252)
+    }
+}
+
+fn looks_binary(sample: &[u8]) -> bool {
+    if sample.contains(&0) {
+        return true;
+    }
+    let control = sample
+        .iter()
+        .filter(|&&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c | 0x1b))
+        .count();
+    control * 10 > sample.len()
 }
 
-pub fn read_file(path: &Path) -> Result<String, WingsError> {
+pub fn read_file(path: &Path) -> Result<FileContents, WingsError> {
     let metadata = std::fs::metadata(path).map_err(WingsError::Io)?;
     if metadata.len() > MAX_FILE_SIZE {
         return Err(WingsError::FileTooLarge);
     }
-    std::fs::read_to_string(path).map_err(WingsError::Io)
+    let bytes = std::fs::read(path).map_err(WingsError::Io)?;
+    Ok(match detect_encoding(&bytes) {
+        Some(encoding) => FileContents::Text {
+            encoding,
+            content: encoding.decode(&bytes),
+        },
+        None => FileContents::Binary(bytes),
+    })
+}
+
+/// Atomically replace `path` with `content`: the data is written to a
+/// temporary file in the same directory, fsynced and renamed over the
+/// target, so a crash mid-write never leaves a truncated file behind.
+pub fn write_file(path: &Path, content: &[u8]) -> Result<(), WingsError> {
+    let parent = path.parent().ok_or(WingsError::PathTraversal)?;
+    let file_name = path.file_name().ok_or(WingsError::PathTraversal)?;
+    std::fs::create_dir_all(parent).map_err(WingsError::Io)?;
+
+    let tmp_path = parent.join(format!(
+        ".{}.nexus-tmp-{}",
+        file_name.to_string_lossy(),
+        uuid::Uuid::new_v4().simple()
+    ));
+
+    let result = (|| -> std::io::Result<()> {
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp_path)?;
+        file.write_all(content)?;
+        // Keep the mode of the file being replaced (e.g. executable scripts)
+        if let Ok(existing) = std::fs::metadata(path) {
+            file.set_permissions(existing.permissions())?;
+        }
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, path)?;
+        std::fs::File::open(parent)?.sync_all()
+    })();
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result.map_err(WingsError::Io)
+}
+
+/// Give `path` and everything below it to the container user, if one is
+/// configured. Symlinks themselves are chowned, not their targets.
+pub fn chown_recursive(path: &Path, owner: Option<ContainerUser>) -> Result<(), WingsError> {
+    let Some(owner) = owner else {
+        return Ok(());
+    };
+    for entry in walkdir::WalkDir::new(path).max_depth(64) {
+        let entry = entry.map_err(|e| WingsError::Io(std::io::Error::other(e)))?;
+        std::os::unix::fs::lchown(entry.path(), Some(owner.uid), Some(owner.gid))
+            .map_err(WingsError::Io)?;
+    }
+    Ok(())
+}
+
+/// Parse an octal permission mode such as "755" or "0644".
+pub fn parse_mode(mode: &str) -> Result<u32, WingsError> {
+    let parsed = u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+        .map_err(|_| WingsError::InvalidRequest(format!("Invalid file mode: {mode}")))?;
+    // Only plain permission bits; setuid/setgid/sticky are not allowed
+    if parsed > 0o777 {
+        return Err(WingsError::InvalidRequest(format!("Invalid file mode: {mode}")));
+    }
+    Ok(parsed)
 }
 
-pub fn write_file(path: &Path, content: &str) -> Result<(), WingsError> {
-    if let Some(parent) = path.parent() {
-        std::fs::create_dir_all(parent).map_err(WingsError::Io)?;
+pub fn chmod(path: &Path, mode: u32) -> Result<(), WingsError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).map_err(WingsError::Io)
+}
+
+/// Length at which search result snippets are cut.
+const SNIPPET_LEN: usize = 200;
+
+/// Criteria for [`search`]. At least one of `name` or `content` is set.
+pub struct SearchOptions {
+    /// Glob matched against the file name, or the relative path if it
+    /// contains a `/`
+    pub name: Option<globset::GlobMatcher>,
+    pub name_matches_path: bool,
+    /// Pattern searched for line by line in text files
+    pub content: Option<regex::Regex>,
+    /// Files larger than this are not searched for content
+    pub max_file_size: u64,
+    /// Stop after this many results
+    pub limit: usize,
+    pub deadline: std::time::Instant,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchMatch {
+    /// Path relative to the server root, with a leading slash
+    pub path: String,
+    pub is_directory: bool,
+    pub size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SearchSummary {
+    pub matches: usize,
+    pub files_scanned: usize,
+    /// The result limit was reached
+    pub truncated: bool,
+    pub timed_out: bool,
+}
+
+fn snippet(line: &str, match_start: usize) -> String {
+    let line = line.trim_end();
+    if line.chars().count() <= SNIPPET_LEN {
+        return line.to_string();
+    }
+    // Center the window on the match
+    let start = line[..match_start].chars().count().saturating_sub(SNIPPET_LEN / 2);
+    line.chars().skip(start).take(SNIPPET_LEN).collect()
+}
+
+/// Search below `start` (inside `root`) for entries matching `options`,
+/// passing each result to `emit` until it returns false. Symlinks are not
+/// followed and binary files are never searched for content.
+pub fn search(
+    root: &Path,
+    start: &Path,
+    options: &SearchOptions,
+    mut emit: impl FnMut(SearchMatch) -> bool,
+) -> SearchSummary {
+    let mut summary = SearchSummary::default();
+    for entry in walkdir::WalkDir::new(start).min_depth(1).max_depth(64) {
+        if std::time::Instant::now() >= options.deadline {
+            summary.timed_out = true;
+            break;
+        }
+        let Ok(entry) = entry else { continue };
+        let Ok(rel) = entry.path().strip_prefix(root) else { continue };
+        let rel_path = format!("/{}", rel.to_string_lossy());
+
+        if let Some(glob) = &options.name {
+            let matched = if options.name_matches_path {
+                glob.is_match(rel)
+            } else {
+                glob.is_match(entry.file_name())
+            };
+            if !matched {
+                continue;
+            }
+        }
+        let Ok(metadata) = entry.metadata() else { continue };
+
+        let mut results = Vec::new();
+        match &options.content {
+            None => results.push(SearchMatch {
+                path: rel_path,
+                is_directory: metadata.is_dir(),
+                size: metadata.len(),
+                line: None,
+                snippet: None,
+            }),
+            Some(pattern) => {
+                if !metadata.is_file() || metadata.len() > options.max_file_size {
+                    continue;
+                }
+                summary.files_scanned += 1;
+                let Ok(bytes) = std::fs::read(entry.path()) else { continue };
+                let Some(encoding) = detect_encoding(&bytes) else { continue };
+                let text = encoding.decode(&bytes);
+                for (idx, line) in text.lines().enumerate() {
+                    if let Some(m) = pattern.find(line) {
+                        results.push(SearchMatch {
+                            path: rel_path.clone(),
+                            is_directory: false,
+                            size: metadata.len(),
+                            line: Some(idx + 1),
+                            snippet: Some(snippet(line, m.start())),
+                        });
+                    }
+                }
+            }
+        }
+
+        for result in results {
+            if summary.matches >= options.limit {
+                summary.truncated = true;
+                return summary;
+            }
+            summary.matches += 1;
+            if !emit(result) {
+                return summary;
+            }
+        }
     }
-    std::fs::write(path, content).map_err(WingsError::Io)
+    summary
 }
 
-pub fn create_directory(path: &Path) -> Result<(), WingsError> {
-    std::fs::create_dir_all(path).map_err(WingsError::Io)
+/// Create `path` and any missing parents, giving every directory created to
+/// the container user, if one is configured.
+pub fn create_directory(path: &Path, owner: Option<ContainerUser>) -> Result<(), WingsError> {
+    let missing: Vec<&Path> = path.ancestors().take_while(|dir| !dir.exists()).collect();
+    std::fs::create_dir_all(path).map_err(WingsError::Io)?;
+    let Some(owner) = owner else {
+        return Ok(());
+    };
+    for dir in missing {
+        std::os::unix::fs::lchown(dir, Some(owner.uid), Some(owner.gid))
+            .map_err(WingsError::Io)?;
+    }
+    Ok(())
 }
 
 pub fn rename_entry(from: &Path, to: &Path) -> Result<(), WingsError> {
@@ -143,178 +672,1873 @@ pub fn delete_entries(paths: &[PathBuf]) -> Result<(), WingsError> {
     Ok(())
 }
 
-pub fn compress(paths: &[PathBuf], dest: &Path) -> Result<(), WingsError> {
-    let file = std::fs::File::create(dest).map_err(WingsError::Io)?;
-    let mut zip_writer = zip::ZipWriter::new(file);
-    let options = zip::write::SimpleFileOptions::default()
-        .compression_method(zip::CompressionMethod::Deflated);
+/// Pick a free name for a copy of `name` inside `dir`: the name itself if
+/// unused, then "name copy", "name copy 2", ... keeping any file extension.
+pub fn copy_destination(dir: &Path, name: &std::ffi::OsStr, is_dir: bool) -> PathBuf {
+    let candidate = dir.join(name);
+    if candidate.symlink_metadata().is_err() {
+        return candidate;
+    }
+    let name_path = Path::new(name);
+    let (stem, ext) = match (is_dir, name_path.file_stem(), name_path.extension()) {
+        (false, Some(stem), Some(ext)) => (
+            stem.to_string_lossy().to_string(),
+            format!(".{}", ext.to_string_lossy()),
+        ),
+        _ => (name.to_string_lossy().to_string(), String::new()),
+    };
+    (1..)
+        .map(|n| match n {
+            1 => dir.join(format!("{stem} copy{ext}")),
+            n => dir.join(format!("{stem} copy {n}{ext}")),
+        })
+        .find(|p| p.symlink_metadata().is_err())
+        .expect("unbounded iterator")
+}
 
-    for path in paths {
-        if path.is_dir() {
-            for entry in walkdir::WalkDir::new(path).max_depth(64) {
-                let entry = entry.map_err(|e| {
-                    WingsError::Io(std::io::Error::new(std::io::ErrorKind::Other, e))
-                })?;
-                let entry_path = entry.path();
-                let name = entry_path
-                    .strip_prefix(path.parent().unwrap_or(path))
-                    .unwrap_or(entry_path)
-                    .to_string_lossy()
-                    .to_string();
-                if entry_path.is_dir() {
-                    zip_writer.add_directory(&name, options).map_err(|e| {
-                        WingsError::Io(std::io::Error::new(std::io::ErrorKind::Other, e))
-                    })?;
-                } else {
-                    zip_writer.start_file(&name, options).map_err(|e| {
-                        WingsError::Io(std::io::Error::new(std::io::ErrorKind::Other, e))
-                    })?;
-                    let mut f = std::fs::File::open(entry_path).map_err(WingsError::Io)?;
-                    let mut buf = Vec::new();
-                    f.read_to_end(&mut buf).map_err(WingsError::Io)?;
-                    zip_writer.write_all(&buf).map_err(WingsError::Io)?;
-                }
+/// Resolve the entries to copy or move through [`validate_path`]. The server
+/// root itself is refused: its copy would land next to it, outside the root.
+pub fn validate_entries(root: &Path, paths: &[String]) -> Result<Vec<PathBuf>, WingsError> {
+    let canonical_root = root.canonicalize().map_err(WingsError::Io)?;
+    paths
+        .iter()
+        .map(|p| {
+            let path = validate_path(root, p)?;
+            if path == canonical_root {
+                return Err(WingsError::InvalidRequest(
+                    "The server root cannot be copied or moved".into(),
+                ));
             }
-        } else {
-            let name = path
-                .file_name()
-                .unwrap_or_default()
-                .to_string_lossy()
-                .to_string();
-            zip_writer.start_file(&name, options).map_err(|e| {
-                WingsError::Io(std::io::Error::new(std::io::ErrorKind::Other, e))
-            })?;
-            let mut f = std::fs::File::open(path).map_err(WingsError::Io)?;
-            let mut buf = Vec::new();
-            f.read_to_end(&mut buf).map_err(WingsError::Io)?;
-            zip_writer.write_all(&buf).map_err(WingsError::Io)?;
+            Ok(path)
+        })
+        .collect()
+}
+
+fn ensure_not_nested(source: &Path, dest_dir: &Path) -> Result<(), WingsError> {
+    if source.is_dir() && dest_dir.starts_with(source) {
+        return Err(WingsError::InvalidRequest(format!(
+            "Cannot place {} inside itself",
+            source.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Copy files or directory trees into `dest_dir` (or next to themselves
+/// when `None`), suffixing names that already exist there. Symlinks are
+/// copied as links, never followed.
+///
+/// `quota` is the server's remaining disk allowance; the copy is refused up
+/// front if the sources would not fit. Returns the paths of the new copies.
+pub fn copy_entries(
+    sources: &[PathBuf],
+    dest_dir: Option<&Path>,
+    progress: &JobProgress,
+    quota: Option<u64>,
+) -> Result<Vec<PathBuf>, WingsError> {
+    if let Some(dest_dir) = dest_dir {
+        for source in sources {
+            ensure_not_nested(source, dest_dir)?;
         }
     }
+    let total: u64 = sources.iter().map(|s| disk_usage(s)).sum();
+    if quota.is_some_and(|quota| total > quota) {
+        return Err(WingsError::QuotaExceeded);
+    }
+    progress.set_total(total);
 
-    zip_writer.finish().map_err(|e| {
-        WingsError::Io(std::io::Error::new(std::io::ErrorKind::Other, e))
-    })?;
+    let mut created = Vec::with_capacity(sources.len());
+    for source in sources {
+        let name = source.file_name().ok_or(WingsError::PathTraversal)?;
+        let dir = match dest_dir {
+            Some(dir) => dir,
+            None => source.parent().ok_or(WingsError::PathTraversal)?,
+        };
+        let target = copy_destination(dir, name, source.is_dir());
+        if let Err(e) = copy_tree(source, &target, progress) {
+            let _ = if target.is_dir() {
+                std::fs::remove_dir_all(&target)
+            } else {
+                std::fs::remove_file(&target)
+            };
+            if progress.is_cancelled() {
+                return Err(WingsError::Cancelled);
+            }
+            return Err(WingsError::Io(e));
+        }
+        created.push(target);
+    }
+    Ok(created)
+}
+
+fn copy_tree(source: &Path, target: &Path, progress: &JobProgress) -> std::io::Result<()> {
+    for entry in walkdir::WalkDir::new(source).max_depth(64) {
+        let entry = entry.map_err(std::io::Error::other)?;
+        progress.check_cancelled()?;
+        let rel = entry.path().strip_prefix(source).unwrap_or(Path::new(""));
+        let dest = target.join(rel);
+        let file_type = entry.file_type();
+        if file_type.is_dir() {
+            std::fs::create_dir_all(&dest)?;
+        } else if file_type.is_symlink() {
+            std::os::unix::fs::symlink(std::fs::read_link(entry.path())?, &dest)?;
+        } else if file_type.is_file() {
+            let mut reader = ProgressReader {
+                inner: std::fs::File::open(entry.path())?,
+                progress,
+            };
+            let mut file = std::fs::File::create(&dest)?;
+            std::io::copy(&mut reader, &mut file)?;
+            let metadata = entry.metadata().map_err(std::io::Error::other)?;
+            file.set_permissions(metadata.permissions())?;
+        }
+    }
     Ok(())
 }
 
-pub fn decompress(archive: &Path, dest: &Path) -> Result<(), WingsError> {
-    let extension = archive
-        .extension()
-        .and_then(|e| e.to_str())
-        .unwrap_or("");
-
-    match extension {
-        "zip" => {
-            let file = std::fs::File::open(archive).map_err(WingsError::Io)?;
-            let mut zip = zip::ZipArchive::new(file).map_err(|e| {
-                WingsError::Io(std::io::Error::new(std::io::ErrorKind::Other, e))
-            })?;
-            zip.extract(dest).map_err(|e| {
-                WingsError::Io(std::io::Error::new(std::io::ErrorKind::Other, e))
-            })?;
-        }
-        "gz" | "tgz" => {
-            let file = std::fs::File::open(archive).map_err(WingsError::Io)?;
-            let gz = flate2::read::GzDecoder::new(file);
-            let mut archive = tar::Archive::new(gz);
-            archive.unpack(dest).map_err(WingsError::Io)?;
-        }
-        _ => {
-            return Err(WingsError::Io(std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                format!("Unsupported archive format: {extension}"),
+/// Move `sources` into the directory `dest_dir`, keeping their names.
+/// Nothing is moved if any of them would overwrite an existing entry.
+pub fn move_entries(sources: &[PathBuf], dest_dir: &Path) -> Result<(), WingsError> {
+    if !dest_dir.is_dir() {
+        return Err(WingsError::InvalidRequest(format!(
+            "{} is not a directory",
+            dest_dir.display()
+        )));
+    }
+    let mut targets = Vec::with_capacity(sources.len());
+    for source in sources {
+        ensure_not_nested(source, dest_dir)?;
+        let name = source.file_name().ok_or(WingsError::PathTraversal)?;
+        let target = dest_dir.join(name);
+        if target.symlink_metadata().is_ok() {
+            return Err(WingsError::InvalidRequest(format!(
+                "{} already exists in the destination",
+                name.to_string_lossy()
             )));
         }
+        targets.push(target);
+    }
+    for (source, target) in sources.iter().zip(&targets) {
+        std::fs::rename(source, target).map_err(WingsError::Io)?;
     }
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::TempDir;
-
-    #[test]
-    fn test_validate_path_rejects_traversal() {
-        let dir = TempDir::new().unwrap();
-        let root = dir.path();
+/// Archive formats produced by [`compress`], chosen by destination extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    TarGz,
+    TarZst,
+}
 
-        let result = validate_path(root, "../../etc/passwd");
-        assert!(result.is_err());
+impl ArchiveFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Some(ArchiveFormat::TarZst)
+        } else {
+            None
+        }
     }
+}
 
-    #[test]
-    fn test_validate_path_accepts_valid_paths() {
-        let dir = TempDir::new().unwrap();
-        let root = dir.path();
+/// Whether `name` is one of Wings' own bookkeeping files inside a server
+/// root (stored config, temp files of atomic writes), which are hidden from
+/// watchers and never backed up.
+pub fn is_wings_file(name: &str) -> bool {
+    name.starts_with(".nexus-") || name.contains(".nexus-tmp-")
+}
 
-        // Create the file so canonicalize works
-        std::fs::write(root.join("test.txt"), "hello").unwrap();
+/// Total size in bytes of all regular files below `path`.
+pub fn disk_usage(path: &Path) -> u64 {
+    walkdir::WalkDir::new(path)
+        .max_depth(64)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.metadata().ok())
+        .filter(|m| m.is_file())
+        .map(|m| m.len())
+        .sum()
+}
 
-        let result = validate_path(root, "test.txt");
-        assert!(result.is_ok());
-        assert!(result.unwrap().ends_with("test.txt"));
+/// Reader wrapper that reports bytes read to a job and aborts once it is cancelled.
+pub struct ProgressReader<'a, R> {
+    pub inner: R,
+    pub progress: &'a JobProgress,
+}
+
+impl<R: std::io::Seek> std::io::Seek for ProgressReader<'_, R> {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
     }
+}
 
-    #[test]
-    fn test_list_directory() {
-        let dir = TempDir::new().unwrap();
-        let root = dir.path();
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.progress.check_cancelled()?;
+        let n = self.inner.read(buf)?;
+        self.progress.add_processed(n as u64);
+        Ok(n)
+    }
+}
 
-        std::fs::write(root.join("file1.txt"), "content").unwrap();
-        std::fs::write(root.join("file2.log"), "data").unwrap();
-        std::fs::create_dir(root.join("subdir")).unwrap();
+/// Writer wrapper that fails, setting `exceeded`, once more than `limit`
+/// bytes have been written.
+struct QuotaWriter<'a, W> {
+    inner: W,
+    written: u64,
+    limit: Option<u64>,
+    exceeded: &'a AtomicBool,
+}
 
-        let entries = list_directory(root).unwrap();
-        assert_eq!(entries.len(), 3);
-        // Directories should come first
-        assert!(entries[0].is_directory);
-        assert_eq!(entries[0].name, "subdir");
+impl<W: Write> Write for QuotaWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.written += n as u64;
+        if self.limit.is_some_and(|limit| self.written > limit) {
+            self.exceeded.store(true, Ordering::Relaxed);
+            return Err(std::io::Error::other("disk quota exceeded"));
+        }
+        Ok(n)
     }
 
-    #[test]
-    fn test_write_file_and_read_file() {
-        let dir = TempDir::new().unwrap();
-        let path = dir.path().join("test.txt");
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
 
-        write_file(&path, "hello world").unwrap();
-        let content = read_file(&path).unwrap();
-        assert_eq!(content, "hello world");
+impl<W: std::io::Seek> std::io::Seek for QuotaWriter<'_, W> {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
     }
+}
 
-    #[test]
-    fn test_create_directory() {
-        let dir = TempDir::new().unwrap();
-        let new_dir = dir.path().join("a/b/c");
+/// An entry to be archived: its path on disk and its name inside the archive.
+pub struct ArchiveEntry {
+    pub path: PathBuf,
+    pub name: String,
+    pub metadata: std::fs::Metadata,
+}
 
-        create_directory(&new_dir).unwrap();
-        assert!(new_dir.exists());
-        assert!(new_dir.is_dir());
+fn collect_entries(paths: &[PathBuf]) -> Result<Vec<ArchiveEntry>, WingsError> {
+    let mut entries = Vec::new();
+    for path in paths {
+        let base = path.parent().unwrap_or(path);
+        // Symlinks are archived as links, never followed
+        for entry in walkdir::WalkDir::new(path).max_depth(64) {
+            let entry = entry.map_err(|e| WingsError::Io(std::io::Error::other(e)))?;
+            let name = entry
+                .path()
+                .strip_prefix(base)
+                .unwrap_or(entry.path())
+                .to_string_lossy()
+                .to_string();
+            let metadata = entry
+                .path()
+                .symlink_metadata()
+                .map_err(WingsError::Io)?;
+            entries.push(ArchiveEntry {
+                path: entry.into_path(),
+                name,
+                metadata,
+            });
+        }
     }
+    Ok(entries)
+}
 
-    #[test]
-    fn test_validate_path_rejects_null_bytes() {
-        let dir = TempDir::new().unwrap();
-        let root = dir.path();
-        let result = validate_path(root, "test\0.txt");
-        assert!(result.is_err());
+/// Stream `paths` into a new archive at `dest`, in the format given by its
+/// extension. File data is copied in chunks, never buffered whole.
+///
+/// The archive is built in a temporary file and only renamed into place on
+/// success. `quota` caps the archive size in bytes (the server's remaining
+/// disk allowance); the job is aborted once it is exceeded or cancelled.
+pub fn compress(
+    paths: &[PathBuf],
+    dest: &Path,
+    progress: &JobProgress,
+    quota: Option<u64>,
+) -> Result<(), WingsError> {
+    let format = ArchiveFormat::from_path(dest).ok_or_else(|| {
+        WingsError::InvalidRequest(
+            "Unsupported archive format, use .zip, .tar.gz or .tar.zst".into(),
+        )
+    })?;
+
+    let entries = collect_entries(paths)?;
+    progress.set_total(
+        entries
+            .iter()
+            .filter(|e| e.metadata.is_file())
+            .map(|e| e.metadata.len())
+            .sum(),
+    );
+
+    let parent = dest.parent().ok_or(WingsError::PathTraversal)?;
+    let file_name = dest.file_name().ok_or(WingsError::PathTraversal)?;
+    let tmp_path = parent.join(format!(
+        ".{}.nexus-tmp-{}",
+        file_name.to_string_lossy(),
+        uuid::Uuid::new_v4().simple()
+    ));
+
+    let quota_exceeded = AtomicBool::new(false);
+    let result = std::fs::File::create(&tmp_path)
+        .and_then(|file| {
+            let writer = QuotaWriter {
+                inner: std::io::BufWriter::new(file),
+                written: 0,
+                limit: quota,
+                exceeded: &quota_exceeded,
+            };
+            match format {
+                ArchiveFormat::Zip => write_zip(writer, &entries, progress),
+                ArchiveFormat::TarGz => {
+                    let encoder =
+                        flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+                    write_tar(encoder, &entries, progress)?.finish()?.flush()
+                }
+                ArchiveFormat::TarZst => {
+                    let encoder = zstd::stream::write::Encoder::new(writer, 0)?;
+                    write_tar(encoder, &entries, progress)?.finish()?.flush()
+                }
+            }
+        })
+        .and_then(|()| std::fs::rename(&tmp_path, dest));
+
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&tmp_path);
+        if progress.is_cancelled() {
+            return Err(WingsError::Cancelled);
+        }
+        if quota_exceeded.load(Ordering::Relaxed) {
+            return Err(WingsError::QuotaExceeded);
+        }
+        return Err(WingsError::Io(e));
     }
+    Ok(())
+}
 
-    #[test]
-    fn test_validate_path_rejects_nested_traversal() {
-        let dir = TempDir::new().unwrap();
-        let root = dir.path();
-        let result = validate_path(root, "valid/../../etc/passwd");
-        assert!(result.is_err());
+/// Append `entries` to a tar stream written to `writer`, returning the
+/// writer once the archive is finished.
+pub fn write_tar<W: Write>(
+    writer: W,
+    entries: &[ArchiveEntry],
+    progress: &JobProgress,
+) -> std::io::Result<W> {
+    let mut builder = tar::Builder::new(writer);
+    builder.follow_symlinks(false);
+    for entry in entries {
+        progress.check_cancelled()?;
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata(&entry.metadata);
+        if entry.metadata.is_file() {
+            let file = std::fs::File::open(&entry.path)?;
+            let reader = ProgressReader { inner: file, progress };
+            builder.append_data(&mut header, &entry.name, reader)?;
+        } else if entry.metadata.is_symlink() {
+            let target = std::fs::read_link(&entry.path)?;
+            builder.append_link(&mut header, &entry.name, target)?;
+        } else if entry.metadata.is_dir() {
+            builder.append_data(&mut header, &entry.name, std::io::empty())?;
+        }
     }
+    builder.into_inner()
+}
 
-    #[test]
-    fn test_validate_path_rejects_absolute_path() {
-        let dir = TempDir::new().unwrap();
-        let root = dir.path();
-        // After stripping leading slash, /etc/passwd becomes etc/passwd which is valid
-        // But we should test that traversal via absolute is rejected
-        let result = validate_path(root, "../../../etc/passwd");
-        assert!(result.is_err());
+fn write_zip<W: Write + std::io::Seek>(
+    writer: W,
+    entries: &[ArchiveEntry],
+    progress: &JobProgress,
+) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut zip_writer = zip::ZipWriter::new(writer);
+    for entry in entries {
+        progress.check_cancelled()?;
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .unix_permissions(entry.metadata.permissions().mode())
+            .large_file(entry.metadata.len() >= u32::MAX as u64);
+        if entry.metadata.is_file() {
+            zip_writer.start_file(&entry.name, options)?;
+            let mut reader = ProgressReader {
+                inner: std::fs::File::open(&entry.path)?,
+                progress,
+            };
+            std::io::copy(&mut reader, &mut zip_writer)?;
+        } else if entry.metadata.is_symlink() {
+            let target = std::fs::read_link(&entry.path)?;
+            zip_writer.add_symlink(&entry.name, target.to_string_lossy(), options)?;
+        } else if entry.metadata.is_dir() {
+            zip_writer.add_directory(&entry.name, options)?;
+        }
+    }
+    zip_writer.finish()?.flush()
+}
+
+/// Limits enforced while extracting an archive, to stop zip bombs.
+#[derive(Debug, Clone)]
+pub struct ExtractLimits {
+    /// Maximum total bytes extracted
+    pub max_size: u64,
+    /// Maximum number of entries
+    pub max_entries: u64,
+    /// Maximum ratio of extracted bytes to archive size, checked once
+    /// more than `ratio_grace` bytes have been extracted
+    pub max_ratio: u64,
+    pub ratio_grace: u64,
+    /// Remaining disk allowance of the server, if it has a limit
+    pub quota: Option<u64>,
+}
+
+impl Default for ExtractLimits {
+    fn default() -> Self {
+        Self {
+            max_size: 50 * 1024 * 1024 * 1024, // 50GB
+            max_entries: 100_000,
+            max_ratio: 200,
+            ratio_grace: 64 * 1024 * 1024,
+            quota: None,
+        }
+    }
+}
+
+/// Stream compression wrapped around an archive or a single file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+}
+
+/// Archive types accepted by [`decompress`], detected from magic bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar(Compression),
+    SevenZ,
+    Rar,
+    /// A single compressed file such as `latest.log.gz`
+    Compressed(Compression),
+}
+
+fn decoder<'a, R: Read + 'a>(
+    reader: R,
+    compression: Compression,
+) -> std::io::Result<Box<dyn Read + 'a>> {
+    let reader = std::io::BufReader::new(reader);
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(reader)),
+        Compression::Bzip2 => Box::new(bzip2::read::MultiBzDecoder::new(reader)),
+        Compression::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(reader)),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(reader)?),
+    })
+}
+
+fn is_tar_header(block: &[u8]) -> bool {
+    block.len() >= 262 && &block[257..262] == b"ustar"
+}
+
+/// Read up to `buf.len()` bytes, stopping early only at end of stream.
+fn read_up_to(reader: &mut impl Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+/// Detect the archive type of `path` from its leading bytes.
+pub fn detect_archive(path: &Path) -> Result<ArchiveKind, WingsError> {
+    let mut header = [0u8; 512];
+    let mut file = std::fs::File::open(path).map_err(WingsError::Io)?;
+    let len = read_up_to(&mut file, &mut header).map_err(WingsError::Io)?;
+    let header = &header[..len];
+
+    let compression = if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+        return Ok(ArchiveKind::Zip);
+    } else if header.starts_with(&[0x37, 0x7A, 0xBC, 0xAF, 0x27, 0x1C]) {
+        return Ok(ArchiveKind::SevenZ);
+    } else if header.starts_with(b"Rar!\x1a\x07") {
+        return Ok(ArchiveKind::Rar);
+    } else if is_tar_header(header) {
+        return Ok(ArchiveKind::Tar(Compression::None));
+    } else if header.starts_with(&[0x1F, 0x8B]) {
+        Compression::Gzip
+    } else if header.starts_with(b"BZh") {
+        Compression::Bzip2
+    } else if header.starts_with(&[0xFD, 0x37, 0x7A, 0x58, 0x5A, 0x00]) {
+        Compression::Xz
+    } else if header.starts_with(&[0x28, 0xB5, 0x2F, 0xFD]) {
+        Compression::Zstd
+    } else {
+        return Err(WingsError::ArchiveRejected("Unsupported archive format".into()));
+    };
+
+    // Peek into the compressed stream to tell a tarball from a single file
+    let file = std::fs::File::open(path).map_err(WingsError::Io)?;
+    let mut block = [0u8; 512];
+    let len = read_up_to(&mut decoder(file, compression).map_err(WingsError::Io)?, &mut block)
+        .map_err(WingsError::Io)?;
+    if is_tar_header(&block[..len]) {
+        Ok(ArchiveKind::Tar(compression))
+    } else {
+        Ok(ArchiveKind::Compressed(compression))
+    }
+}
+
+/// Writes archive entries below `dest`, resolving every entry path through
+/// [`validate_path`] and enforcing [`ExtractLimits`].
+struct Extractor<'a> {
+    root: &'a Path,
+    canonical_root: PathBuf,
+    dest_rel: PathBuf,
+    progress: &'a JobProgress,
+    limits: &'a ExtractLimits,
+    archive_size: u64,
+    written: u64,
+    entries: u64,
+    /// Paths created by this extraction, removed again if it fails
+    created: Vec<PathBuf>,
+    created_set: HashSet<PathBuf>,
+    /// Existing files an entry replaced, and where they were moved to
+    replaced: Vec<(PathBuf, PathBuf)>,
+    /// Hidden directory in the server root holding replaced files and
+    /// staged RAR contents until the extraction ends
+    work_dir: Option<PathBuf>,
+}
+
+impl<'a> Extractor<'a> {
+    fn new(
+        root: &'a Path,
+        dest: &Path,
+        progress: &'a JobProgress,
+        limits: &'a ExtractLimits,
+        archive_size: u64,
+    ) -> Result<Self, WingsError> {
+        let canonical_root = root.canonicalize().map_err(WingsError::Io)?;
+        let dest_rel = dest
+            .strip_prefix(&canonical_root)
+            .map_err(|_| WingsError::PathTraversal)?
+            .to_path_buf();
+        Ok(Self {
+            root,
+            canonical_root,
+            dest_rel,
+            progress,
+            limits,
+            archive_size,
+            written: 0,
+            entries: 0,
+            created: Vec::new(),
+            created_set: HashSet::new(),
+            replaced: Vec::new(),
+            work_dir: None,
+        })
+    }
+
+    /// Resolve an entry name to a path inside the server root. A link already
+    /// at that path is not followed: the entry replaces it.
+    fn target(&self, name: &str) -> Result<PathBuf, WingsError> {
+        let name = name.replace('\\', "/");
+        let requested = self.dest_rel.join(name.trim_start_matches('/'));
+        let path = validate_path(self.root, &requested.to_string_lossy())?;
+        match (requested.parent(), requested.file_name()) {
+            (Some(parent), Some(file_name)) => {
+                Ok(validate_path(self.root, &parent.to_string_lossy())?.join(file_name))
+            }
+            _ => Ok(path),
+        }
+    }
+
+    fn begin_entry(&mut self) -> Result<(), WingsError> {
+        if self.progress.is_cancelled() {
+            return Err(WingsError::Cancelled);
+        }
+        self.entries += 1;
+        if self.entries > self.limits.max_entries {
+            return Err(WingsError::ArchiveRejected(format!(
+                "Archive has more than {} entries",
+                self.limits.max_entries
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check `size` extracted bytes against the limits.
+    fn check_size(&self, size: u64) -> Result<(), WingsError> {
+        if self.limits.quota.is_some_and(|quota| size > quota) {
+            return Err(WingsError::QuotaExceeded);
+        }
+        if size > self.limits.max_size {
+            return Err(WingsError::ArchiveRejected(format!(
+                "Archive expands to more than {} bytes",
+                self.limits.max_size
+            )));
+        }
+        if size > self.limits.ratio_grace
+            && size > self.archive_size.saturating_mul(self.limits.max_ratio)
+        {
+            return Err(WingsError::ArchiveRejected(
+                "Archive compression ratio is suspiciously high".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn create_dir(&mut self, path: &Path) -> Result<(), WingsError> {
+        let mut missing = Vec::new();
+        let mut current = Some(path);
+        while let Some(dir) = current {
+            if dir.exists() {
+                break;
+            }
+            missing.push(dir.to_path_buf());
+            current = dir.parent();
+        }
+        std::fs::create_dir_all(path).map_err(WingsError::Io)?;
+        for dir in missing.into_iter().rev() {
+            self.created_set.insert(dir.clone());
+            self.created.push(dir);
+        }
+        Ok(())
+    }
+
+    fn work_dir(&mut self) -> Result<PathBuf, WingsError> {
+        if let Some(dir) = &self.work_dir {
+            return Ok(dir.clone());
+        }
+        let dir = self
+            .canonical_root
+            .join(format!(".nexus-extract-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir(&dir).map_err(WingsError::Io)?;
+        self.work_dir = Some(dir.clone());
+        Ok(dir)
+    }
+
+    /// Make room for an entry at `path`. A file or link that was there before
+    /// the extraction is moved aside so it can be put back on rollback.
+    fn make_room(&mut self, path: &Path) -> Result<(), WingsError> {
+        let metadata = match path.symlink_metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => {
+                self.created_set.insert(path.to_path_buf());
+                self.created.push(path.to_path_buf());
+                return Ok(());
+            }
+        };
+        if metadata.is_dir() {
+            return Err(WingsError::ArchiveRejected(format!(
+                "{} would replace a directory",
+                path.display()
+            )));
+        }
+        if self.created_set.contains(path) {
+            // Written earlier by this archive
+            return std::fs::remove_file(path).map_err(WingsError::Io);
+        }
+        let backup = self.work_dir()?.join(self.replaced.len().to_string());
+        std::fs::rename(path, &backup).map_err(WingsError::Io)?;
+        self.replaced.push((path.to_path_buf(), backup));
+        self.created_set.insert(path.to_path_buf());
+        self.created.push(path.to_path_buf());
+        Ok(())
+    }
+
+    fn dir(&mut self, name: &str) -> Result<(), WingsError> {
+        self.begin_entry()?;
+        let path = self.target(name)?;
+        self.create_dir(&path)
+    }
+
+    fn file(
+        &mut self,
+        name: &str,
+        reader: &mut dyn Read,
+        mode: Option<u32>,
+    ) -> Result<(), WingsError> {
+        use std::os::unix::fs::PermissionsExt;
+
+        self.begin_entry()?;
+        let path = self.target(name)?;
+        if let Some(parent) = path.parent() {
+            self.create_dir(parent)?;
+        }
+        self.make_room(&path)?;
+
+        let mut file = std::fs::File::create(&path).map_err(WingsError::Io)?;
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(_) if self.progress.is_cancelled() => return Err(WingsError::Cancelled),
+                Err(e) => return Err(WingsError::Io(e)),
+            };
+            self.written += n as u64;
+            self.check_size(self.written)?;
+            file.write_all(&buf[..n]).map_err(WingsError::Io)?;
+        }
+        if let Some(mode) = mode {
+            // Never restore setuid/setgid/sticky bits from an archive
+            file.set_permissions(std::fs::Permissions::from_mode(mode & 0o777))
+                .map_err(WingsError::Io)?;
+        }
+        Ok(())
+    }
+
+    /// Create a symlink, rejecting targets that resolve outside the server root.
+    fn symlink(&mut self, name: &str, target: &Path) -> Result<(), WingsError> {
+        self.begin_entry()?;
+        let path = self.target(name)?;
+        if let Some(parent) = path.parent() {
+            self.create_dir(parent)?;
+        }
+        self.check_link_target(&path, target)?;
+        // Replacing a link is fine, e.g. when restoring over an existing tree
+        if path.symlink_metadata().is_ok_and(|m| !m.is_symlink()) {
+            return Err(WingsError::ArchiveRejected(format!(
+                "Symlink {name} would replace an existing file"
+            )));
+        }
+        self.make_room(&path)?;
+        std::os::unix::fs::symlink(target, &path).map_err(WingsError::Io)?;
+        Ok(())
+    }
+
+    /// Resolve `target` the way the kernel will for a link at `path`: from
+    /// the real directory of the link, following links already on disk at
+    /// every step. Each step must stay inside the server root, so a target
+    /// can't escape through an earlier entry such as `d -> .`.
+    fn check_link_target(&self, path: &Path, target: &Path) -> Result<(), WingsError> {
+        use std::path::Component;
+
+        let parent = path.parent().ok_or(WingsError::PathTraversal)?;
+        let mut resolved = parent.canonicalize().map_err(WingsError::Io)?;
+        if !resolved.starts_with(&self.canonical_root) {
+            return Err(WingsError::PathTraversal);
+        }
+        for component in target.components() {
+            match component {
+                Component::Normal(part) => {
+                    resolved.push(part);
+                    if let Ok(real) = resolved.canonicalize() {
+                        resolved = real;
+                    }
+                }
+                Component::ParentDir => {
+                    resolved.pop();
+                }
+                Component::CurDir => {}
+                _ => return Err(WingsError::PathTraversal),
+            }
+            if !resolved.starts_with(&self.canonical_root) {
+                return Err(WingsError::PathTraversal);
+            }
+        }
+        Ok(())
+    }
+
+    fn hard_link(&mut self, name: &str, target: &str) -> Result<(), WingsError> {
+        self.begin_entry()?;
+        let path = self.target(name)?;
+        let source = self.target(target)?;
+        if let Some(parent) = path.parent() {
+            self.create_dir(parent)?;
+        }
+        self.make_room(&path)?;
+        std::fs::hard_link(source, &path).map_err(WingsError::Io)
+    }
+
+    /// Remove everything this extraction created, deepest paths first, and
+    /// put back the files it replaced.
+    fn rollback(&self) {
+        for path in self.created.iter().rev() {
+            match path.symlink_metadata() {
+                Ok(m) if m.is_dir() => {
+                    let _ = std::fs::remove_dir(path);
+                }
+                Ok(_) => {
+                    let _ = std::fs::remove_file(path);
+                }
+                Err(_) => {}
+            }
+        }
+        for (path, backup) in self.replaced.iter().rev() {
+            if let Err(e) = std::fs::rename(backup, path) {
+                tracing::warn!(path = %path.display(), error = %e, "Failed to restore replaced file");
+            }
+        }
+    }
+
+    /// Keep the extracted files if `result` is a success, otherwise undo the
+    /// extraction.
+    fn finish(self, result: Result<(), WingsError>) -> Result<(), WingsError> {
+        let result = match result {
+            Err(_) if self.progress.is_cancelled() => Err(WingsError::Cancelled),
+            result => result,
+        };
+        if result.is_err() {
+            self.rollback();
+        }
+        if let Some(dir) = &self.work_dir {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+        result
+    }
+}
+
+/// Safely extract `archive` into `dest`, both inside `root`.
+///
+/// The format is detected from magic bytes. Every entry is resolved through
+/// [`validate_path`], symlinks escaping the root are rejected and `limits`
+/// are enforced while data is written. On failure, everything created by
+/// the extraction is removed again and replaced files are restored.
+pub fn decompress(
+    root: &Path,
+    archive: &Path,
+    dest: &Path,
+    progress: &JobProgress,
+    limits: &ExtractLimits,
+) -> Result<(), WingsError> {
+    let kind = detect_archive(archive)?;
+    let archive_size = std::fs::metadata(archive).map_err(WingsError::Io)?.len();
+    progress.set_total(archive_size);
+
+    let mut extractor = Extractor::new(root, dest, progress, limits, archive_size)?;
+    let dest_path = extractor.target("")?;
+    extractor.create_dir(&dest_path)?;
+
+    let result = match kind {
+        ArchiveKind::Zip => extract_zip(&mut extractor, archive),
+        ArchiveKind::Tar(compression) => extract_tar(&mut extractor, archive, compression),
+        ArchiveKind::SevenZ => extract_7z(&mut extractor, archive),
+        ArchiveKind::Rar => extract_rar(&mut extractor, archive),
+        ArchiveKind::Compressed(compression) => {
+            extract_single(&mut extractor, archive, compression)
+        }
+    };
+    extractor.finish(result)
+}
+
+/// Extract a tar stream that does not come from a local file, such as a
+/// download, into `dest` inside `root`.
+///
+/// `size` is the expected length of the stream, used for progress. The
+/// reader is always read to its end, so a wrapper that validates the data
+/// once exhausted (e.g. against a checksum) can still fail the extraction,
+/// which is then rolled back like any other error.
+pub fn decompress_tar_stream(
+    root: &Path,
+    reader: impl Read,
+    dest: &Path,
+    compression: Compression,
+    size: u64,
+    progress: &JobProgress,
+    limits: &ExtractLimits,
+) -> Result<(), WingsError> {
+    progress.set_total(size);
+    let mut extractor = Extractor::new(root, dest, progress, limits, size)?;
+    let dest_path = extractor.target("")?;
+    extractor.create_dir(&dest_path)?;
+
+    let result = extract_tar_from(&mut extractor, reader, compression);
+    extractor.finish(result)
+}
+
+fn extract_tar(
+    extractor: &mut Extractor,
+    archive: &Path,
+    compression: Compression,
+) -> Result<(), WingsError> {
+    let file = std::fs::File::open(archive).map_err(WingsError::Io)?;
+    extract_tar_from(extractor, file, compression)
+}
+
+fn extract_tar_from(
+    extractor: &mut Extractor,
+    reader: impl Read,
+    compression: Compression,
+) -> Result<(), WingsError> {
+    let reader = ProgressReader {
+        inner: reader,
+        progress: extractor.progress,
+    };
+    let mut tar = tar::Archive::new(decoder(reader, compression).map_err(WingsError::Io)?);
+
+    for entry in tar.entries().map_err(WingsError::Io)? {
+        let mut entry = entry.map_err(WingsError::Io)?;
+        let name = String::from_utf8_lossy(&entry.path_bytes()).to_string();
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_dir() {
+            extractor.dir(&name)?;
+        } else if entry_type.is_file() || entry_type.is_contiguous() {
+            let mode = entry.header().mode().ok();
+            extractor.file(&name, &mut entry, mode)?;
+        } else if entry_type.is_symlink() || entry_type.is_hard_link() {
+            let target = entry
+                .link_name()
+                .map_err(WingsError::Io)?
+                .ok_or_else(|| WingsError::ArchiveRejected(format!("Link {name} has no target")))?
+                .into_owned();
+            if entry_type.is_symlink() {
+                extractor.symlink(&name, &target)?;
+            } else {
+                extractor.hard_link(&name, &target.to_string_lossy())?;
+            }
+        }
+        // Device nodes, FIFOs and metadata entries are skipped
+    }
+    // Consume the trailing padding too, see decompress_tar_stream
+    std::io::copy(&mut tar.into_inner(), &mut std::io::sink()).map_err(WingsError::Io)?;
+    Ok(())
+}
+
+fn extract_zip(extractor: &mut Extractor, archive: &Path) -> Result<(), WingsError> {
+    let file = std::fs::File::open(archive).map_err(WingsError::Io)?;
+    let mut zip = zip::ZipArchive::new(file)
+        .map_err(|e| WingsError::ArchiveRejected(format!("Invalid zip archive: {e}")))?;
+
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i).map_err(|e| WingsError::Io(e.into()))?;
+        let name = entry.name().to_string();
+        let compressed = entry.compressed_size();
+        if entry.is_dir() {
+            extractor.dir(&name)?;
+        } else if entry.is_symlink() {
+            let mut target = String::new();
+            (&mut entry)
+                .take(4096)
+                .read_to_string(&mut target)
+                .map_err(WingsError::Io)?;
+            extractor.symlink(&name, Path::new(&target))?;
+        } else {
+            let mode = entry.unix_mode();
+            extractor.file(&name, &mut entry, mode)?;
+        }
+        extractor.progress.add_processed(compressed);
+    }
+    Ok(())
+}
+
+fn extract_7z(extractor: &mut Extractor, archive: &Path) -> Result<(), WingsError> {
+    let file = std::fs::File::open(archive).map_err(WingsError::Io)?;
+    let len = file.metadata().map_err(WingsError::Io)?.len();
+    let reader = ProgressReader {
+        inner: file,
+        progress: extractor.progress,
+    };
+    let mut sevenz = sevenz_rust::SevenZReader::new(reader, len, sevenz_rust::Password::empty())
+        .map_err(|e| WingsError::ArchiveRejected(format!("Invalid 7z archive: {e}")))?;
+
+    // The callback can only return 7z errors, so ours are carried out here
+    let mut failure = None;
+    let result = sevenz.for_each_entries(|entry, reader| {
+        let outcome = if entry.is_anti_item() {
+            Ok(())
+        } else if entry.is_directory() {
+            extractor.dir(entry.name())
+        } else {
+            extractor.file(entry.name(), reader, None)
+        };
+        match outcome {
+            Ok(()) => Ok(true),
+            Err(e) => {
+                failure = Some(e);
+                Ok(false)
+            }
+        }
+    });
+    if let Some(e) = failure {
+        return Err(e);
+    }
+    result.map_err(|e| WingsError::ArchiveRejected(format!("Invalid 7z archive: {e}")))
+}
+
+/// Sizes of the entries in `unrar lt` output, 0 for entries without one
+/// such as directories.
+fn rar_entry_sizes(listing: &str) -> Vec<u64> {
+    let mut sizes = Vec::new();
+    for line in listing.lines().map(str::trim) {
+        if line.starts_with("Name: ") {
+            sizes.push(0);
+        } else if let (Some(size), Some(last)) = (line.strip_prefix("Size: "), sizes.last_mut()) {
+            *last = size.trim().parse().unwrap_or(0);
+        }
+    }
+    sizes
+}
+
+fn unrar<S: AsRef<std::ffi::OsStr>>(args: &[S]) -> Result<String, WingsError> {
+    let output = std::process::Command::new("unrar")
+        .args(args)
+        .output()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => WingsError::ArchiveRejected(
+                "RAR extraction requires unrar to be installed on the node".into(),
+            ),
+            _ => WingsError::Io(e),
+        })?;
+    if !output.status.success() {
+        return Err(WingsError::ArchiveRejected(format!(
+            "unrar failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// RAR has no streaming decoder available, so archives are unpacked with the
+/// node's `unrar`. The listing is checked against the limits first, then the
+/// archive is unpacked into the extraction's work directory, inside the
+/// server root, and copied in entry by entry through the usual checks.
+fn extract_rar(extractor: &mut Extractor, archive: &Path) -> Result<(), WingsError> {
+    let listing = unrar(&["lt".as_ref(), "-p-".as_ref(), archive.as_os_str()])?;
+    let sizes = rar_entry_sizes(&listing);
+    if sizes.len() as u64 > extractor.limits.max_entries {
+        return Err(WingsError::ArchiveRejected(format!(
+            "Archive has more than {} entries",
+            extractor.limits.max_entries
+        )));
+    }
+    extractor.check_size(sizes.iter().sum())?;
+
+    // Removed with the work directory once the extraction ends
+    let staging = extractor.work_dir()?.join("rar");
+    std::fs::create_dir(&staging).map_err(WingsError::Io)?;
+    let dest = staging.join("");
+    unrar(&[
+        "x".as_ref(),
+        "-y".as_ref(),
+        "-p-".as_ref(),
+        "-idq".as_ref(),
+        archive.as_os_str(),
+        dest.as_os_str(),
+    ])?;
+    extractor.progress.add_processed(extractor.archive_size);
+
+    for entry in walkdir::WalkDir::new(&staging).min_depth(1).max_depth(64) {
+        let entry = entry.map_err(|e| WingsError::Io(std::io::Error::other(e)))?;
+        let name = entry
+            .path()
+            .strip_prefix(&staging)
+            .map_err(|_| WingsError::PathTraversal)?
+            .to_string_lossy()
+            .to_string();
+        let file_type = entry.file_type();
+        if file_type.is_dir() {
+            extractor.dir(&name)?;
+        } else if file_type.is_symlink() {
+            let target = std::fs::read_link(entry.path()).map_err(WingsError::Io)?;
+            extractor.symlink(&name, &target)?;
+        } else if file_type.is_file() {
+            let mut file = std::fs::File::open(entry.path()).map_err(WingsError::Io)?;
+            extractor.file(&name, &mut file, None)?;
+        }
+    }
+    Ok(())
+}
+
+/// Name of the file a single compressed file expands to: its name without
+/// the compression's extension, like `gunzip` would pick, or with a
+/// suffix when there is no such extension to drop.
+fn single_file_name(archive: &Path, compression: Compression) -> Result<String, WingsError> {
+    let name = archive
+        .file_name()
+        .ok_or(WingsError::PathTraversal)?
+        .to_string_lossy()
+        .to_string();
+    let extensions: &[&str] = match compression {
+        Compression::Gzip => &[".gz", ".gzip"],
+        Compression::Bzip2 => &[".bz2", ".bzip2"],
+        Compression::Xz => &[".xz"],
+        Compression::Zstd => &[".zst", ".zstd"],
+        Compression::None => &[],
+    };
+    let stem = extensions.iter().find_map(|ext| {
+        name.len()
+            .checked_sub(ext.len())
+            .filter(|&at| at > 0 && name.is_char_boundary(at))
+            .filter(|&at| name[at..].eq_ignore_ascii_case(ext))
+            .map(|at| name[..at].to_string())
+    });
+    Ok(stem.unwrap_or_else(|| format!("{name}.out")))
+}
+
+fn extract_single(
+    extractor: &mut Extractor,
+    archive: &Path,
+    compression: Compression,
+) -> Result<(), WingsError> {
+    let name = single_file_name(archive, compression)?;
+    let file = std::fs::File::open(archive).map_err(WingsError::Io)?;
+    let reader = ProgressReader {
+        inner: file,
+        progress: extractor.progress,
+    };
+    let mut decoded = decoder(reader, compression).map_err(WingsError::Io)?;
+    extractor.file(&name, &mut decoded, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_validate_path_rejects_traversal() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+
+        let result = validate_path(root, "../../etc/passwd");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_validate_path_accepts_valid_paths() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+
+        // Create the file so canonicalize works
+        std::fs::write(root.join("test.txt"), "hello").unwrap();
+
+        let result = validate_path(root, "test.txt");
+        assert!(result.is_ok());
+        assert!(result.unwrap().ends_with("test.txt"));
+    }
+
+    #[test]
+    fn test_list_directory() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+
+        std::fs::write(root.join("file1.txt"), "content").unwrap();
+        std::fs::write(root.join("file2.log"), "data").unwrap();
+        std::fs::create_dir(root.join("subdir")).unwrap();
+
+        let entries = list_directory(root, &ListOptions::default()).unwrap().entries;
+        assert_eq!(entries.len(), 3);
+        // Directories should come first
+        assert!(entries[0].is_directory);
+        assert_eq!(entries[0].name, "subdir");
+    }
+
+    #[test]
+    fn test_write_file_and_read_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test.txt");
+
+        write_file(&path, b"hello world").unwrap();
+        match read_file(&path).unwrap() {
+            FileContents::Text { encoding, content } => {
+                assert_eq!(encoding, TextEncoding::Utf8);
+                assert_eq!(content, "hello world");
+            }
+            FileContents::Binary(_) => panic!("expected text"),
+        }
+    }
+
+    #[test]
+    fn test_read_file_detects_binary() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("level.dat");
+        let data = vec![0x1f, 0x8b, 0x08, 0x00, 0x00, 0xff, 0x10];
+
+        write_file(&path, &data).unwrap();
+        match read_file(&path).unwrap() {
+            FileContents::Binary(bytes) => assert_eq!(bytes, data),
+            FileContents::Text { .. } => panic!("expected binary"),
+        }
+    }
+
+    #[test]
+    fn test_latin1_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("server.properties");
+        // "motd=Café" in ISO-8859-1
+        let original = b"motd=Caf\xe9\n".to_vec();
+        std::fs::write(&path, &original).unwrap();
+
+        let FileContents::Text { encoding, content } = read_file(&path).unwrap() else {
+            panic!("expected text");
+        };
+        assert_eq!(encoding, TextEncoding::Windows1252);
+        assert_eq!(content, "motd=Café\n");
+
+        write_file(&path, &encoding.encode(&content).unwrap()).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), original);
+    }
+
+    #[test]
+    fn test_detect_utf16_bom() {
+        let bytes = TextEncoding::Utf16Le.encode("hi").unwrap();
+        assert_eq!(detect_encoding(&bytes), Some(TextEncoding::Utf16Le));
+        assert_eq!(TextEncoding::Utf16Le.decode(&bytes), "hi");
+    }
+
+    #[test]
+    fn test_write_file_replaces_atomically() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("config.yml");
+        std::fs::write(&path, "old").unwrap();
+
+        write_file(&path, b"new").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        // No temporary files are left next to the target
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_create_directory() {
+        let dir = TempDir::new().unwrap();
+        let new_dir = dir.path().join("a/b/c");
+
+        create_directory(&new_dir, None).unwrap();
+        assert!(new_dir.exists());
+        assert!(new_dir.is_dir());
+    }
+
+    #[test]
+    fn test_create_directory_chowns_created_parents() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("a")).unwrap();
+        let existing = std::fs::metadata(dir.path().join("a")).unwrap();
+        // Only root may give files away; anyone else chowns to themselves
+        let uid = if existing.uid() == 0 { 4242 } else { existing.uid() };
+        let owner = ContainerUser { uid, gid: uid };
+
+        create_directory(&dir.path().join("a/b/c"), Some(owner)).unwrap();
+        for created in ["a/b", "a/b/c"] {
+            let meta = std::fs::metadata(dir.path().join(created)).unwrap();
+            assert_eq!((meta.uid(), meta.gid()), (uid, uid), "{created}");
+        }
+        let meta = std::fs::metadata(dir.path().join("a")).unwrap();
+        assert_eq!((meta.uid(), meta.gid()), (existing.uid(), existing.gid()));
+    }
+
+    #[test]
+    fn test_validate_path_rejects_null_bytes() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        let result = validate_path(root, "test\0.txt");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_validate_path_rejects_nested_traversal() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        let result = validate_path(root, "valid/../../etc/passwd");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_validate_path_rejects_absolute_path() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        // After stripping leading slash, /etc/passwd becomes etc/passwd which is valid
+        // But we should test that traversal via absolute is rejected
+        let result = validate_path(root, "../../../etc/passwd");
+        assert!(result.is_err());
+    }
+
+    fn sample_tree(root: &Path) -> PathBuf {
+        let world = root.join("world");
+        std::fs::create_dir_all(world.join("region")).unwrap();
+        std::fs::write(world.join("level.dat"), vec![7u8; 4096]).unwrap();
+        std::fs::write(world.join("region/r.0.0.mca"), "region data").unwrap();
+        world
+    }
+
+    #[test]
+    fn test_archive_format_from_path() {
+        assert_eq!(ArchiveFormat::from_path(Path::new("a.zip")), Some(ArchiveFormat::Zip));
+        assert_eq!(ArchiveFormat::from_path(Path::new("a.tar.gz")), Some(ArchiveFormat::TarGz));
+        assert_eq!(ArchiveFormat::from_path(Path::new("a.TGZ")), Some(ArchiveFormat::TarGz));
+        assert_eq!(ArchiveFormat::from_path(Path::new("a.tar.zst")), Some(ArchiveFormat::TarZst));
+        assert_eq!(ArchiveFormat::from_path(Path::new("a.rar")), None);
+    }
+
+    #[test]
+    fn test_compress_tar_zst_streams_all_files() {
+        let dir = TempDir::new().unwrap();
+        let world = sample_tree(dir.path());
+        let dest = dir.path().join("world.tar.zst");
+        let progress = JobProgress::default();
+
+        compress(&[world], &dest, &progress, None).unwrap();
+        assert_eq!(progress.total(), 4096 + 11);
+        assert_eq!(progress.processed(), progress.total());
+
+        let file = std::fs::File::open(&dest).unwrap();
+        let mut archive = tar::Archive::new(zstd::stream::read::Decoder::new(file).unwrap());
+        let mut names: Vec<String> = archive
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["world", "world/level.dat", "world/region", "world/region/r.0.0.mca"]);
+    }
+
+    #[test]
+    fn test_compress_zip() {
+        let dir = TempDir::new().unwrap();
+        let world = sample_tree(dir.path());
+        let dest = dir.path().join("world.zip");
+
+        compress(&[world], &dest, &JobProgress::default(), None).unwrap();
+        let mut zip = zip::ZipArchive::new(std::fs::File::open(&dest).unwrap()).unwrap();
+        let mut level = Vec::new();
+        zip.by_name("world/level.dat").unwrap().read_to_end(&mut level).unwrap();
+        assert_eq!(level, vec![7u8; 4096]);
+    }
+
+    #[test]
+    fn test_compress_cancelled_removes_partial_archive() {
+        let dir = TempDir::new().unwrap();
+        let world = sample_tree(dir.path());
+        let dest = dir.path().join("world.tar.gz");
+        let progress = JobProgress::default();
+        progress.cancel();
+
+        let result = compress(&[world], &dest, &progress, None);
+        assert!(matches!(result, Err(WingsError::Cancelled)));
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_compress_respects_quota() {
+        let dir = TempDir::new().unwrap();
+        let world = sample_tree(dir.path());
+        let dest = dir.path().join("world.zip");
+
+        let result = compress(&[world], &dest, &JobProgress::default(), Some(64));
+        assert!(matches!(result, Err(WingsError::QuotaExceeded)));
+        assert!(!dest.exists());
+    }
+
+    fn tar_with(build: impl FnOnce(&mut tar::Builder<Vec<u8>>)) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        build(&mut builder);
+        builder.into_inner().unwrap()
+    }
+
+    fn append_file(builder: &mut tar::Builder<Vec<u8>>, name: &str, data: &[u8]) {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, name, data).unwrap();
+    }
+
+    fn extract(root: &Path, archive: &Path, limits: &ExtractLimits) -> Result<(), WingsError> {
+        let dest = validate_path(root, "out").unwrap();
+        decompress(root, archive, &dest, &JobProgress::default(), limits)
+    }
+
+    #[test]
+    fn test_decompress_compress_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        let world = sample_tree(root);
+        let archive = root.join("world.tar.gz");
+        compress(&[world], &archive, &JobProgress::default(), None).unwrap();
+
+        extract(root, &archive, &ExtractLimits::default()).unwrap();
+        assert_eq!(std::fs::read(root.join("out/world/level.dat")).unwrap(), vec![7u8; 4096]);
+        assert_eq!(
+            std::fs::read_to_string(root.join("out/world/region/r.0.0.mca")).unwrap(),
+            "region data"
+        );
+    }
+
+    #[test]
+    fn test_decompress_rejects_zip_slip() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("server");
+        std::fs::create_dir(&root).unwrap();
+        let archive = root.join("evil.zip");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&archive).unwrap());
+        zip.start_file("../../evil.txt", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"pwned").unwrap();
+        zip.finish().unwrap();
+
+        let result = extract(&root, &archive, &ExtractLimits::default());
+        assert!(matches!(result, Err(WingsError::PathTraversal)));
+        assert!(!dir.path().join("evil.txt").exists());
+    }
+
+    #[test]
+    fn test_decompress_rejects_escaping_symlink() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("server");
+        std::fs::create_dir(&root).unwrap();
+        let data = tar_with(|b| {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_size(0);
+            b.append_link(&mut header, "etc", "../../../etc").unwrap();
+            append_file(b, "etc/passwd", b"root::0:0");
+        });
+        let archive = root.join("evil.tar");
+        std::fs::write(&archive, data).unwrap();
+
+        let result = extract(&root, &archive, &ExtractLimits::default());
+        assert!(matches!(result, Err(WingsError::PathTraversal)));
+        assert!(!root.join("out/etc").exists());
+    }
+
+    #[test]
+    fn test_decompress_rejects_symlink_escaping_through_link() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("server");
+        std::fs::create_dir(&root).unwrap();
+        let data = tar_with(|b| {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_size(0);
+            // Lexically inside out/d/d/d, really in out
+            b.append_link(&mut header, "d", ".").unwrap();
+            b.append_link(&mut header, "d/d/d/l", "../../x").unwrap();
+        });
+        let archive = root.join("evil.tar");
+        std::fs::write(&archive, data).unwrap();
+
+        let result = extract(&root, &archive, &ExtractLimits::default());
+        assert!(matches!(result, Err(WingsError::PathTraversal)));
+        assert!(root.join("out/d").symlink_metadata().is_err());
+    }
+
+    #[test]
+    fn test_decompress_detects_format_by_magic() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        let data = tar_with(|b| append_file(b, "plugins/config.yml", b"enabled: true"));
+        let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+        encoder.write_all(&data).unwrap();
+        // Misleading extension: detection must not rely on it
+        let archive = root.join("plugins.bin");
+        std::fs::write(&archive, encoder.finish().unwrap()).unwrap();
+
+        assert_eq!(
+            detect_archive(&archive).unwrap(),
+            ArchiveKind::Tar(Compression::Xz)
+        );
+        extract(root, &archive, &ExtractLimits::default()).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(root.join("out/plugins/config.yml")).unwrap(),
+            "enabled: true"
+        );
+    }
+
+    #[test]
+    fn test_decompress_single_gzip_file() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        let mut encoder =
+            flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"[12:00:00] Server started").unwrap();
+        let archive = root.join("latest.log.gz");
+        std::fs::write(&archive, encoder.finish().unwrap()).unwrap();
+
+        extract(root, &archive, &ExtractLimits::default()).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(root.join("out/latest.log")).unwrap(),
+            "[12:00:00] Server started"
+        );
+    }
+
+    #[test]
+    fn test_single_file_name() {
+        let name = |file: &str, compression| single_file_name(Path::new(file), compression).unwrap();
+        assert_eq!(name("latest.log.gz", Compression::Gzip), "latest.log");
+        assert_eq!(name("world.dat.ZST", Compression::Zstd), "world.dat");
+        // No extension to drop: keep the whole name rather than cutting it
+        assert_eq!(name("backup.2024-01-01", Compression::Gzip), "backup.2024-01-01.out");
+        assert_eq!(name("data", Compression::Xz), "data.out");
+        assert_eq!(name(".gz", Compression::Gzip), ".gz.out");
+    }
+
+    #[test]
+    fn test_decompress_rejects_bomb_and_rolls_back() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        let data = tar_with(|b| {
+            append_file(b, "small.txt", b"ok");
+            append_file(b, "zeros.bin", &vec![0u8; 1024 * 1024]);
+        });
+        let mut encoder = zstd::stream::write::Encoder::new(Vec::new(), 19).unwrap();
+        encoder.write_all(&data).unwrap();
+        let archive = root.join("bomb.tar.zst");
+        std::fs::write(&archive, encoder.finish().unwrap()).unwrap();
+
+        let limits = ExtractLimits {
+            max_ratio: 10,
+            ratio_grace: 0,
+            ..Default::default()
+        };
+        let result = extract(root, &archive, &limits);
+        assert!(matches!(result, Err(WingsError::ArchiveRejected(_))));
+        assert!(!root.join("out").exists());
+    }
+
+    #[test]
+    fn test_decompress_rollback_restores_replaced_files() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        std::fs::create_dir(root.join("out")).unwrap();
+        std::fs::write(root.join("out/server.properties"), "motd=old").unwrap();
+        std::os::unix::fs::symlink("server.properties", root.join("out/link")).unwrap();
+        let data = tar_with(|b| {
+            append_file(b, "server.properties", b"motd=new");
+            append_file(b, "link", b"now a file");
+            append_file(b, "new.txt", b"new");
+            append_file(b, "zeros.bin", &vec![0u8; 1024 * 1024]);
+        });
+        let archive = root.join("update.tar");
+        std::fs::write(&archive, &data).unwrap();
+
+        let quota = ExtractLimits {
+            quota: Some(1024),
+            ..Default::default()
+        };
+        assert!(matches!(extract(root, &archive, &quota), Err(WingsError::QuotaExceeded)));
+        assert_eq!(
+            std::fs::read_to_string(root.join("out/server.properties")).unwrap(),
+            "motd=old"
+        );
+        assert_eq!(
+            std::fs::read_link(root.join("out/link")).unwrap(),
+            Path::new("server.properties")
+        );
+        assert!(!root.join("out/new.txt").exists());
+
+        extract(root, &archive, &ExtractLimits::default()).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(root.join("out/server.properties")).unwrap(),
+            "motd=new"
+        );
+        // Nothing is left behind in the work directory either way
+        let hidden = std::fs::read_dir(root)
+            .unwrap()
+            .flatten()
+            .filter(|e| e.file_name().to_string_lossy().starts_with(".nexus-extract-"))
+            .count();
+        assert_eq!(hidden, 0);
+    }
+
+    #[test]
+    fn test_rar_entry_sizes() {
+        let listing = "
+UNRAR 6.24 freeware      Copyright (c) 1993-2023 Alexander Roshal
+
+Archive: world.rar
+Details: RAR 5
+
+        Name: world
+        Type: Directory
+  Attributes: drwxr-xr-x
+
+        Name: world/level.dat
+        Type: File
+        Size: 4096
+ Packed size: 61
+       Ratio: 1%
+  Attributes: -rw-r--r--
+
+        Name: world/region/r.0.0.mca
+        Type: File
+        Size: 10737418240
+ Packed size: 1024
+       Ratio: 0%
+";
+        assert_eq!(rar_entry_sizes(listing), [0, 4096, 10737418240]);
+    }
+
+    #[test]
+    fn test_decompress_enforces_entry_limit() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        let data = tar_with(|b| {
+            for i in 0..5 {
+                append_file(b, &format!("file{i}.txt"), b"x");
+            }
+        });
+        let archive = root.join("many.tar");
+        std::fs::write(&archive, data).unwrap();
+
+        let limits = ExtractLimits {
+            max_entries: 3,
+            ..Default::default()
+        };
+        assert!(matches!(
+            extract(root, &archive, &limits),
+            Err(WingsError::ArchiveRejected(_))
+        ));
+        assert!(matches!(
+            extract(root, &archive, &ExtractLimits { quota: Some(2), ..Default::default() }),
+            Err(WingsError::QuotaExceeded)
+        ));
+    }
+
+    #[test]
+    fn test_decompress_7z() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        let src = root.join("src");
+        std::fs::create_dir(&src).unwrap();
+        std::fs::write(src.join("server.properties"), "motd=hello").unwrap();
+        let archive = root.join("server.7z");
+        sevenz_rust::compress_to_path(&src, &archive).unwrap();
+
+        assert_eq!(detect_archive(&archive).unwrap(), ArchiveKind::SevenZ);
+        extract(root, &archive, &ExtractLimits::default()).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(root.join("out/server.properties")).unwrap(),
+            "motd=hello"
+        );
+    }
+
+    #[test]
+    fn test_copy_destination_suffixes_conflicts() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("server.jar"), "jar").unwrap();
+        std::fs::write(root.join("server copy.jar"), "jar").unwrap();
+
+        let name = std::ffi::OsStr::new("server.jar");
+        assert_eq!(copy_destination(root, name, false), root.join("server copy 2.jar"));
+        assert_eq!(
+            copy_destination(root, std::ffi::OsStr::new("new.txt"), false),
+            root.join("new.txt")
+        );
+    }
+
+    #[test]
+    fn test_copy_entries_duplicates_tree() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        let world = sample_tree(root);
+        std::os::unix::fs::symlink("level.dat", world.join("level.link")).unwrap();
+        let progress = JobProgress::default();
+
+        copy_entries(&[world], None, &progress, None).unwrap();
+        let copy = root.join("world copy");
+        assert_eq!(std::fs::read(copy.join("level.dat")).unwrap(), vec![7u8; 4096]);
+        assert_eq!(
+            std::fs::read_link(copy.join("level.link")).unwrap(),
+            Path::new("level.dat")
+        );
+        assert_eq!(progress.processed(), progress.total());
+    }
+
+    #[test]
+    fn test_copy_entries_checks_quota_and_nesting() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        let world = sample_tree(root);
+
+        let sources = [world.clone()];
+        let result = copy_entries(&sources, None, &JobProgress::default(), Some(100));
+        assert!(matches!(result, Err(WingsError::QuotaExceeded)));
+        let region = world.join("region");
+        let result = copy_entries(&sources, Some(&region), &JobProgress::default(), None);
+        assert!(matches!(result, Err(WingsError::InvalidRequest(_))));
+        assert!(!root.join("world copy").exists());
+    }
+
+    #[test]
+    fn test_validate_entries_rejects_root() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        sample_tree(root);
+
+        for path in ["/", "", ".", "world/.."] {
+            assert!(
+                matches!(
+                    validate_entries(root, &[path.to_string()]),
+                    Err(WingsError::InvalidRequest(_))
+                ),
+                "{path}"
+            );
+        }
+        let entries = validate_entries(root, &["/world".to_string()]).unwrap();
+        assert_eq!(entries, [root.canonicalize().unwrap().join("world")]);
+    }
+
+    #[test]
+    fn test_move_entries_into_directory() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("a.txt"), "a").unwrap();
+        std::fs::write(root.join("b.txt"), "b").unwrap();
+        std::fs::create_dir_all(root.join("archive")).unwrap();
+        std::fs::write(root.join("archive/b.txt"), "old").unwrap();
+
+        let sources = [root.join("a.txt"), root.join("b.txt")];
+        assert!(move_entries(&sources, &root.join("archive")).is_err());
+        // Nothing moved when any target conflicts
+        assert!(root.join("a.txt").exists());
+
+        move_entries(&sources[..1], &root.join("archive")).unwrap();
+        assert_eq!(std::fs::read_to_string(root.join("archive/a.txt")).unwrap(), "a");
+        assert!(!root.join("a.txt").exists());
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(parse_mode("755").unwrap(), 0o755);
+        assert_eq!(parse_mode("0644").unwrap(), 0o644);
+        assert!(parse_mode("4755").is_err());
+        assert!(parse_mode("rwx").is_err());
+    }
+
+    #[test]
+    fn test_chmod() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("start.sh");
+        std::fs::write(&path, "#!/bin/sh").unwrap();
+
+        chmod(&path, 0o750).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o750);
+    }
+
+    fn search_options(name: Option<&str>, content: Option<&str>) -> SearchOptions {
+        SearchOptions {
+            name: name.map(|n| globset::Glob::new(n).unwrap().compile_matcher()),
+            name_matches_path: name.is_some_and(|n| n.contains('/')),
+            content: content.map(|c| regex::Regex::new(c).unwrap()),
+            max_file_size: MAX_FILE_SIZE,
+            limit: 100,
+            deadline: std::time::Instant::now() + std::time::Duration::from_secs(10),
+        }
+    }
+
+    #[test]
+    fn test_search_content_with_line_numbers() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("plugins/Essentials")).unwrap();
+        std::fs::write(
+            root.join("plugins/Essentials/config.yml"),
+            "spawn-on-join: false\nteleport-cooldown: 5\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("plugins/other.yml"), "cooldown: 1\n").unwrap();
+        std::fs::write(root.join("plugins/data.bin"), b"\0\0teleport-cooldown").unwrap();
+
+        let mut results = Vec::new();
+        let options = search_options(Some("config.yml"), Some("teleport-\\w+"));
+        let summary = search(root, root, &options, |m| {
+            results.push(m);
+            true
+        });
+
+        assert_eq!(summary.matches, 1);
+        assert_eq!(results[0].path, "/plugins/Essentials/config.yml");
+        assert_eq!(results[0].line, Some(2));
+        assert_eq!(results[0].snippet.as_deref(), Some("teleport-cooldown: 5"));
+    }
+
+    #[test]
+    fn test_search_by_name_skips_binary_and_truncates() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        for i in 0..5 {
+            std::fs::write(root.join(format!("log{i}.txt")), "x").unwrap();
+        }
+        // Matches the pattern, but NUL bytes make it binary
+        std::fs::write(root.join("level.txt"), b"LevelName=x\0\0\x01\x02").unwrap();
+
+        let mut found = Vec::new();
+        search(root, root, &search_options(Some("*.txt"), Some("x")), |m| {
+            found.push(m.path);
+            true
+        });
+        found.sort();
+        assert_eq!(found, ["/log0.txt", "/log1.txt", "/log2.txt", "/log3.txt", "/log4.txt"]);
+
+        let mut options = search_options(Some("*.txt"), None);
+        options.limit = 3;
+        let mut count = 0;
+        let summary = search(root, root, &options, |_| {
+            count += 1;
+            true
+        });
+        assert_eq!(count, 3);
+        assert!(summary.truncated);
+    }
+
+    #[test]
+    fn test_list_directory_pages_with_cursor() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        for i in 0..7 {
+            std::fs::write(root.join(format!("file{i}.txt")), "x".repeat(i)).unwrap();
+        }
+        std::fs::create_dir(root.join("logs")).unwrap();
+
+        let mut options = ListOptions {
+            sort: SortKey::Size,
+            descending: true,
+            limit: 3,
+            ..Default::default()
+        };
+        let mut names = Vec::new();
+        loop {
+            let page = list_directory(root, &options).unwrap();
+            names.extend(page.entries.into_iter().map(|e| e.name));
+            match page.next_cursor {
+                Some(cursor) => options.cursor = Some(ListCursor::decode(&cursor).unwrap()),
+                None => break,
+            }
+        }
+
+        assert_eq!(names.len(), 8);
+        assert_eq!(names[0], "logs");
+        assert_eq!(names[1], "file6.txt");
+        assert_eq!(names[7], "file0.txt");
+    }
+
+    #[test]
+    fn test_list_directory_recursive_with_filter() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("logs/old")).unwrap();
+        std::fs::write(root.join("latest.log"), "").unwrap();
+        std::fs::write(root.join("logs/a.log"), "").unwrap();
+        std::fs::write(root.join("logs/old/b.log"), "").unwrap();
+        std::fs::write(root.join("logs/notes.txt"), "").unwrap();
+        std::os::unix::fs::symlink("latest.log", root.join("current.log")).unwrap();
+
+        let options = ListOptions {
+            filter: Some(globset::Glob::new("*.log").unwrap().compile_matcher()),
+            depth: 2,
+            ..Default::default()
+        };
+        let entries = list_directory(root, &options).unwrap().entries;
+        let paths: Vec<_> = entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["current.log", "latest.log", "logs/a.log"]);
+        assert!(entries[0].is_symlink);
+        assert_eq!(entries[0].symlink_target.as_deref(), Some("latest.log"));
     }
 }
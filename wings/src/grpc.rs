@@ -1,15 +1,17 @@
 use std::collections::HashMap;
 use std::pin::Pin;
 use std::sync::Arc;
-use std::time::Duration;
 
-use dashmap::DashMap;
-use futures_util::StreamExt;
 use tokio::sync::{mpsc, Mutex};
+use tokio_stream::wrappers::ReceiverStream;
 use tokio_stream::Stream;
 use tonic::{Request, Response, Status, Streaming};
 
-use crate::docker::{ServerConfig as DockerServerConfig, PortMapping as DockerPortMapping};
+use crate::docker::{
+    PortMapping as DockerPortMapping, PortProtocol, ServerConfig as DockerServerConfig,
+};
+use crate::backups;
+use crate::error::WingsError;
 use crate::installer;
 use crate::state::AppState;
 
@@ -23,37 +25,33 @@ use proto::*;
 /// Bounded event buffer for offline queueing
 const MAX_EVENT_BUFFER: usize = 1000;
 
+/// Events in flight to a connected Panel
+const EVENT_STREAM_BUFFER: usize = 64;
+
 /// Shared event sender — Wings pushes events here, gRPC stream reads them
 pub type EventSender = mpsc::Sender<WingsEvent>;
 pub type EventReceiver = mpsc::Receiver<WingsEvent>;
 
-/// Per-server mutex to deduplicate concurrent power commands
-pub type ServerLocks = Arc<DashMap<String, Arc<Mutex<()>>>>;
-
 pub struct WingsGrpcService {
     state: Arc<AppState>,
-    event_tx: EventSender,
-    server_locks: ServerLocks,
+    /// Events waiting for the Panel, read by one EventStream at a time
+    events: Arc<Mutex<EventReceiver>>,
 }
 
 impl WingsGrpcService {
-    pub fn new(state: Arc<AppState>, event_tx: EventSender) -> Self {
+    pub fn new(state: Arc<AppState>, events: EventReceiver) -> Self {
         Self {
             state,
-            event_tx,
-            server_locks: Arc::new(DashMap::new()),
+            events: Arc::new(Mutex::new(events)),
         }
     }
 
     fn get_lock(&self, uuid: &str) -> Arc<Mutex<()>> {
-        self.server_locks
-            .entry(uuid.to_string())
-            .or_insert_with(|| Arc::new(Mutex::new(())))
-            .clone()
+        self.state.server_lock(uuid)
     }
 
-    fn to_docker_config(cfg: &ServerConfig) -> DockerServerConfig {
-        DockerServerConfig {
+    fn to_docker_config(cfg: &ServerConfig) -> Result<DockerServerConfig, WingsError> {
+        let mut docker_cfg = DockerServerConfig {
             uuid: cfg.uuid.clone(),
             docker_image: cfg.docker_image.clone(),
             startup_command: cfg.startup_command.clone(),
@@ -67,13 +65,56 @@ impl WingsGrpcService {
                 .map(|pm| DockerPortMapping {
                     host_port: pm.host_port as u16,
                     container_port: pm.container_port as u16,
+                    protocol: match proto::PortProtocol::try_from(pm.protocol) {
+                        Ok(proto::PortProtocol::Udp) => PortProtocol::Udp,
+                        Ok(proto::PortProtocol::Both) => PortProtocol::Both,
+                        Ok(proto::PortProtocol::Tcp) | Err(_) => PortProtocol::Tcp,
+                    },
+                    bind_ip: Some(pm.bind_ip.clone()).filter(|ip| !ip.is_empty()),
+                    primary: pm.primary,
                 })
                 .collect(),
             volume_path: cfg.volume_path.clone(),
+            pull_policy: match proto::PullPolicy::try_from(cfg.pull_policy) {
+                Ok(proto::PullPolicy::Always) => Some(crate::config::PullPolicy::Always),
+                Ok(proto::PullPolicy::IfNotPresent) => Some(crate::config::PullPolicy::IfNotPresent),
+                Ok(proto::PullPolicy::Never) => Some(crate::config::PullPolicy::Never),
+                Ok(proto::PullPolicy::Default) | Err(_) => None,
+            },
+            image_digest: None,
+            rebuild_pending: false,
+            swap_limit: 0,
+            memory_overhead: 0,
+            io_weight: 0,
+            pids_limit: 0,
+            cpuset: String::new(),
+            oom_kill_disable: false,
+            network_isolated: cfg.network_isolated,
+            egress_limit: 0,
+            ingress_limit: 0,
+        };
+        if let Some(limits) = &cfg.limits {
+            Self::apply_limits(&mut docker_cfg, limits)?;
         }
+        Ok(docker_cfg)
+    }
+
+    fn apply_limits(
+        cfg: &mut DockerServerConfig,
+        limits: &ResourceLimits,
+    ) -> Result<(), WingsError> {
+        cfg.io_weight = crate::docker::io_weight(limits.io_weight)?;
+        cfg.swap_limit = limits.swap_limit_mb;
+        cfg.memory_overhead = limits.memory_overhead_mb;
+        cfg.pids_limit = limits.pids_limit;
+        cfg.cpuset = limits.cpuset.clone();
+        cfg.oom_kill_disable = limits.oom_kill_disable;
+        cfg.egress_limit = limits.egress_limit_mbit;
+        cfg.ingress_limit = limits.ingress_limit_mbit;
+        Ok(())
     }
 
-    fn docker_state_to_proto(state: &str) -> ServerState {
+    pub(crate) fn docker_state_to_proto(state: &str) -> ServerState {
         match state {
             "running" => ServerState::StateRunning,
             "created" | "restarting" => ServerState::StateStarting,
@@ -82,9 +123,43 @@ impl WingsGrpcService {
         }
     }
 
-    async fn emit_event(&self, event: WingsEvent) {
-        if self.event_tx.try_send(event).is_err() {
-            tracing::warn!("Event buffer full, dropping oldest events");
+    /// Stream events to the Panel, starting with those buffered while it was
+    /// away. A second stream waits until the first one ends.
+    fn panel_events(&self) -> ReceiverStream<Result<WingsEvent, Status>> {
+        let events = self.events.clone();
+        let (tx, rx) = mpsc::channel(EVENT_STREAM_BUFFER);
+        tokio::spawn(async move {
+            let mut events = events.lock_owned().await;
+            // Only take an event once it can be sent, so none are lost when
+            // the Panel disconnects
+            while let Ok(permit) = tx.reserve().await {
+                tokio::select! {
+                    biased;
+                    _ = tx.closed() => break,
+                    event = events.recv() => match event {
+                        Some(event) => permit.send(Ok(event)),
+                        None => break,
+                    },
+                }
+            }
+        });
+        ReceiverStream::new(rx)
+    }
+
+    /// Map a Wings error to the closest gRPC status.
+    fn status_from(e: WingsError) -> Status {
+        match e {
+            WingsError::ServerNotFound(_) | WingsError::BackupNotFound(_) => {
+                Status::not_found(e.to_string())
+            }
+            WingsError::InvalidRequest(_) | WingsError::PathTraversal => {
+                Status::invalid_argument(e.to_string())
+            }
+            WingsError::PortConflict { .. } => Status::already_exists(e.to_string()),
+            WingsError::RemoteStorage(_) | WingsError::Transfer(_) => {
+                Status::unavailable(e.to_string())
+            }
+            _ => Status::internal(e.to_string()),
         }
     }
 
@@ -94,14 +169,7 @@ impl WingsGrpcService {
         if !path.exists() {
             return 0;
         }
-        walkdir::WalkDir::new(&path)
-            .max_depth(64)
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .filter_map(|e| e.metadata().ok())
-            .filter(|m| m.is_file())
-            .map(|m| m.len())
-            .sum()
+        crate::files::disk_usage(&path)
     }
 }
 
@@ -114,62 +182,32 @@ impl WingsService for WingsGrpcService {
         let req = request.into_inner();
         let server_cfg = req.server.ok_or_else(|| Status::invalid_argument("Missing server config"))?;
 
-        let mut docker_cfg = Self::to_docker_config(&server_cfg);
-
-        // Ensure data directory
-        let server_dir = std::path::Path::new(&self.state.config.storage.data_dir).join(&docker_cfg.uuid);
-        std::fs::create_dir_all(&server_dir).map_err(|e| Status::internal(e.to_string()))?;
-        docker_cfg.volume_path = server_dir.to_string_lossy().to_string();
+        let mut docker_cfg = Self::to_docker_config(&server_cfg).map_err(Self::status_from)?;
+        docker_cfg.volume_path = crate::containers::volume_path(&self.state, &docker_cfg.uuid);
 
         // Store config in registry for later reconstruction
-        self.state.store_server_config(&docker_cfg).await;
+        crate::ports::claim(&self.state, &docker_cfg)
+            .await
+            .map_err(Self::status_from)?;
+
+        // Ensure data directory
+        let server_dir = std::path::Path::new(&docker_cfg.volume_path);
+        std::fs::create_dir_all(server_dir).map_err(|e| Status::internal(e.to_string()))?;
+        crate::files::chown_recursive(server_dir, self.state.docker.container_user())
+            .map_err(|e| Status::internal(e.to_string()))?;
 
         tracing::info!(uuid = %docker_cfg.uuid, image = %docker_cfg.docker_image, "Creating server");
 
-        let container_id = self.state.docker.create_server(&docker_cfg)
-            .await
-            .map_err(|e| Status::internal(format!("Docker create failed: {e}")))?;
-
-        // Run install if provided
-        if !req.install_script.is_empty() && !req.install_docker_image.is_empty() {
-            let state = self.state.clone();
-            let event_tx = self.event_tx.clone();
-            let cfg = docker_cfg.clone();
-            let script = req.install_script;
-            let image = req.install_docker_image;
-
-            tokio::spawn(async move {
-                let panel_url = Some(state.config.panel.url.as_str());
-                let panel_auth_str = format!("{}.{}", state.config.panel.token_id, state.config.panel.token);
-                let panel_auth = Some(panel_auth_str.as_str());
-
-                match installer::run_install(&state.docker, &cfg, &script, &image, panel_url, panel_auth).await {
-                    Ok(output) => {
-                        tracing::info!(uuid = %cfg.uuid, lines = output.len(), "Install completed");
-                        let _ = event_tx.try_send(WingsEvent {
-                            event: Some(wings_event::Event::InstallComplete(ServerInstallComplete {
-                                uuid: cfg.uuid.clone(),
-                                timestamp_ms: chrono::Utc::now().timestamp_millis(),
-                            })),
-                        });
-                    }
-                    Err(e) => {
-                        tracing::error!(uuid = %cfg.uuid, error = %e, "Install failed");
-                        let _ = event_tx.try_send(WingsEvent {
-                            event: Some(wings_event::Event::InstallFailed(ServerInstallFailed {
-                                uuid: cfg.uuid.clone(),
-                                error_message: e.to_string(),
-                                timestamp_ms: chrono::Utc::now().timestamp_millis(),
-                            })),
-                        });
-                    }
-                }
+        let install = (!req.install_script.is_empty() && !req.install_docker_image.is_empty())
+            .then_some(installer::InstallScript {
+                script: req.install_script,
+                image: req.install_docker_image,
             });
-        }
+        installer::spawn_setup(self.state.clone(), docker_cfg.clone(), true, install);
 
         Ok(Response::new(CreateServerResponse {
-            container_id,
             uuid: docker_cfg.uuid,
+            ..Default::default()
         }))
     }
 
@@ -189,7 +227,8 @@ impl WingsService for WingsGrpcService {
 
         self.state.console_buffers.write().await.remove(&req.uuid);
         self.state.remove_server_config(&req.uuid).await;
-        self.server_locks.remove(&req.uuid);
+        crate::schedules::remove(&self.state, &req.uuid);
+        self.state.server_locks.remove(&req.uuid);
 
         Ok(Response::new(DeleteServerResponse {}))
     }
@@ -200,44 +239,21 @@ impl WingsService for WingsGrpcService {
     ) -> Result<Response<ReinstallServerResponse>, Status> {
         let req = request.into_inner();
         let server_cfg = req.server.ok_or_else(|| Status::invalid_argument("Missing server config"))?;
-        let docker_cfg = Self::to_docker_config(&server_cfg);
+        let mut docker_cfg = Self::to_docker_config(&server_cfg).map_err(Self::status_from)?;
+        docker_cfg.volume_path = crate::containers::volume_path(&self.state, &docker_cfg.uuid);
 
         tracing::info!(uuid = %docker_cfg.uuid, "Reinstalling server");
 
         // Update stored config
-        self.state.store_server_config(&docker_cfg).await;
-
-        let state = self.state.clone();
-        let event_tx = self.event_tx.clone();
-        let script = req.install_script;
-        let image = req.install_docker_image;
-        let cfg = docker_cfg;
+        crate::ports::claim(&self.state, &docker_cfg)
+            .await
+            .map_err(Self::status_from)?;
 
-        tokio::spawn(async move {
-            let panel_url = Some(state.config.panel.url.as_str());
-            let panel_auth_str = format!("{}.{}", state.config.panel.token_id, state.config.panel.token);
-            let panel_auth = Some(panel_auth_str.as_str());
-
-            match installer::run_install(&state.docker, &cfg, &script, &image, panel_url, panel_auth).await {
-                Ok(_) => {
-                    let _ = event_tx.try_send(WingsEvent {
-                        event: Some(wings_event::Event::InstallComplete(ServerInstallComplete {
-                            uuid: cfg.uuid.clone(),
-                            timestamp_ms: chrono::Utc::now().timestamp_millis(),
-                        })),
-                    });
-                }
-                Err(e) => {
-                    let _ = event_tx.try_send(WingsEvent {
-                        event: Some(wings_event::Event::InstallFailed(ServerInstallFailed {
-                            uuid: cfg.uuid.clone(),
-                            error_message: e.to_string(),
-                            timestamp_ms: chrono::Utc::now().timestamp_millis(),
-                        })),
-                    });
-                }
-            }
-        });
+        let install = installer::InstallScript {
+            script: req.install_script,
+            image: req.install_docker_image,
+        };
+        installer::spawn_setup(self.state.clone(), docker_cfg, false, Some(install));
 
         Ok(Response::new(ReinstallServerResponse {}))
     }
@@ -255,19 +271,11 @@ impl WingsService for WingsGrpcService {
 
         tracing::info!(uuid = %req.uuid, action = ?action, "Power action");
 
-        // If starting and container doesn't exist, recreate from stored config
+        // Create a missing container or apply a pending rebuild first
         if matches!(action, PowerAction::PowerStart) {
-            let container_exists = self.state.docker.get_container_status(&req.uuid).await.is_ok();
-            if !container_exists {
-                tracing::info!(uuid = %req.uuid, "Container missing, recreating from stored config");
-                if let Some(cfg) = self.state.get_server_config(&req.uuid).await {
-                    self.state.docker.create_server(&cfg)
-                        .await
-                        .map_err(|e| Status::internal(format!("Failed to recreate container: {e}")))?;
-                } else {
-                    return Err(Status::not_found("Server config not found in registry, cannot recreate container"));
-                }
-            }
+            crate::containers::prepare_start(&self.state, &req.uuid)
+                .await
+                .map_err(Self::status_from)?;
         }
 
         let prev_state = self.state.docker.get_container_status(&req.uuid).await.unwrap_or_else(|_| "unknown".to_string());
@@ -280,25 +288,24 @@ impl WingsService for WingsGrpcService {
                     .map_err(|e| Status::internal(e.to_string()))?;
 
                 let new_state_str = self.state.docker.get_container_status(&req.uuid).await.unwrap_or_else(|_| "unknown".to_string());
-                self.emit_event(WingsEvent {
+                self.state.emit_event(WingsEvent {
                     event: Some(wings_event::Event::StateChanged(ServerStateChanged {
                         uuid: req.uuid.clone(),
                         previous_state: Self::docker_state_to_proto(&prev_state).into(),
                         new_state: Self::docker_state_to_proto(&new_state_str).into(),
                         timestamp_ms: chrono::Utc::now().timestamp_millis(),
                     })),
-                }).await;
+                });
             }
             _ => {
                 // Stop/restart/kill: spawn background task, respond immediately
                 let state = self.state.clone();
-                let event_tx = self.event_tx.clone();
                 let uuid = req.uuid.clone();
                 let prev = prev_state.clone();
                 tokio::spawn(async move {
                     let result = match action {
                         PowerAction::PowerStop => state.docker.stop_server(&uuid, 30).await,
-                        PowerAction::PowerRestart => state.docker.restart_server(&uuid).await,
+                        PowerAction::PowerRestart => crate::containers::restart(&state, &uuid).await,
                         PowerAction::PowerKill => state.docker.kill_server(&uuid).await,
                         _ => unreachable!(),
                     };
@@ -307,7 +314,7 @@ impl WingsService for WingsGrpcService {
                         return;
                     }
                     let new_state_str = state.docker.get_container_status(&uuid).await.unwrap_or_else(|_| "unknown".to_string());
-                    let _ = event_tx.try_send(WingsEvent {
+                    state.emit_event(WingsEvent {
                         event: Some(wings_event::Event::StateChanged(ServerStateChanged {
                             uuid: uuid.clone(),
                             previous_state: WingsGrpcService::docker_state_to_proto(&prev).into(),
@@ -339,10 +346,15 @@ impl WingsService for WingsGrpcService {
     ) -> Result<Response<SyncConfigResponse>, Status> {
         let req = request.into_inner();
         let cfg = req.server.ok_or_else(|| Status::invalid_argument("Missing server config"))?;
-        let docker_cfg = Self::to_docker_config(&cfg);
-        self.state.store_server_config(&docker_cfg).await;
-        tracing::info!(uuid = %docker_cfg.uuid, "Server config synced");
-        Ok(Response::new(SyncConfigResponse {}))
+        let docker_cfg = Self::to_docker_config(&cfg).map_err(Self::status_from)?;
+        let uuid = docker_cfg.uuid.clone();
+        let lock = self.get_lock(&uuid);
+        let _guard = lock.lock().await;
+        let rebuild_pending = crate::containers::sync_config(&self.state, docker_cfg)
+            .await
+            .map_err(Self::status_from)?;
+        tracing::info!(uuid = %uuid, rebuild_pending, "Server config synced");
+        Ok(Response::new(SyncConfigResponse { rebuild_pending }))
     }
 
     async fn get_server_status(
@@ -354,8 +366,10 @@ impl WingsService for WingsGrpcService {
             .await
             .map_err(|e| Status::not_found(e.to_string()))?;
 
+        let stored = self.state.get_server_config(&req.uuid).await;
         let resources = if container_state == "running" {
-            match self.state.docker.get_stats(&req.uuid).await {
+            let cpu_limit = stored.as_ref().map_or(0, |cfg| cfg.cpu_limit);
+            match self.state.docker.get_stats(&req.uuid, cpu_limit).await {
                 Ok(mut stats) => {
                     stats.disk_bytes = Self::calculate_disk_usage(
                         &self.state.config.storage.data_dir,
@@ -364,10 +378,13 @@ impl WingsService for WingsGrpcService {
                     Some(ResourceStats {
                         uuid: req.uuid.clone(),
                         cpu_percent: stats.cpu_percent,
+                        cpu_limit_percent: stats.cpu_limit_percent,
                         memory_bytes: stats.memory_bytes,
                         memory_limit: stats.memory_limit,
                         network_rx_bytes: stats.network_rx_bytes,
                         network_tx_bytes: stats.network_tx_bytes,
+                        network_rx_bytes_per_sec: stats.network_rx_bytes_per_sec,
+                        network_tx_bytes_per_sec: stats.network_tx_bytes_per_sec,
                         disk_bytes: stats.disk_bytes,
                         timestamp_ms: chrono::Utc::now().timestamp_millis(),
                     })
@@ -378,10 +395,15 @@ impl WingsService for WingsGrpcService {
             None
         };
 
+        let image_digest = stored.as_ref().and_then(|cfg| cfg.image_digest.clone()).unwrap_or_default();
+        let rebuild_pending = stored.is_some_and(|cfg| cfg.rebuild_pending);
+
         Ok(Response::new(ServerStatusResponse {
             uuid: req.uuid,
             state: Self::docker_state_to_proto(&container_state).into(),
             resources,
+            image_digest,
+            rebuild_pending,
         }))
     }
 
@@ -432,45 +454,275 @@ impl WingsService for WingsGrpcService {
 
         tracing::info!(uuid = %req.uuid, mem = req.memory_limit_mb, cpu = req.cpu_limit, disk = req.disk_limit_mb, "Updating resources");
 
-        // Use Docker update API for live resource changes
-        let container_name = format!("nexus-{}", &req.uuid.replace('-', "")[..8.min(req.uuid.replace('-', "").len())]);
-        let update_config = bollard::container::UpdateContainerOptions::<String> {
-            memory: Some((req.memory_limit_mb * 1024 * 1024) as i64),
-            nano_cpus: Some((req.cpu_limit as i64) * 10_000_000),
-            ..Default::default()
+        let mut cfg = self.state.get_server_config(&req.uuid)
+            .await
+            .ok_or_else(|| Status::not_found("Server config not found in registry"))?;
+        cfg.memory_limit = req.memory_limit_mb;
+        cfg.cpu_limit = req.cpu_limit as u64;
+        cfg.disk_limit = req.disk_limit_mb;
+        if let Some(limits) = &req.limits {
+            Self::apply_limits(&mut cfg, limits).map_err(Self::status_from)?;
+        }
+        let rebuild_pending = crate::containers::sync_config(&self.state, cfg)
+            .await
+            .map_err(Self::status_from)?;
+
+        Ok(Response::new(UpdateResourcesResponse { rebuild_pending }))
+    }
+
+    async fn create_backup(
+        &self,
+        request: Request<CreateBackupRequest>,
+    ) -> Result<Response<CreateBackupResponse>, Status> {
+        let req = request.into_inner();
+        let adapter = match proto::BackupAdapter::try_from(req.adapter) {
+            Ok(proto::BackupAdapter::S3) => backups::BackupAdapter::S3,
+            Ok(proto::BackupAdapter::Local) => backups::BackupAdapter::Local,
+            Err(_) => return Err(Status::invalid_argument("Unknown backup adapter")),
         };
+        let format = match proto::BackupFormat::try_from(req.format) {
+            Ok(proto::BackupFormat::Chunked) => backups::BackupFormat::Chunked,
+            Ok(proto::BackupFormat::Archive) => backups::BackupFormat::Archive,
+            Err(_) => return Err(Status::invalid_argument("Unknown backup format")),
+        };
+        let (backup_uuid, job_id) = backups::start_backup(
+            &self.state,
+            &req.uuid,
+            Some(&req.backup_uuid),
+            adapter,
+            format,
+        )
+        .map_err(Self::status_from)?;
+        tracing::info!(uuid = %req.uuid, backup = %backup_uuid, "Backup started");
+        Ok(Response::new(CreateBackupResponse { backup_uuid, job_id }))
+    }
 
-        self.state.docker.client().update_container(&container_name, update_config)
+    async fn list_backups(
+        &self,
+        request: Request<ListBackupsRequest>,
+    ) -> Result<Response<ListBackupsResponse>, Status> {
+        let req = request.into_inner();
+        let backup_dir = std::path::Path::new(&self.state.config.storage.backup_dir);
+        let backups = backups::list(backup_dir, &req.uuid).map_err(Self::status_from)?;
+        Ok(Response::new(ListBackupsResponse {
+            backups: backups.iter().map(Into::into).collect(),
+        }))
+    }
+
+    async fn delete_backup(
+        &self,
+        request: Request<DeleteBackupRequest>,
+    ) -> Result<Response<DeleteBackupResponse>, Status> {
+        let req = request.into_inner();
+        let backup_uuid = backups::parse_backup_uuid(&req.backup_uuid).map_err(Self::status_from)?;
+        let backup_dir = std::path::Path::new(&self.state.config.storage.backup_dir);
+        backups::delete(backup_dir, self.state.s3.as_ref(), &req.uuid, &backup_uuid)
             .await
-            .map_err(|e| Status::internal(format!("Docker update failed: {e}")))?;
+            .map_err(Self::status_from)?;
+        tracing::info!(uuid = %req.uuid, backup = %backup_uuid, "Backup deleted");
+        Ok(Response::new(DeleteBackupResponse {}))
+    }
 
-        // Update stored config
-        if let Some(mut cfg) = self.state.get_server_config(&req.uuid).await {
-            cfg.memory_limit = req.memory_limit_mb;
-            cfg.cpu_limit = req.cpu_limit as u64;
-            cfg.disk_limit = req.disk_limit_mb;
-            self.state.store_server_config(&cfg).await;
+    async fn restore_backup(
+        &self,
+        request: Request<RestoreBackupRequest>,
+    ) -> Result<Response<RestoreBackupResponse>, Status> {
+        let req = request.into_inner();
+        let job_id = backups::start_restore(&self.state, &req.uuid, &req.backup_uuid, req.wipe)
+            .map_err(Self::status_from)?;
+        tracing::info!(uuid = %req.uuid, backup = %req.backup_uuid, wipe = req.wipe, "Restore started");
+        Ok(Response::new(RestoreBackupResponse { job_id }))
+    }
+
+    async fn verify_backup(
+        &self,
+        request: Request<VerifyBackupRequest>,
+    ) -> Result<Response<VerifyBackupResponse>, Status> {
+        let req = request.into_inner();
+        let backup_uuid = backups::parse_backup_uuid(&req.backup_uuid).map_err(Self::status_from)?;
+        let state = self.state.clone();
+        let report = tokio::task::spawn_blocking(move || {
+            let backup_dir = std::path::Path::new(&state.config.storage.backup_dir);
+            backups::verify(backup_dir, state.s3.as_ref(), &req.uuid, &backup_uuid)
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(Self::status_from)?;
+        Ok(Response::new(VerifyBackupResponse {
+            valid: report.valid,
+            archive_missing: report.archive_missing,
+            checked_chunks: report.checked_chunks,
+            missing_chunks: report.missing_chunks,
+            corrupt_chunks: report.corrupt_chunks,
+        }))
+    }
+
+    async fn prune_backups(
+        &self,
+        request: Request<PruneBackupsRequest>,
+    ) -> Result<Response<PruneBackupsResponse>, Status> {
+        let req = request.into_inner();
+        let policy = crate::snapshots::RetentionPolicy {
+            hourly: req.hourly,
+            daily: req.daily,
+            weekly: req.weekly,
+        };
+        let job_id =
+            backups::start_prune(&self.state, &req.uuid, policy).map_err(Self::status_from)?;
+        tracing::info!(uuid = %req.uuid, "Prune started");
+        Ok(Response::new(PruneBackupsResponse { job_id }))
+    }
+
+    async fn accept_transfer(
+        &self,
+        request: Request<AcceptTransferRequest>,
+    ) -> Result<Response<AcceptTransferResponse>, Status> {
+        let req = request.into_inner();
+        crate::transfer::accept(&self.state, &req.uuid, &req.token)
+            .await
+            .map_err(Self::status_from)?;
+        Ok(Response::new(AcceptTransferResponse {}))
+    }
+
+    async fn start_transfer(
+        &self,
+        request: Request<StartTransferRequest>,
+    ) -> Result<Response<StartTransferResponse>, Status> {
+        let req = request.into_inner();
+        let target = crate::transfer::TransferTarget {
+            url: req.destination_url,
+            token: req.token,
+        };
+        let job_id = crate::transfer::start(&self.state, &req.uuid, target)
+            .await
+            .map_err(Self::status_from)?;
+        tracing::info!(uuid = %req.uuid, "Transfer started");
+        Ok(Response::new(StartTransferResponse { job_id }))
+    }
+
+    async fn sync_schedules(
+        &self,
+        request: Request<SyncSchedulesRequest>,
+    ) -> Result<Response<SyncSchedulesResponse>, Status> {
+        use crate::schedules::{Action, PowerSignal};
+
+        let req = request.into_inner();
+        let mut schedules = Vec::with_capacity(req.schedules.len());
+        for schedule in req.schedules {
+            let mut steps = Vec::with_capacity(schedule.actions.len());
+            for step in schedule.actions {
+                let action = match ScheduleActionType::try_from(step.r#type) {
+                    Ok(ScheduleActionType::ScheduleActionCommand) => Action::Command {
+                        command: step.command,
+                    },
+                    Ok(ScheduleActionType::ScheduleActionPower) => {
+                        let signal = match PowerAction::try_from(step.power_action) {
+                            Ok(PowerAction::PowerStart) => PowerSignal::Start,
+                            Ok(PowerAction::PowerStop) => PowerSignal::Stop,
+                            Ok(PowerAction::PowerRestart) => PowerSignal::Restart,
+                            Ok(PowerAction::PowerKill) => PowerSignal::Kill,
+                            Err(_) => return Err(Status::invalid_argument("Unknown power action")),
+                        };
+                        Action::Power { signal }
+                    }
+                    Ok(ScheduleActionType::ScheduleActionBackup) => {
+                        let adapter = match proto::BackupAdapter::try_from(step.backup_adapter) {
+                            Ok(proto::BackupAdapter::S3) => backups::BackupAdapter::S3,
+                            Ok(proto::BackupAdapter::Local) => backups::BackupAdapter::Local,
+                            Err(_) => return Err(Status::invalid_argument("Unknown backup adapter")),
+                        };
+                        let format = match proto::BackupFormat::try_from(step.backup_format) {
+                            Ok(proto::BackupFormat::Chunked) => backups::BackupFormat::Chunked,
+                            Ok(proto::BackupFormat::Archive) => backups::BackupFormat::Archive,
+                            Err(_) => return Err(Status::invalid_argument("Unknown backup format")),
+                        };
+                        Action::Backup { adapter, format }
+                    }
+                    Err(_) => return Err(Status::invalid_argument("Unknown schedule action")),
+                };
+                steps.push(crate::schedules::ScheduleStep {
+                    delay_seconds: step.delay_seconds,
+                    action,
+                });
+            }
+            schedules.push(crate::schedules::Schedule {
+                id: schedule.id,
+                name: schedule.name,
+                cron: schedule.cron,
+                enabled: schedule.enabled,
+                only_when_online: schedule.only_when_online,
+                steps,
+            });
         }
 
-        Ok(Response::new(UpdateResourcesResponse {}))
+        let count = schedules.len();
+        crate::schedules::sync(&self.state, &req.uuid, schedules).map_err(Self::status_from)?;
+        tracing::info!(uuid = %req.uuid, "Synced {count} schedule(s)");
+        Ok(Response::new(SyncSchedulesResponse {}))
     }
 
-    type EventStreamStream = Pin<Box<dyn Stream<Item = Result<PanelCommand, Status>> + Send>>;
+    async fn list_images(
+        &self,
+        _request: Request<ListImagesRequest>,
+    ) -> Result<Response<ListImagesResponse>, Status> {
+        let images = crate::images::list(&self.state)
+            .await
+            .map_err(Self::status_from)?
+            .into_iter()
+            .map(|image| ImageInfo {
+                id: image.id,
+                tags: image.tags,
+                size_bytes: image.size_bytes,
+                created_ms: image.created_ms,
+                servers: image.servers,
+            })
+            .collect();
+        Ok(Response::new(ListImagesResponse { images }))
+    }
+
+    async fn pull_image(
+        &self,
+        request: Request<PullImageRequest>,
+    ) -> Result<Response<PullImageResponse>, Status> {
+        let req = request.into_inner();
+        crate::images::spawn_pull(&self.state, &req.image).map_err(Self::status_from)?;
+        Ok(Response::new(PullImageResponse {}))
+    }
+
+    async fn remove_image(
+        &self,
+        request: Request<RemoveImageRequest>,
+    ) -> Result<Response<RemoveImageResponse>, Status> {
+        let req = request.into_inner();
+        crate::images::remove(&self.state, &req.image, req.force)
+            .await
+            .map_err(Self::status_from)?;
+        Ok(Response::new(RemoveImageResponse {}))
+    }
+
+    async fn prune_images(
+        &self,
+        _request: Request<PruneImagesRequest>,
+    ) -> Result<Response<PruneImagesResponse>, Status> {
+        let pruned = crate::images::prune(&self.state)
+            .await
+            .map_err(Self::status_from)?;
+        tracing::info!("Pruned {} image(s)", pruned.removed.len());
+        Ok(Response::new(PruneImagesResponse {
+            removed: pruned.removed,
+            reclaimed_bytes: pruned.reclaimed_bytes,
+        }))
+    }
+
+    type EventStreamStream = Pin<Box<dyn Stream<Item = Result<WingsEvent, Status>> + Send>>;
 
+    #[allow(clippy::result_large_err)]
     async fn event_stream(
         &self,
-        _request: Request<Streaming<WingsEvent>>,
+        _request: Request<Streaming<PanelCommand>>,
     ) -> Result<Response<Self::EventStreamStream>, Status> {
-        // Panel commands stream (currently no Panel→Wings commands, just keepalive)
-        let stream = tokio_stream::wrappers::IntervalStream::new(
-            tokio::time::interval(Duration::from_secs(30)),
-        )
-        .map(|_| Ok(PanelCommand {
-            command_type: "keepalive".to_string(),
-            payload: vec![],
-        }));
-
-        Ok(Response::new(Box::pin(stream)))
+        // Panel commands are reserved for later
+        Ok(Response::new(Box::pin(self.panel_events())))
     }
 }
 
@@ -478,3 +730,68 @@ impl WingsService for WingsGrpcService {
 pub fn create_event_channel() -> (EventSender, EventReceiver) {
     mpsc::channel(MAX_EVENT_BUFFER)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::test_node;
+    use futures_util::StreamExt;
+
+    fn console(line: &str) -> WingsEvent {
+        WingsEvent {
+            event: Some(wings_event::Event::ConsoleOutput(ConsoleOutput {
+                uuid: "a".to_string(),
+                line: line.to_string(),
+                ..Default::default()
+            })),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_event_stream_forwards_events() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let (state, events) = test_node(tmp.path());
+        let service = WingsGrpcService::new(state.clone(), events);
+
+        // Buffered until the Panel connects
+        state.emit_event(console("before"));
+        let mut stream = service.panel_events();
+        assert_eq!(stream.next().await.unwrap().unwrap(), console("before"));
+        state.emit_event(console("during"));
+        assert_eq!(stream.next().await.unwrap().unwrap(), console("during"));
+
+        // Nothing is lost across a reconnect
+        drop(stream);
+        state.emit_event(console("after"));
+        let mut stream = service.panel_events();
+        assert_eq!(stream.next().await.unwrap().unwrap(), console("after"));
+    }
+
+    #[tokio::test]
+    async fn test_progress_leaves_room_for_completions() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let (state, mut events) = test_node(tmp.path());
+
+        // The Panel is away while a long job ticks
+        for i in 0..MAX_EVENT_BUFFER {
+            state.emit_event(console(&i.to_string()));
+        }
+        state.emit_event(WingsEvent {
+            event: Some(wings_event::Event::FileOperationProgress(FileOperationProgress {
+                uuid: "a".to_string(),
+                status: FileOperationStatus::FileOpCompleted.into(),
+                ..Default::default()
+            })),
+        });
+
+        let mut buffered = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            buffered.push(event);
+        }
+        assert!(buffered.len() < MAX_EVENT_BUFFER);
+        assert!(matches!(
+            buffered.last().and_then(|e| e.event.as_ref()),
+            Some(wings_event::Event::FileOperationProgress(_))
+        ));
+    }
+}
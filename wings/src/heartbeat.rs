@@ -30,7 +30,7 @@ pub fn start(state: Arc<AppState>, shutdown: tokio::sync::watch::Receiver<()>) {
 
 async fn run_heartbeat_loop(state: Arc<AppState>, mut shutdown: tokio::sync::watch::Receiver<()>) {
     // Mark initial value as seen so changed() waits for actual shutdown signal
-    let _ = *shutdown.borrow_and_update();
+    shutdown.borrow_and_update();
 
     let mut interval = time::interval(Duration::from_secs(30));
     let client = reqwest::Client::new();
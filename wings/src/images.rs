@@ -0,0 +1,393 @@
+//! Docker image management for the Panel: pulls with progress events,
+//! listing, removal and pruning of images no server uses.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bollard::image::{ListImagesOptions, RemoveImageOptions};
+use bollard::models::ImageSummary;
+use serde::Serialize;
+
+use crate::config::PullPolicy;
+use crate::docker::ServerConfig;
+use crate::error::WingsError;
+use crate::grpc::proto::{wings_event, ImagePullProgress, ImagePullStatus, WingsEvent};
+use crate::state::AppState;
+
+/// How often a running pull reports its progress.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageInfo {
+    pub id: String,
+    pub tags: Vec<String>,
+    pub size_bytes: u64,
+    pub created_ms: i64,
+    /// Servers whose stored config uses the image
+    pub servers: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct PruneResult {
+    pub removed: Vec<String>,
+    pub reclaimed_bytes: u64,
+}
+
+/// Canonical form of an image reference, as Docker lists it in repo tags:
+/// without the default registry and library namespace, and with an explicit
+/// tag unless pinned by digest.
+fn normalize_reference(image: &str) -> String {
+    let image = image
+        .strip_prefix("docker.io/")
+        .or_else(|| image.strip_prefix("index.docker.io/"))
+        .unwrap_or(image);
+    let image = image.strip_prefix("library/").unwrap_or(image);
+    let name = image.rsplit('/').next().unwrap_or(image);
+    if image.contains('@') || name.contains(':') {
+        image.to_string()
+    } else {
+        format!("{image}:latest")
+    }
+}
+
+/// The repo digest identifying `image`, e.g. `nginx@sha256:...`. Images
+/// pushed to several repositories have one digest per repository.
+fn pick_digest(image: &str, repo_digests: &[String]) -> Option<String> {
+    let reference = normalize_reference(image);
+    if reference.contains('@') {
+        return Some(reference);
+    }
+    let repo = match reference.rsplit_once(':') {
+        Some((repo, tag)) if !tag.contains('/') => repo,
+        _ => reference.as_str(),
+    };
+    repo_digests
+        .iter()
+        .find(|digest| digest.split_once('@').is_some_and(|(r, _)| r == repo))
+        .or(repo_digests.first())
+        .cloned()
+}
+
+/// Stored servers keyed by the normalized image they run and the digest
+/// it last resolved to.
+async fn servers_by_image(state: &AppState) -> HashMap<String, Vec<String>> {
+    let mut servers: HashMap<String, Vec<String>> = HashMap::new();
+    for config in state.list_server_configs().await {
+        let digest = config.image_digest.iter().map(|d| normalize_reference(d));
+        for image in std::iter::once(normalize_reference(&config.docker_image)).chain(digest) {
+            let users = servers.entry(image).or_default();
+            if !users.contains(&config.uuid) {
+                users.push(config.uuid.clone());
+            }
+        }
+    }
+    servers
+}
+
+/// Servers using an image under any of its tags or digests.
+fn image_users(servers: &HashMap<String, Vec<String>>, image: &ImageSummary) -> Vec<String> {
+    let mut users: Vec<String> = image
+        .repo_tags
+        .iter()
+        .chain(&image.repo_digests)
+        .filter_map(|reference| servers.get(reference))
+        .flatten()
+        .cloned()
+        .collect();
+    users.sort();
+    users.dedup();
+    users
+}
+
+fn emit_progress(
+    state: &AppState,
+    image: &str,
+    uuid: Option<&str>,
+    status: ImagePullStatus,
+    bytes: (u64, u64),
+    error_message: String,
+) {
+    state.emit_event(WingsEvent {
+        event: Some(wings_event::Event::ImagePullProgress(ImagePullProgress {
+            image: image.to_string(),
+            uuid: uuid.unwrap_or_default().to_string(),
+            status: status.into(),
+            bytes_downloaded: bytes.0,
+            bytes_total: bytes.1,
+            error_message,
+            timestamp_ms: chrono::Utc::now().timestamp_millis(),
+        })),
+    });
+}
+
+/// Pull an image, reporting progress as ImagePullProgress events. `uuid` is
+/// the server the pull is for, if any.
+pub async fn pull(state: &AppState, image: &str, uuid: Option<&str>) -> Result<(), WingsError> {
+    tracing::info!(image, "Pulling image");
+    let report = |status: ImagePullStatus, bytes: (u64, u64), error: String| {
+        emit_progress(state, image, uuid, status, bytes, error)
+    };
+    let mut bytes = (0, 0);
+    let mut last_report = Instant::now();
+    report(ImagePullStatus::ImagePullRunning, bytes, String::new());
+
+    let result = state
+        .docker
+        .pull_image(image, |progress| {
+            bytes = (progress.downloaded(), progress.total());
+            if last_report.elapsed() >= PROGRESS_INTERVAL {
+                last_report = Instant::now();
+                report(ImagePullStatus::ImagePullRunning, bytes, String::new());
+            }
+        })
+        .await;
+
+    match &result {
+        Ok(()) => report(ImagePullStatus::ImagePullCompleted, bytes, String::new()),
+        Err(e) => {
+            tracing::warn!(image, error = %e, "Image pull failed");
+            report(ImagePullStatus::ImagePullFailed, bytes, e.to_string());
+        }
+    }
+    result
+}
+
+/// Make sure an image is present as `policy` asks, returning the digest it
+/// resolved to. A failed pull falls back to the local image if there is one.
+pub async fn ensure(
+    state: &AppState,
+    image: &str,
+    policy: PullPolicy,
+    uuid: Option<&str>,
+) -> Result<Option<String>, WingsError> {
+    let mut local = state.docker.local_image(image).await?;
+    let should_pull = match policy {
+        PullPolicy::Always => true,
+        PullPolicy::IfNotPresent => local.is_none(),
+        PullPolicy::Never => false,
+    };
+    if should_pull {
+        match pull(state, image, uuid).await {
+            Ok(()) => local = state.docker.local_image(image).await?,
+            Err(e) if local.is_some() => {
+                tracing::warn!(image, error = %e, "Pull failed, using the local image")
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    let local = local.ok_or_else(|| {
+        WingsError::Config(format!(
+            "Image {image} is not present and the pull policy forbids pulling it"
+        ))
+    })?;
+    Ok(pick_digest(
+        image,
+        local.repo_digests.as_deref().unwrap_or_default(),
+    ))
+}
+
+/// Make sure a server's image is present under its pull policy and record
+/// the digest it resolved to, in `config` and in the stored config.
+pub async fn ensure_server_image(
+    state: &AppState,
+    config: &mut ServerConfig,
+) -> Result<(), WingsError> {
+    let policy = config
+        .pull_policy
+        .unwrap_or(state.config.docker.pull_policy);
+    let digest = ensure(state, &config.docker_image, policy, Some(&config.uuid)).await?;
+    if let Some(mut stored) = state.get_server_config(&config.uuid).await {
+        if stored.docker_image == config.docker_image && stored.image_digest != digest {
+            stored.image_digest = digest.clone();
+            state.store_server_config(&stored).await;
+        }
+    }
+    config.image_digest = digest;
+    Ok(())
+}
+
+/// Start pulling an image in the background.
+pub fn spawn_pull(state: &Arc<AppState>, image: &str) -> Result<(), WingsError> {
+    if image.trim().is_empty() {
+        return Err(WingsError::InvalidRequest("Missing image".to_string()));
+    }
+    let state = state.clone();
+    let image = image.to_string();
+    tokio::spawn(async move {
+        let _ = pull(&state, &image, None).await;
+    });
+    Ok(())
+}
+
+/// Images present on this node.
+pub async fn list(state: &AppState) -> Result<Vec<ImageInfo>, WingsError> {
+    let servers = servers_by_image(state).await;
+    let summaries = state
+        .docker
+        .client()
+        .list_images(None::<ListImagesOptions<String>>)
+        .await?;
+    Ok(summaries
+        .into_iter()
+        .map(|image| ImageInfo {
+            servers: image_users(&servers, &image),
+            id: image.id,
+            tags: image.repo_tags,
+            size_bytes: image.size.max(0) as u64,
+            created_ms: image.created * 1000,
+        })
+        .collect())
+}
+
+/// Remove an image. Images used by a server on this node need `force`.
+pub async fn remove(state: &AppState, image: &str, force: bool) -> Result<(), WingsError> {
+    if !force {
+        if let Some(users) = servers_by_image(state)
+            .await
+            .get(&normalize_reference(image))
+        {
+            return Err(WingsError::InvalidRequest(format!(
+                "Image {image} is used by server(s) {}",
+                users.join(", ")
+            )));
+        }
+    }
+    state
+        .docker
+        .client()
+        .remove_image(
+            image,
+            Some(RemoveImageOptions {
+                force,
+                ..Default::default()
+            }),
+            None,
+        )
+        .await?;
+    tracing::info!(image, "Removed image");
+    Ok(())
+}
+
+/// Remove every image no stored server config uses. Docker refuses to
+/// remove images a container still runs on, so those are kept.
+pub async fn prune(state: &AppState) -> Result<PruneResult, WingsError> {
+    let servers = servers_by_image(state).await;
+    let images = state
+        .docker
+        .client()
+        .list_images(None::<ListImagesOptions<String>>)
+        .await?;
+
+    let mut result = PruneResult::default();
+    for image in images {
+        // Images pulled by digest have no tags
+        if !image_users(&servers, &image).is_empty() {
+            continue;
+        }
+        match state
+            .docker
+            .client()
+            .remove_image(&image.id, None, None)
+            .await
+        {
+            Ok(_) => {
+                let name = image.repo_tags.first().unwrap_or(&image.id);
+                tracing::info!(image = %name, "Pruned image");
+                result.removed.push(name.clone());
+                result.reclaimed_bytes += image.size.max(0) as u64;
+            }
+            Err(e) => tracing::debug!(image = %image.id, error = %e, "Keeping image"),
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_digest() {
+        let digests = [
+            "mirror.local/nginx@sha256:aaa".to_string(),
+            "nginx@sha256:bbb".to_string(),
+        ];
+        assert_eq!(
+            pick_digest("nginx:1.27", &digests).as_deref(),
+            Some("nginx@sha256:bbb")
+        );
+        assert_eq!(
+            pick_digest("docker.io/library/nginx", &digests).as_deref(),
+            Some("nginx@sha256:bbb")
+        );
+        assert_eq!(
+            pick_digest("mirror.local/nginx", &digests).as_deref(),
+            Some("mirror.local/nginx@sha256:aaa")
+        );
+        // Retagged locally: any digest of the image still identifies it
+        assert_eq!(
+            pick_digest("game:stable", &digests).as_deref(),
+            Some("mirror.local/nginx@sha256:aaa")
+        );
+        assert_eq!(
+            pick_digest("alpine@sha256:ccc", &[]).as_deref(),
+            Some("alpine@sha256:ccc")
+        );
+        // Built locally, never pushed
+        assert_eq!(pick_digest("game:dev", &[]), None);
+    }
+
+    #[test]
+    fn test_normalize_reference() {
+        assert_eq!(normalize_reference("nginx"), "nginx:latest");
+        assert_eq!(
+            normalize_reference("docker.io/library/nginx:1.27"),
+            "nginx:1.27"
+        );
+        assert_eq!(
+            normalize_reference("docker.io/itzg/minecraft-server"),
+            "itzg/minecraft-server:latest"
+        );
+        assert_eq!(
+            normalize_reference("ghcr.io/nexus/java:21"),
+            "ghcr.io/nexus/java:21"
+        );
+        // A registry port is not a tag
+        assert_eq!(
+            normalize_reference("registry.local:5000/java"),
+            "registry.local:5000/java:latest"
+        );
+        assert_eq!(
+            normalize_reference("alpine@sha256:abc"),
+            "alpine@sha256:abc"
+        );
+    }
+
+    #[test]
+    fn test_image_users() {
+        let servers = HashMap::from([
+            ("nginx:1.27".to_string(), vec!["a".to_string()]),
+            ("alpine@sha256:ccc".to_string(), vec!["b".to_string()]),
+            (
+                "nginx@sha256:bbb".to_string(),
+                vec!["a".to_string(), "c".to_string()],
+            ),
+        ]);
+        let image = |tags: &[&str], digests: &[&str]| ImageSummary {
+            repo_tags: tags.iter().map(|t| t.to_string()).collect(),
+            repo_digests: digests.iter().map(|d| d.to_string()).collect(),
+            ..Default::default()
+        };
+        assert_eq!(
+            image_users(&servers, &image(&["nginx:1.27"], &["nginx@sha256:bbb"])),
+            ["a", "c"]
+        );
+        // Pulled by digest, so untagged
+        assert_eq!(
+            image_users(&servers, &image(&[], &["alpine@sha256:ccc"])),
+            ["b"]
+        );
+        assert!(image_users(&servers, &image(&["nginx:1.26"], &["nginx@sha256:ddd"])).is_empty());
+    }
+}
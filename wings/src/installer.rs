@@ -1,14 +1,79 @@
+use std::sync::Arc;
+
 use bollard::container::{
     Config as ContainerConfig, CreateContainerOptions, LogOutput, LogsOptions,
     RemoveContainerOptions, StartContainerOptions, WaitContainerOptions,
 };
-use bollard::image::CreateImageOptions;
 use bollard::models::HostConfig;
 use futures_util::StreamExt;
 
 use crate::docker::{DockerManager, ServerConfig};
 use crate::error::WingsError;
+use crate::grpc::proto::{wings_event, ServerInstallComplete, ServerInstallFailed, WingsEvent};
+use crate::images;
+use crate::state::AppState;
+
+/// Install script and the image it runs in.
+pub struct InstallScript {
+    pub script: String,
+    pub image: String,
+}
+
+/// Set a server up in the background: fetch its image, create the container
+/// if asked to and run the install script. The outcome is reported as
+/// ServerInstallComplete or ServerInstallFailed.
+pub fn spawn_setup(
+    state: Arc<AppState>,
+    mut config: ServerConfig,
+    create_container: bool,
+    install: Option<InstallScript>,
+) {
+    tokio::spawn(async move {
+        let result = async {
+            images::ensure_server_image(&state, &mut config).await?;
+            if create_container {
+                state.docker.create_container(&config).await?;
+            }
+            if let Some(install) = install {
+                let policy = state.config.docker.pull_policy;
+                images::ensure(&state, &install.image, policy, Some(&config.uuid)).await?;
+                let panel_auth = format!("{}.{}", state.config.panel.token_id, state.config.panel.token);
+                let output = run_install(
+                    &state.docker,
+                    &config,
+                    &install.script,
+                    &install.image,
+                    Some(&state.config.panel.url),
+                    Some(&panel_auth),
+                )
+                .await?;
+                tracing::info!(uuid = %config.uuid, lines = output.len(), "Install completed");
+            }
+            Ok::<_, WingsError>(())
+        }
+        .await;
+
+        let timestamp_ms = chrono::Utc::now().timestamp_millis();
+        let event = match result {
+            Ok(()) => wings_event::Event::InstallComplete(ServerInstallComplete {
+                uuid: config.uuid.clone(),
+                timestamp_ms,
+            }),
+            Err(e) => {
+                tracing::error!(uuid = %config.uuid, error = %e, "Install failed");
+                wings_event::Event::InstallFailed(ServerInstallFailed {
+                    uuid: config.uuid.clone(),
+                    error_message: e.to_string(),
+                    timestamp_ms,
+                })
+            }
+        };
+        state.emit_event(WingsEvent { event: Some(event) });
+    });
+}
 
+/// Run an install script in a throwaway container. The install image must
+/// already be present.
 pub async fn run_install(
     docker: &DockerManager,
     server_config: &ServerConfig,
@@ -41,23 +106,6 @@ pub async fn run_install(
     // Access the inner Docker client via a helper
     let client = docker.client();
 
-    // Pull install image first
-    tracing::info!("Pulling install image: {install_image}");
-    let mut pull_stream = client.create_image(
-        Some(CreateImageOptions {
-            from_image: install_image.to_string(),
-            ..Default::default()
-        }),
-        None,
-        None,
-    );
-    while let Some(result) = pull_stream.next().await {
-        if let Err(e) = result {
-            tracing::error!("Failed to pull install image {install_image}: {e}");
-            return Err(WingsError::Docker(e));
-        }
-    }
-
     // Clean up any leftover container
     let _ = client
         .remove_container(
@@ -147,16 +195,22 @@ pub async fn run_install(
                     }),
                 )
                 .await;
-            return Err(WingsError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!(
-                    "Install script exited with code {}",
-                    wait_result.status_code
-                ),
-            )));
+            return Err(WingsError::Io(std::io::Error::other(format!(
+                "Install script exited with code {}",
+                wait_result.status_code
+            ))));
         }
     }
 
+    // Install scripts run as root; hand the files to the container user
+    let volume_path = std::path::PathBuf::from(&server_config.volume_path);
+    let owner = docker.container_user();
+    match tokio::task::spawn_blocking(move || crate::files::chown_recursive(&volume_path, owner)).await {
+        Ok(Err(e)) => tracing::warn!("Failed to chown files after install: {e}"),
+        Err(e) => tracing::warn!("Failed to chown files after install: {e}"),
+        Ok(Ok(())) => {}
+    }
+
     // Notify Panel of install success
     if let (Some(url), Some(auth)) = (panel_url, panel_auth) {
         let callback_url = format!(
@@ -0,0 +1,293 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use dashmap::DashMap;
+use serde::Serialize;
+
+use crate::error::WingsError;
+use crate::grpc::proto::{wings_event, FileOperationProgress, FileOperationStatus, WingsEvent};
+use crate::state::AppState;
+
+/// How often a running job reports its progress.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Operations that replace or remove a server's files, of which only one
+/// runs for a server at a time.
+pub const SERVER_DATA_JOBS: &[&str] = &["backup", "restore", "prune", "transfer"];
+
+/// Shared progress counters for a long-running file operation.
+///
+/// The operation itself runs on a blocking thread and only touches these
+/// atomics; the async side polls them to emit progress events.
+#[derive(Debug, Default)]
+pub struct JobProgress {
+    bytes_total: AtomicU64,
+    bytes_processed: AtomicU64,
+    cancelled: AtomicBool,
+}
+
+impl JobProgress {
+    pub fn set_total(&self, bytes: u64) {
+        self.bytes_total.store(bytes, Ordering::Relaxed);
+    }
+
+    pub fn add_processed(&self, bytes: u64) {
+        self.bytes_processed.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn total(&self) -> u64 {
+        self.bytes_total.load(Ordering::Relaxed)
+    }
+
+    pub fn processed(&self) -> u64 {
+        self.bytes_processed.load(Ordering::Relaxed)
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Returns an error if the job has been cancelled, for use in copy loops.
+    pub fn check_cancelled(&self) -> std::io::Result<()> {
+        if self.is_cancelled() {
+            Err(std::io::Error::other("operation cancelled"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A file job currently running for a server.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobInfo {
+    pub id: String,
+    pub server_uuid: String,
+    pub operation: &'static str,
+    pub bytes_processed: u64,
+    pub bytes_total: u64,
+}
+
+struct Job {
+    server_uuid: String,
+    operation: &'static str,
+    progress: Arc<JobProgress>,
+}
+
+/// Registry of running background file jobs, keyed by job id.
+#[derive(Default)]
+pub struct JobRegistry {
+    jobs: DashMap<String, Job>,
+    /// Held while checking for conflicting jobs and registering a new one
+    admission: Mutex<()>,
+}
+
+impl JobRegistry {
+    fn insert(&self, server_uuid: &str, operation: &'static str) -> (String, Arc<JobProgress>) {
+        let job_id = uuid::Uuid::new_v4().to_string();
+        let progress = Arc::new(JobProgress::default());
+        self.jobs.insert(
+            job_id.clone(),
+            Job {
+                server_uuid: server_uuid.to_string(),
+                operation,
+                progress: progress.clone(),
+            },
+        );
+        (job_id, progress)
+    }
+
+    /// Register a job unless one of the `exclusive` operations is already
+    /// running for the server. Checking and registering happen under one
+    /// lock, so of two concurrent requests only one gets through.
+    fn insert_exclusive(
+        &self,
+        server_uuid: &str,
+        operation: &'static str,
+        exclusive: &[&str],
+    ) -> Result<(String, Arc<JobProgress>), WingsError> {
+        let _admission = self.admission.lock().unwrap_or_else(|e| e.into_inner());
+        let running = self
+            .jobs
+            .iter()
+            .find(|job| job.server_uuid == server_uuid && exclusive.contains(&job.operation))
+            .map(|job| job.operation);
+        if let Some(running) = running {
+            return Err(WingsError::InvalidRequest(format!(
+                "A {running} is already running for this server"
+            )));
+        }
+        Ok(self.insert(server_uuid, operation))
+    }
+
+    pub fn list(&self, server_uuid: &str) -> Vec<JobInfo> {
+        self.jobs
+            .iter()
+            .filter(|job| job.server_uuid == server_uuid)
+            .map(|job| JobInfo {
+                id: job.key().clone(),
+                server_uuid: job.server_uuid.clone(),
+                operation: job.operation,
+                bytes_processed: job.progress.processed(),
+                bytes_total: job.progress.total(),
+            })
+            .collect()
+    }
+
+    /// Request cancellation of a job. Returns false if no such job is running
+    /// for the server.
+    pub fn cancel(&self, server_uuid: &str, job_id: &str) -> bool {
+        match self.jobs.get(job_id) {
+            Some(job) if job.server_uuid == server_uuid => {
+                job.progress.cancel();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Run `work` on a blocking thread as a background job for `server_uuid`.
+///
+/// Progress events are emitted while the job runs, followed by a final
+/// completed/failed/cancelled event. Returns the job id immediately.
+pub fn spawn<F>(state: Arc<AppState>, server_uuid: &str, operation: &'static str, work: F) -> String
+where
+    F: FnOnce(&JobProgress) -> Result<(), WingsError> + Send + 'static,
+{
+    let (job_id, progress) = state.jobs.insert(server_uuid, operation);
+    run(state, server_uuid, operation, job_id.clone(), progress, work);
+    job_id
+}
+
+/// Like [`spawn`], but refused while a job for any of the `exclusive`
+/// operations is running for the server.
+pub fn spawn_exclusive<F>(
+    state: Arc<AppState>,
+    server_uuid: &str,
+    operation: &'static str,
+    exclusive: &[&str],
+    work: F,
+) -> Result<String, WingsError>
+where
+    F: FnOnce(&JobProgress) -> Result<(), WingsError> + Send + 'static,
+{
+    let (job_id, progress) = state.jobs.insert_exclusive(server_uuid, operation, exclusive)?;
+    run(state, server_uuid, operation, job_id.clone(), progress, work);
+    Ok(job_id)
+}
+
+fn run<F>(
+    state: Arc<AppState>,
+    server_uuid: &str,
+    operation: &'static str,
+    id: String,
+    progress: Arc<JobProgress>,
+    work: F,
+) where
+    F: FnOnce(&JobProgress) -> Result<(), WingsError> + Send + 'static,
+{
+    let server_uuid = server_uuid.to_string();
+    tokio::spawn(async move {
+        let worker_progress = progress.clone();
+        let mut handle = tokio::task::spawn_blocking(move || work(&worker_progress));
+        let mut ticker = tokio::time::interval(PROGRESS_INTERVAL);
+
+        let result = loop {
+            tokio::select! {
+                res = &mut handle => break res,
+                _ = ticker.tick() => {
+                    emit_progress(&state, &server_uuid, &id, operation, &progress, FileOperationStatus::FileOpRunning, String::new());
+                }
+            }
+        };
+
+        let (status, error) = match result {
+            Ok(Ok(())) => (FileOperationStatus::FileOpCompleted, String::new()),
+            Ok(Err(_)) if progress.is_cancelled() => {
+                (FileOperationStatus::FileOpCancelled, String::new())
+            }
+            Ok(Err(e)) => (FileOperationStatus::FileOpFailed, e.to_string()),
+            Err(e) => (FileOperationStatus::FileOpFailed, e.to_string()),
+        };
+        if !error.is_empty() {
+            tracing::warn!(uuid = %server_uuid, job = %id, operation, error = %error, "File job failed");
+        }
+        emit_progress(
+            &state,
+            &server_uuid,
+            &id,
+            operation,
+            &progress,
+            status,
+            error,
+        );
+        state.jobs.jobs.remove(&id);
+    });
+}
+
+fn emit_progress(
+    state: &AppState,
+    server_uuid: &str,
+    job_id: &str,
+    operation: &str,
+    progress: &JobProgress,
+    status: FileOperationStatus,
+    error_message: String,
+) {
+    state.emit_event(WingsEvent {
+        event: Some(wings_event::Event::FileOperationProgress(
+            FileOperationProgress {
+                uuid: server_uuid.to_string(),
+                job_id: job_id.to_string(),
+                operation: operation.to_string(),
+                bytes_processed: progress.processed(),
+                bytes_total: progress.total(),
+                status: status.into(),
+                error_message,
+                timestamp_ms: chrono::Utc::now().timestamp_millis(),
+            },
+        )),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exclusive_jobs_admit_one_at_a_time() {
+        let registry = Arc::new(JobRegistry::default());
+        let barrier = Arc::new(std::sync::Barrier::new(8));
+        let admitted = (0..8)
+            .map(|_| {
+                let (registry, barrier) = (registry.clone(), barrier.clone());
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    registry
+                        .insert_exclusive("a", "backup", &["backup", "restore"])
+                        .is_ok()
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .filter(|&admitted| admitted)
+            .count();
+        assert_eq!(admitted, 1);
+
+        // Other servers and operations outside the group are unaffected
+        assert!(registry.insert_exclusive("b", "restore", &["backup", "restore"]).is_ok());
+        assert!(registry.insert_exclusive("a", "copy", &["copy"]).is_ok());
+        match registry.insert_exclusive("a", "restore", &["backup", "restore"]) {
+            Err(WingsError::InvalidRequest(message)) => {
+                assert_eq!(message, "A backup is already running for this server")
+            }
+            other => panic!("unexpected result {other:?}"),
+        }
+    }
+}
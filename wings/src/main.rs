@@ -1,17 +1,30 @@
 mod auth;
+mod bandwidth;
+mod backups;
 mod config;
+mod containers;
 mod console;
 mod docker;
 mod error;
 mod files;
 pub mod grpc;
 pub mod heartbeat;
+mod images;
 mod installer;
+mod jobs;
+mod ports;
+mod registry;
 mod routes;
+mod s3;
+mod schedules;
 mod server;
+mod sftp;
+mod snapshots;
 mod state;
+mod transfer;
+mod watcher;
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use clap::{Parser, Subcommand};
 
@@ -72,7 +85,7 @@ async fn async_main() -> anyhow::Result<()> {
     }
 }
 
-async fn run_daemon(config_path: &PathBuf) -> anyhow::Result<()> {
+async fn run_daemon(config_path: &Path) -> anyhow::Result<()> {
     let cfg = config::Config::load(config_path)
         .map_err(|e| anyhow::anyhow!("Failed to load config: {e}"))?;
 
@@ -92,7 +105,7 @@ async fn run_daemon(config_path: &PathBuf) -> anyhow::Result<()> {
     );
 
     // Ensure Docker network
-    let docker = crate::docker::DockerManager::new(&cfg.docker.socket)?;
+    let docker = crate::docker::DockerManager::new(&cfg.docker)?;
     if let Err(e) = docker.ensure_network().await {
         tracing::warn!("Failed to ensure Docker network: {e}");
     }
@@ -100,7 +113,10 @@ async fn run_daemon(config_path: &PathBuf) -> anyhow::Result<()> {
         tracing::warn!("Failed to attach containers to network: {e}");
     }
 
-    let state = std::sync::Arc::new(state::AppState::new(cfg.clone(), docker));
+    // Create gRPC event channel
+    let (event_tx, event_rx) = grpc::create_event_channel();
+
+    let state = std::sync::Arc::new(state::AppState::new(cfg.clone(), docker, event_tx));
 
     // Reconstruct server registry from existing containers
     {
@@ -149,13 +165,21 @@ async fn run_daemon(config_path: &PathBuf) -> anyhow::Result<()> {
     // Start heartbeat
     heartbeat::start(state.clone(), shutdown_rx.clone());
 
-    // Create gRPC event channel
-    let (event_tx, _event_rx) = grpc::create_event_channel();
+    // Start schedules synced by the Panel
+    schedules::start(&state);
+
+    // Shape the bandwidth of servers as their containers start
+    bandwidth::start(&state);
+
+    // Start SFTP server
+    if let Err(e) = sftp::start(state.clone(), shutdown_rx.clone()).await {
+        tracing::error!("Failed to start SFTP server: {e}");
+    }
 
     // Start gRPC server
     let grpc_port = cfg.api.port + 1; // gRPC on next port (e.g., 8081)
     let grpc_addr = format!("{}:{}", cfg.api.host, grpc_port).parse()?;
-    let grpc_service = grpc::WingsGrpcService::new(state.clone(), event_tx);
+    let grpc_service = grpc::WingsGrpcService::new(state.clone(), event_rx);
     let grpc_shutdown_rx = shutdown_rx.clone();
 
     tokio::spawn(async move {
@@ -267,14 +291,22 @@ async fn run_configure() -> anyhow::Result<()> {
         },
         docker: config::DockerConfig {
             socket: "/var/run/docker.sock".to_string(),
+            user: None,
+            registries: Vec::new(),
+            pull_policy: config::PullPolicy::default(),
+            hardening: config::HardeningConfig::default(),
+            network: config::NetworkConfig::default(),
         },
         storage: config::StorageConfig {
             data_dir: "/var/lib/nexus-wings/data".to_string(),
+            backup_dir: "/var/lib/nexus-wings/backups".to_string(),
+            s3: None,
         },
         logging: config::LoggingConfig {
             level: "info".to_string(),
             file: None,
         },
+        sftp: config::SftpConfig::default(),
     };
 
     let config_dir = std::path::Path::new("/etc/nexus-wings");
@@ -287,7 +319,7 @@ async fn run_configure() -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn run_diagnostics(config_path: &PathBuf) -> anyhow::Result<()> {
+async fn run_diagnostics(config_path: &Path) -> anyhow::Result<()> {
     println!("Nexus Wings — Diagnostics");
     println!("=========================\n");
 
@@ -299,7 +331,7 @@ async fn run_diagnostics(config_path: &PathBuf) -> anyhow::Result<()> {
 
             // Check Docker
             print!("Docker connection... ");
-            match docker::DockerManager::new(&cfg.docker.socket) {
+            match docker::DockerManager::new(&cfg.docker) {
                 Ok(dm) => match dm.docker_version().await {
                     Ok(version) => println!("OK (Docker {version})"),
                     Err(e) => println!("FAILED ({e})"),
@@ -307,6 +339,15 @@ async fn run_diagnostics(config_path: &PathBuf) -> anyhow::Result<()> {
                 Err(e) => println!("FAILED ({e})"),
             }
 
+            // Check private registry logins
+            for registry in &cfg.docker.registries {
+                print!("Registry {}... ", registry.host);
+                match registry::check_login(registry).await {
+                    Ok(()) => println!("OK ({})", registry.username),
+                    Err(e) => println!("FAILED ({e})"),
+                }
+            }
+
             // Check Panel connectivity
             print!("Panel connectivity... ");
             let panel_url = format!("{}/api/v1/health", cfg.panel.url.trim_end_matches('/'));
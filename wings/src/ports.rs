@@ -0,0 +1,244 @@
+//! Host ports taken by servers on this node, built from stored server
+//! configs and the ports live containers publish. Allocations that collide
+//! are rejected up front instead of failing when Docker starts the server.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::IpAddr;
+
+use bollard::models::PortTypeEnum;
+use serde::Serialize;
+
+use crate::docker::ServerConfig;
+use crate::error::WingsError;
+use crate::state::AppState;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct UsedPort {
+    pub ip: String,
+    pub port: u16,
+    pub protocol: &'static str,
+}
+
+impl UsedPort {
+    fn is_wildcard(&self) -> bool {
+        matches!(self.ip.as_str(), "0.0.0.0" | "::")
+    }
+
+    /// Whether binding both would fail. A wildcard bind takes the port on
+    /// every address.
+    fn overlaps(&self, other: &UsedPort) -> bool {
+        self.port == other.port
+            && self.protocol == other.protocol
+            && (self.ip == other.ip || self.is_wildcard() || other.is_wildcard())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ServerPorts {
+    pub uuid: String,
+    pub ports: Vec<UsedPort>,
+}
+
+/// Host ports a server's config binds.
+fn config_ports(config: &ServerConfig) -> Vec<UsedPort> {
+    config
+        .port_mappings
+        .iter()
+        .flat_map(|pm| {
+            pm.protocol.docker_protocols().iter().map(|protocol| UsedPort {
+                ip: pm.host_ip().to_string(),
+                port: pm.host_port,
+                protocol,
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Default)]
+pub struct PortRegistry {
+    servers: BTreeMap<String, BTreeSet<UsedPort>>,
+}
+
+impl PortRegistry {
+    fn add(&mut self, uuid: &str, port: UsedPort) {
+        self.servers.entry(uuid.to_string()).or_default().insert(port);
+    }
+
+    fn add_config(&mut self, config: &ServerConfig) {
+        for port in config_ports(config) {
+            self.add(&config.uuid, port);
+        }
+    }
+
+    /// Ports of every stored server and every running server container.
+    pub async fn build(state: &AppState) -> Result<Self, WingsError> {
+        let mut registry = Self::default();
+        for config in state.list_server_configs().await {
+            registry.add_config(&config);
+        }
+        for (uuid, ports) in state.docker.published_ports().await? {
+            for port in ports {
+                let protocol = match port.typ {
+                    Some(PortTypeEnum::TCP) => "tcp",
+                    Some(PortTypeEnum::UDP) => "udp",
+                    _ => continue,
+                };
+                let (Some(ip), Some(host_port)) = (port.ip, port.public_port) else {
+                    continue;
+                };
+                // Docker lists wildcard binds once per address family
+                let ip = if ip == "::" { "0.0.0.0".to_string() } else { ip };
+                registry.add(
+                    &uuid,
+                    UsedPort {
+                        ip,
+                        port: host_port,
+                        protocol,
+                    },
+                );
+            }
+        }
+        Ok(registry)
+    }
+
+    /// Make sure `config` only binds valid addresses and ports no other
+    /// server, nor another of its own mappings, already takes.
+    pub fn check(&self, config: &ServerConfig) -> Result<(), WingsError> {
+        let ports = config_ports(config);
+        for (i, port) in ports.iter().enumerate() {
+            if port.ip.parse::<IpAddr>().is_err() {
+                return Err(WingsError::InvalidRequest(format!(
+                    "Invalid bind IP {} for port {}",
+                    port.ip, port.port
+                )));
+            }
+            if ports[..i].iter().any(|other| other.overlaps(port)) {
+                return Err(WingsError::PortConflict {
+                    port: port.clone(),
+                    server: config.uuid.clone(),
+                });
+            }
+            let taken = self
+                .servers
+                .iter()
+                .filter(|(uuid, _)| **uuid != config.uuid)
+                .find(|(_, used)| used.iter().any(|other| other.overlaps(port)));
+            if let Some((uuid, _)) = taken {
+                return Err(WingsError::PortConflict {
+                    port: port.clone(),
+                    server: uuid.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn into_servers(self) -> Vec<ServerPorts> {
+        self.servers
+            .into_iter()
+            .map(|(uuid, ports)| ServerPorts {
+                uuid,
+                ports: ports.into_iter().collect(),
+            })
+            .collect()
+    }
+}
+
+/// Reject a server config whose ports collide with another server's.
+pub async fn check(state: &AppState, config: &ServerConfig) -> Result<(), WingsError> {
+    PortRegistry::build(state).await?.check(config)
+}
+
+/// Check a config's ports and store it, so that no other config can take
+/// the same ports in between.
+pub async fn claim(state: &AppState, config: &ServerConfig) -> Result<(), WingsError> {
+    let _guard = state.port_claims.lock().await;
+    check(state, config).await?;
+    state.store_server_config(config).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::docker::{PortMapping, PortProtocol};
+
+    fn server(uuid: &str, ports: &[(u16, PortProtocol, Option<&str>)]) -> ServerConfig {
+        let mut config: ServerConfig = serde_json::from_value(serde_json::json!({
+            "uuid": uuid,
+            "docker_image": "ghcr.io/nexus/java:21",
+            "startup_command": "java -jar server.jar",
+            "environment": {},
+            "memory_limit": 1024,
+            "cpu_limit": 100,
+            "disk_limit": 0,
+            "port_mappings": [],
+            "volume_path": format!("/data/{uuid}"),
+        }))
+        .unwrap();
+        config.port_mappings = ports
+            .iter()
+            .map(|&(port, protocol, bind_ip)| PortMapping {
+                host_port: port,
+                container_port: port,
+                protocol,
+                bind_ip: bind_ip.map(str::to_string),
+                primary: false,
+            })
+            .collect();
+        config
+    }
+
+    #[test]
+    fn test_port_conflicts() {
+        let mut registry = PortRegistry::default();
+        registry.add_config(&server("a", &[(25565, PortProtocol::Tcp, None)]));
+        registry.add_config(&server(
+            "b",
+            &[(27015, PortProtocol::Both, Some("10.0.0.2"))],
+        ));
+
+        let conflict = |config: &ServerConfig| match registry.check(config) {
+            Err(WingsError::PortConflict { server, .. }) => Some(server),
+            Err(e) => panic!("unexpected error: {e}"),
+            Ok(()) => None,
+        };
+
+        // Same port on another protocol or address is free
+        assert_eq!(conflict(&server("c", &[(25565, PortProtocol::Udp, None)])), None);
+        assert_eq!(
+            conflict(&server("c", &[(27015, PortProtocol::Tcp, Some("10.0.0.3"))])),
+            None
+        );
+        // A server doesn't conflict with itself
+        assert_eq!(conflict(&server("a", &[(25565, PortProtocol::Both, None)])), None);
+
+        assert_eq!(
+            conflict(&server("c", &[(25565, PortProtocol::Tcp, Some("10.0.0.2"))])).as_deref(),
+            Some("a")
+        );
+        assert_eq!(
+            conflict(&server("c", &[(27015, PortProtocol::Udp, None)])).as_deref(),
+            Some("b")
+        );
+        // Mappings of one server can collide too
+        assert_eq!(
+            conflict(&server(
+                "c",
+                &[(7777, PortProtocol::Both, None), (7777, PortProtocol::Udp, None)]
+            ))
+            .as_deref(),
+            Some("c")
+        );
+    }
+
+    #[test]
+    fn test_invalid_bind_ip() {
+        let registry = PortRegistry::default();
+        let config = server("c", &[(25565, PortProtocol::Tcp, Some("10.0.0"))]);
+        assert!(matches!(
+            registry.check(&config),
+            Err(WingsError::InvalidRequest(_))
+        ));
+    }
+}
@@ -0,0 +1,197 @@
+//! Private registry credentials: picking them by image host for pulls and
+//! checking them against the registry.
+
+use bollard::auth::DockerCredentials;
+
+use crate::config::RegistryConfig;
+use crate::error::WingsError;
+
+/// Host Docker Hub images are pulled from when no registry is named.
+const DOCKER_HUB: &str = "docker.io";
+
+/// Canonical name of a registry host, folding Docker Hub's aliases.
+fn canonical_host(host: &str) -> &str {
+    let host = host
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .split('/')
+        .next()
+        .unwrap_or_default();
+    match host {
+        "index.docker.io" | "registry-1.docker.io" | "registry.hub.docker.com" => DOCKER_HUB,
+        host => host,
+    }
+}
+
+/// Registry an image is pulled from. As in Docker, the first path component
+/// names a registry only if it looks like a host.
+pub fn image_registry(image: &str) -> &str {
+    match image.split_once('/') {
+        Some((first, _)) if first.contains(['.', ':']) || first == "localhost" => {
+            canonical_host(first)
+        }
+        _ => DOCKER_HUB,
+    }
+}
+
+/// Credentials to pull `image` with, if any are configured for its registry.
+pub fn credentials(registries: &[RegistryConfig], image: &str) -> Option<DockerCredentials> {
+    let host = image_registry(image);
+    let registry = registries
+        .iter()
+        .find(|r| canonical_host(&r.host).eq_ignore_ascii_case(host))?;
+    Some(DockerCredentials {
+        username: Some(registry.username.clone()),
+        password: Some(registry.password.clone()),
+        serveraddress: Some(host.to_string()),
+        ..Default::default()
+    })
+}
+
+/// Parameters of a `WWW-Authenticate: Bearer realm="...",service="..."`
+/// challenge.
+fn parse_bearer_challenge(header: &str) -> Option<(String, Option<String>)> {
+    let params = header.strip_prefix("Bearer ")?;
+    let mut realm = None;
+    let mut service = None;
+    for param in params.split(',') {
+        let Some((key, value)) = param.trim().split_once('=') else {
+            continue;
+        };
+        let value = value.trim_matches('"').to_string();
+        match key {
+            "realm" => realm = Some(value),
+            "service" => service = Some(value),
+            _ => {}
+        }
+    }
+    Some((realm?, service))
+}
+
+/// Log in to a registry with its configured credentials, the way
+/// `docker login` does.
+pub async fn check_login(registry: &RegistryConfig) -> Result<(), WingsError> {
+    let host = canonical_host(&registry.host);
+    let base = if host == DOCKER_HUB {
+        "https://registry-1.docker.io".to_string()
+    } else {
+        format!("https://{host}")
+    };
+    let client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(10))
+        .build()
+        .map_err(|e| WingsError::Io(std::io::Error::other(e.to_string())))?;
+    let unreachable = |e: reqwest::Error| {
+        WingsError::Io(std::io::Error::other(format!(
+            "Registry {host} unreachable: {e}"
+        )))
+    };
+
+    let challenge = client
+        .get(format!("{base}/v2/"))
+        .send()
+        .await
+        .map_err(unreachable)?;
+    if challenge.status().is_success() {
+        // Anonymous access; nothing to log in to
+        return Ok(());
+    }
+    let header = challenge
+        .headers()
+        .get(reqwest::header::WWW_AUTHENTICATE)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or_default();
+
+    let login = match parse_bearer_challenge(header) {
+        Some((realm, service)) => {
+            let mut request = client.get(&realm);
+            if let Some(service) = service {
+                request = request.query(&[("service", service)]);
+            }
+            request
+        }
+        None => client.get(format!("{base}/v2/")),
+    };
+    let response = login
+        .basic_auth(&registry.username, Some(&registry.password))
+        .send()
+        .await
+        .map_err(unreachable)?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(WingsError::Config(format!(
+            "Registry {host} rejected the credentials for {} ({})",
+            registry.username,
+            response.status()
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry(host: &str, username: &str) -> RegistryConfig {
+        RegistryConfig {
+            host: host.to_string(),
+            username: username.to_string(),
+            password: "secret".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_image_registry() {
+        assert_eq!(image_registry("nginx"), "docker.io");
+        assert_eq!(image_registry("itzg/minecraft-server:java21"), "docker.io");
+        assert_eq!(image_registry("docker.io/library/nginx"), "docker.io");
+        assert_eq!(image_registry("index.docker.io/library/nginx"), "docker.io");
+        assert_eq!(image_registry("ghcr.io/nexus/java:21"), "ghcr.io");
+        assert_eq!(
+            image_registry("harbor.local:8443/games/rust"),
+            "harbor.local:8443"
+        );
+        assert_eq!(image_registry("localhost/test"), "localhost");
+    }
+
+    #[test]
+    fn test_credentials_match_image_host() {
+        let registries = [
+            registry("https://index.docker.io/v1/", "hub-user"),
+            registry("ghcr.io", "gh-user"),
+            registry("harbor.local:8443", "robot"),
+        ];
+        let user = |image| credentials(&registries, image).and_then(|c| c.username);
+
+        assert_eq!(user("nginx").as_deref(), Some("hub-user"));
+        assert_eq!(user("GHCR.io/nexus/java:21").as_deref(), Some("gh-user"));
+        assert_eq!(
+            user("harbor.local:8443/games/rust").as_deref(),
+            Some("robot")
+        );
+        assert_eq!(user("harbor.local/games/rust"), None);
+        assert_eq!(user("quay.io/nexus/java"), None);
+
+        let creds = credentials(&registries, "ghcr.io/nexus/java").unwrap();
+        assert_eq!(creds.serveraddress.as_deref(), Some("ghcr.io"));
+        assert_eq!(creds.password.as_deref(), Some("secret"));
+    }
+
+    #[test]
+    fn test_parse_bearer_challenge() {
+        assert_eq!(
+            parse_bearer_challenge(
+                r#"Bearer realm="https://auth.docker.io/token",service="registry.docker.io""#
+            ),
+            Some((
+                "https://auth.docker.io/token".to_string(),
+                Some("registry.docker.io".to_string())
+            ))
+        );
+        assert_eq!(
+            parse_bearer_challenge(r#"Bearer realm="https://ghcr.io/token""#),
+            Some(("https://ghcr.io/token".to_string(), None))
+        );
+        assert_eq!(parse_bearer_challenge(r#"Basic realm="Harbor""#), None);
+    }
+}
@@ -0,0 +1,111 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use axum::extract::{Path as AxumPath, State};
+use axum::Json;
+use serde::Deserialize;
+
+use crate::backups;
+use crate::snapshots::RetentionPolicy;
+use crate::error::WingsError;
+use crate::state::AppState;
+
+#[derive(Deserialize)]
+pub struct CreateBackupRequest {
+    /// Optional; generated when omitted
+    pub backup_uuid: Option<String>,
+    /// Where to store the archive; `local` unless given
+    #[serde(default)]
+    pub adapter: backups::BackupAdapter,
+    /// `archive` unless given
+    #[serde(default)]
+    pub format: backups::BackupFormat,
+}
+
+#[derive(Deserialize)]
+pub struct RestoreBackupRequest {
+    /// Delete the current server files before extracting
+    #[serde(default)]
+    pub wipe: bool,
+}
+
+pub async fn list_backups(
+    State(state): State<Arc<AppState>>,
+    AxumPath(uuid): AxumPath<String>,
+) -> Result<Json<serde_json::Value>, WingsError> {
+    let backups = backups::list(Path::new(&state.config.storage.backup_dir), &uuid)?;
+    Ok(Json(serde_json::json!({ "backups": backups })))
+}
+
+pub async fn create_backup(
+    State(state): State<Arc<AppState>>,
+    AxumPath(uuid): AxumPath<String>,
+    Json(body): Json<CreateBackupRequest>,
+) -> Result<Json<serde_json::Value>, WingsError> {
+    let (backup_uuid, job_id) =
+        backups::start_backup(
+            &state,
+            &uuid,
+            body.backup_uuid.as_deref(),
+            body.adapter,
+            body.format,
+        )?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "backup_uuid": backup_uuid,
+        "job_id": job_id,
+    })))
+}
+
+pub async fn delete_backup(
+    State(state): State<Arc<AppState>>,
+    AxumPath((uuid, backup_uuid)): AxumPath<(String, String)>,
+) -> Result<Json<serde_json::Value>, WingsError> {
+    let backup_uuid = backups::parse_backup_uuid(&backup_uuid)?;
+    backups::delete(
+        Path::new(&state.config.storage.backup_dir),
+        state.s3.as_ref(),
+        &uuid,
+        &backup_uuid,
+    )
+    .await?;
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+pub async fn restore_backup(
+    State(state): State<Arc<AppState>>,
+    AxumPath((uuid, backup_uuid)): AxumPath<(String, String)>,
+    Json(body): Json<RestoreBackupRequest>,
+) -> Result<Json<serde_json::Value>, WingsError> {
+    let job_id = backups::start_restore(&state, &uuid, &backup_uuid, body.wipe)?;
+    Ok(Json(
+        serde_json::json!({ "success": true, "job_id": job_id }),
+    ))
+}
+
+pub async fn verify_backup(
+    State(state): State<Arc<AppState>>,
+    AxumPath((uuid, backup_uuid)): AxumPath<(String, String)>,
+) -> Result<Json<serde_json::Value>, WingsError> {
+    let backup_uuid = backups::parse_backup_uuid(&backup_uuid)?;
+    let report = tokio::task::spawn_blocking(move || {
+        backups::verify(
+            Path::new(&state.config.storage.backup_dir),
+            state.s3.as_ref(),
+            &uuid,
+            &backup_uuid,
+        )
+    })
+    .await
+    .map_err(|e| WingsError::Io(std::io::Error::other(e)))??;
+    Ok(Json(serde_json::json!({ "success": true, "report": report })))
+}
+
+pub async fn prune_backups(
+    State(state): State<Arc<AppState>>,
+    AxumPath(uuid): AxumPath<String>,
+    Json(policy): Json<RetentionPolicy>,
+) -> Result<Json<serde_json::Value>, WingsError> {
+    let job_id = backups::start_prune(&state, &uuid, policy)?;
+    Ok(Json(serde_json::json!({ "success": true, "job_id": job_id })))
+}
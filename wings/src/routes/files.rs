@@ -1,12 +1,18 @@
 use std::path::Path;
 use std::sync::Arc;
 
+use axum::body::Bytes;
 use axum::extract::{Multipart, Path as AxumPath, Query, State};
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{HeaderMap, HeaderName};
+use axum::response::{IntoResponse, Response};
 use axum::Json;
+use base64::Engine;
 use serde::{Deserialize, Serialize};
 
 use crate::error::WingsError;
 use crate::files as file_ops;
+use crate::jobs;
 use crate::state::AppState;
 
 #[derive(Deserialize)]
@@ -14,10 +20,22 @@ pub struct PathQuery {
     pub path: Option<String>,
 }
 
+/// Largest `files/write` body: a file of [`file_ops::MAX_FILE_SIZE`] sent as
+/// base64 in a JSON request, with room for the rest of the JSON.
+pub const MAX_WRITE_BODY: usize = file_ops::MAX_FILE_SIZE as usize / 3 * 4 + 64 * 1024;
+
+/// Response header telling clients whether `files/read` returned text or raw bytes.
+const FILE_TYPE_HEADER: HeaderName = HeaderName::from_static("x-file-type");
+/// Response header carrying the detected encoding of a text file.
+const FILE_ENCODING_HEADER: HeaderName = HeaderName::from_static("x-file-encoding");
+
 #[derive(Deserialize)]
 pub struct WriteRequest {
     pub path: String,
     pub content: String,
+    /// `base64` for binary content, or the text encoding to save the file in
+    /// (as reported by `files/read`). Defaults to UTF-8.
+    pub encoding: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -42,15 +60,86 @@ pub struct CompressRequest {
     pub destination: String,
 }
 
+#[derive(Deserialize)]
+pub struct CopyRequest {
+    pub paths: Vec<String>,
+    /// Directory to copy into; defaults to each entry's own directory.
+    pub destination: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct MoveRequest {
+    pub paths: Vec<String>,
+    pub destination: String,
+}
+
+#[derive(Deserialize)]
+pub struct ChmodRequest {
+    pub files: Vec<ChmodEntry>,
+}
+
+#[derive(Deserialize)]
+pub struct ChmodEntry {
+    pub path: String,
+    /// Octal permission mode, e.g. "755"
+    pub mode: String,
+}
+
 #[derive(Deserialize)]
 pub struct DecompressRequest {
     pub path: String,
     pub destination: String,
 }
 
+#[derive(Deserialize)]
+pub struct ListQuery {
+    pub path: Option<String>,
+    #[serde(default)]
+    pub sort: file_ops::SortKey,
+    #[serde(default)]
+    pub descending: bool,
+    /// Glob matched against entry names, e.g. `*.log`
+    pub filter: Option<String>,
+    /// How many levels to descend; 1 lists only the directory itself
+    pub depth: Option<usize>,
+    /// `next_cursor` from the previous page
+    pub cursor: Option<String>,
+    /// Page size, at most [`LIST_MAX_LIMIT`] which is also the default;
+    /// clients follow `next_cursor` for the rest
+    pub limit: Option<usize>,
+}
+
+const LIST_MAX_LIMIT: usize = 5000;
+const LIST_MAX_DEPTH: usize = 8;
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    /// Directory to search below; defaults to the server root
+    pub path: Option<String>,
+    /// Glob matched against file names, e.g. `*.yml`
+    pub name: Option<String>,
+    /// Regular expression searched for in file contents
+    pub content: Option<String>,
+    #[serde(default)]
+    pub case_insensitive: bool,
+    /// Skip content search in files larger than this many bytes
+    pub max_file_size: Option<u64>,
+    pub limit: Option<usize>,
+    /// Search timeout in seconds
+    pub timeout: Option<u64>,
+}
+
+const SEARCH_DEFAULT_LIMIT: usize = 100;
+const SEARCH_MAX_LIMIT: usize = 1000;
+const SEARCH_DEFAULT_FILE_SIZE: u64 = 1024 * 1024;
+const SEARCH_DEFAULT_TIMEOUT: u64 = 10;
+const SEARCH_MAX_TIMEOUT: u64 = 60;
+
 #[derive(Serialize)]
 pub struct FileListResponse {
     pub files: Vec<file_ops::FileEntry>,
+    /// Pass as `cursor` to fetch the next page; absent on the last page
+    pub next_cursor: Option<String>,
 }
 
 fn server_root(state: &AppState, uuid: &str) -> std::path::PathBuf {
@@ -60,34 +149,208 @@ fn server_root(state: &AppState, uuid: &str) -> std::path::PathBuf {
 pub async fn list_files(
     State(state): State<Arc<AppState>>,
     AxumPath(uuid): AxumPath<String>,
-    Query(query): Query<PathQuery>,
+    Query(query): Query<ListQuery>,
 ) -> Result<Json<FileListResponse>, WingsError> {
     let root = server_root(&state, &uuid);
     let requested = query.path.as_deref().unwrap_or("/");
     let path = file_ops::validate_path(&root, requested)?;
-    let files = file_ops::list_directory(&path)?;
-    Ok(Json(FileListResponse { files }))
+
+    let filter = query
+        .filter
+        .as_deref()
+        .map(|pattern| {
+            globset::Glob::new(pattern)
+                .map(|glob| glob.compile_matcher())
+                .map_err(|e| WingsError::InvalidRequest(format!("invalid filter: {}", e)))
+        })
+        .transpose()?;
+    let options = file_ops::ListOptions {
+        sort: query.sort,
+        descending: query.descending,
+        filter,
+        depth: query.depth.unwrap_or(1).clamp(1, LIST_MAX_DEPTH),
+        cursor: query
+            .cursor
+            .as_deref()
+            .map(file_ops::ListCursor::decode)
+            .transpose()?,
+        limit: query
+            .limit
+            .map_or(LIST_MAX_LIMIT, |limit| limit.clamp(1, LIST_MAX_LIMIT)),
+    };
+
+    let page = tokio::task::spawn_blocking(move || file_ops::list_directory(&path, &options))
+        .await
+        .map_err(|e| WingsError::Io(std::io::Error::other(e)))??;
+    Ok(Json(FileListResponse {
+        files: page.entries,
+        next_cursor: page.next_cursor,
+    }))
+}
+
+/// Search a server's files by name and/or content.
+///
+/// Results are streamed as newline-delimited JSON, one match per line,
+/// followed by a final `{"done": true, ...}` summary line.
+pub async fn search_files(
+    State(state): State<Arc<AppState>>,
+    AxumPath(uuid): AxumPath<String>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Response, WingsError> {
+    if query.name.is_none() && query.content.is_none() {
+        return Err(WingsError::InvalidRequest(
+            "name or content is required".into(),
+        ));
+    }
+    let server_root = server_root(&state, &uuid);
+    let root = file_ops::validate_path(&server_root, "/")?;
+    let start = file_ops::validate_path(&server_root, query.path.as_deref().unwrap_or("/"))?;
+    if !start.is_dir() {
+        return Err(WingsError::InvalidRequest("path is not a directory".into()));
+    }
+
+    let name = query
+        .name
+        .as_deref()
+        .map(|pattern| {
+            // Path globs are matched relative to the server root
+            globset::GlobBuilder::new(pattern.trim_start_matches('/'))
+                .case_insensitive(query.case_insensitive)
+                .literal_separator(true)
+                .build()
+                .map(|glob| glob.compile_matcher())
+                .map_err(|e| WingsError::InvalidRequest(format!("invalid name pattern: {}", e)))
+        })
+        .transpose()?;
+    let content = query
+        .content
+        .as_deref()
+        .map(|pattern| {
+            regex::RegexBuilder::new(pattern)
+                .case_insensitive(query.case_insensitive)
+                .size_limit(1 << 20)
+                .build()
+                .map_err(|e| WingsError::InvalidRequest(format!("invalid content pattern: {}", e)))
+        })
+        .transpose()?;
+
+    let timeout = query
+        .timeout
+        .unwrap_or(SEARCH_DEFAULT_TIMEOUT)
+        .min(SEARCH_MAX_TIMEOUT);
+    let options = file_ops::SearchOptions {
+        name_matches_path: query.name.as_deref().is_some_and(|n| n.contains('/')),
+        name,
+        content,
+        max_file_size: query
+            .max_file_size
+            .unwrap_or(SEARCH_DEFAULT_FILE_SIZE)
+            .min(file_ops::MAX_FILE_SIZE),
+        limit: query
+            .limit
+            .unwrap_or(SEARCH_DEFAULT_LIMIT)
+            .clamp(1, SEARCH_MAX_LIMIT),
+        deadline: std::time::Instant::now() + std::time::Duration::from_secs(timeout),
+    };
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<String>(64);
+    tokio::task::spawn_blocking(move || {
+        let summary = file_ops::search(&root, &start, &options, |found| {
+            let line = serde_json::to_string(&found).unwrap_or_default() + "\n";
+            // A closed channel means the client went away
+            tx.blocking_send(line).is_ok()
+        });
+        let mut done = serde_json::to_value(&summary).unwrap_or_default();
+        done["done"] = serde_json::Value::Bool(true);
+        let _ = tx.blocking_send(done.to_string() + "\n");
+    });
+
+    let stream = tokio_stream::StreamExt::map(
+        tokio_stream::wrappers::ReceiverStream::new(rx),
+        Ok::<_, std::convert::Infallible>,
+    );
+    Ok((
+        [(CONTENT_TYPE, "application/x-ndjson")],
+        axum::body::Body::from_stream(stream),
+    )
+        .into_response())
 }
 
 pub async fn read_file(
     State(state): State<Arc<AppState>>,
     AxumPath(uuid): AxumPath<String>,
     Query(query): Query<PathQuery>,
-) -> Result<String, WingsError> {
+) -> Result<Response, WingsError> {
     let root = server_root(&state, &uuid);
     let requested = query.path.as_deref().unwrap_or("/");
     let path = file_ops::validate_path(&root, requested)?;
-    file_ops::read_file(&path)
+    let response = match file_ops::read_file(&path)? {
+        file_ops::FileContents::Text { encoding, content } => (
+            [
+                (CONTENT_TYPE, "text/plain; charset=utf-8"),
+                (FILE_TYPE_HEADER, "text"),
+                (FILE_ENCODING_HEADER, encoding.as_str()),
+            ],
+            content,
+        )
+            .into_response(),
+        file_ops::FileContents::Binary(bytes) => (
+            [
+                (CONTENT_TYPE, "application/octet-stream"),
+                (FILE_TYPE_HEADER, "binary"),
+            ],
+            bytes,
+        )
+            .into_response(),
+    };
+    Ok(response)
 }
 
+/// Accepts either a JSON [`WriteRequest`] or, for any other content type, the
+/// raw file bytes as the body with the target given by the `path` query.
 pub async fn write_file(
     State(state): State<Arc<AppState>>,
     AxumPath(uuid): AxumPath<String>,
-    Json(body): Json<WriteRequest>,
+    Query(query): Query<PathQuery>,
+    headers: HeaderMap,
+    body: Bytes,
 ) -> Result<Json<serde_json::Value>, WingsError> {
     let root = server_root(&state, &uuid);
-    let path = file_ops::validate_path(&root, &body.path)?;
-    file_ops::write_file(&path, &body.content)?;
+    let is_json = headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+
+    let (requested, data) = if is_json {
+        let req: WriteRequest = serde_json::from_slice(&body)
+            .map_err(|e| WingsError::InvalidRequest(e.to_string()))?;
+        let data = match req.encoding.as_deref() {
+            Some("base64") => base64::engine::general_purpose::STANDARD
+                .decode(req.content.as_bytes())
+                .map_err(|e| WingsError::InvalidRequest(format!("Invalid base64 content: {e}")))?,
+            Some(name) => file_ops::TextEncoding::from_name(name)
+                .ok_or_else(|| WingsError::InvalidRequest(format!("Unknown encoding: {name}")))?
+                .encode(&req.content)?,
+            None => req.content.into_bytes(),
+        };
+        (req.path, data)
+    } else {
+        let requested = query
+            .path
+            .ok_or_else(|| WingsError::InvalidRequest("Missing path".into()))?;
+        (requested, body.to_vec())
+    };
+
+    if data.len() as u64 > file_ops::MAX_FILE_SIZE {
+        return Err(WingsError::FileTooLarge);
+    }
+    let path = file_ops::validate_path(&root, &requested)?;
+    let owner = state.docker.container_user();
+    if let Some(parent) = path.parent() {
+        file_ops::create_directory(parent, owner)?;
+    }
+    file_ops::write_file(&path, &data)?;
+    file_ops::chown_recursive(&path, owner)?;
     Ok(Json(serde_json::json!({ "success": true })))
 }
 
@@ -98,7 +361,7 @@ pub async fn create_directory(
 ) -> Result<Json<serde_json::Value>, WingsError> {
     let root = server_root(&state, &uuid);
     let path = file_ops::validate_path(&root, &body.path)?;
-    file_ops::create_directory(&path)?;
+    file_ops::create_directory(&path, state.docker.container_user())?;
     Ok(Json(serde_json::json!({ "success": true })))
 }
 
@@ -129,6 +392,46 @@ pub async fn delete_files(
     Ok(Json(serde_json::json!({ "success": true })))
 }
 
+pub async fn copy_files(
+    State(state): State<Arc<AppState>>,
+    AxumPath(uuid): AxumPath<String>,
+    Json(body): Json<CopyRequest>,
+) -> Result<Json<serde_json::Value>, WingsError> {
+    let root = server_root(&state, &uuid);
+    let paths = file_ops::validate_entries(&root, &body.paths)?;
+    let destination = body
+        .destination
+        .as_deref()
+        .map(|d| file_ops::validate_path(&root, d))
+        .transpose()?;
+    let quota = state.remaining_quota(&uuid).await;
+    let owner = state.docker.container_user();
+
+    let job_id = jobs::spawn(state.clone(), &uuid, "copy", move |progress| {
+        let created = file_ops::copy_entries(&paths, destination.as_deref(), progress, quota)?;
+        for path in created {
+            file_ops::chown_recursive(&path, owner)?;
+        }
+        Ok(())
+    });
+    Ok(Json(serde_json::json!({ "success": true, "job_id": job_id })))
+}
+
+pub async fn move_files(
+    State(state): State<Arc<AppState>>,
+    AxumPath(uuid): AxumPath<String>,
+    Json(body): Json<MoveRequest>,
+) -> Result<Json<serde_json::Value>, WingsError> {
+    let root = server_root(&state, &uuid);
+    let paths = file_ops::validate_entries(&root, &body.paths)?;
+    let destination = file_ops::validate_path(&root, &body.destination)?;
+
+    let job_id = jobs::spawn(state.clone(), &uuid, "move", move |_| {
+        file_ops::move_entries(&paths, &destination)
+    });
+    Ok(Json(serde_json::json!({ "success": true, "job_id": job_id })))
+}
+
 pub async fn compress_files(
     State(state): State<Arc<AppState>>,
     AxumPath(uuid): AxumPath<String>,
@@ -141,7 +444,39 @@ pub async fn compress_files(
         .map(|p| file_ops::validate_path(&root, p))
         .collect::<Result<Vec<_>, _>>()?;
     let dest = file_ops::validate_path(&root, &body.destination)?;
-    file_ops::compress(&paths, &dest)?;
+    if file_ops::ArchiveFormat::from_path(&dest).is_none() {
+        return Err(WingsError::InvalidRequest(
+            "Unsupported archive format, use .zip, .tar.gz or .tar.zst".into(),
+        ));
+    }
+    let quota = state.remaining_quota(&uuid).await;
+    if quota == Some(0) {
+        return Err(WingsError::QuotaExceeded);
+    }
+
+    let owner = state.docker.container_user();
+
+    let job_id = jobs::spawn(state.clone(), &uuid, "compress", move |progress| {
+        file_ops::compress(&paths, &dest, progress, quota)?;
+        file_ops::chown_recursive(&dest, owner)
+    });
+    Ok(Json(serde_json::json!({ "success": true, "job_id": job_id })))
+}
+
+pub async fn chmod_files(
+    State(state): State<Arc<AppState>>,
+    AxumPath(uuid): AxumPath<String>,
+    Json(body): Json<ChmodRequest>,
+) -> Result<Json<serde_json::Value>, WingsError> {
+    let root = server_root(&state, &uuid);
+    let entries = body
+        .files
+        .iter()
+        .map(|f| Ok((file_ops::validate_path(&root, &f.path)?, file_ops::parse_mode(&f.mode)?)))
+        .collect::<Result<Vec<_>, WingsError>>()?;
+    for (path, mode) in entries {
+        file_ops::chmod(&path, mode)?;
+    }
     Ok(Json(serde_json::json!({ "success": true })))
 }
 
@@ -153,8 +488,19 @@ pub async fn decompress_file(
     let root = server_root(&state, &uuid);
     let archive = file_ops::validate_path(&root, &body.path)?;
     let dest = file_ops::validate_path(&root, &body.destination)?;
-    file_ops::decompress(&archive, &dest)?;
-    Ok(Json(serde_json::json!({ "success": true })))
+    file_ops::detect_archive(&archive)?;
+    let limits = file_ops::ExtractLimits {
+        quota: state.remaining_quota(&uuid).await,
+        ..Default::default()
+    };
+
+    let owner = state.docker.container_user();
+
+    let job_id = jobs::spawn(state.clone(), &uuid, "decompress", move |progress| {
+        file_ops::decompress(&root, &archive, &dest, progress, &limits)?;
+        file_ops::chown_recursive(&dest, owner)
+    });
+    Ok(Json(serde_json::json!({ "success": true, "job_id": job_id })))
 }
 
 pub async fn upload_file(
@@ -165,7 +511,7 @@ pub async fn upload_file(
     let root = server_root(&state, &uuid);
 
     while let Some(field) = multipart.next_field().await.map_err(|e| {
-        WingsError::Io(std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))
+        WingsError::Io(std::io::Error::other(e.to_string()))
     })? {
         let file_name = field
             .file_name()
@@ -173,10 +519,32 @@ pub async fn upload_file(
             .unwrap_or_else(|| "upload".to_string());
         let path = file_ops::validate_path(&root, &file_name)?;
         let data = field.bytes().await.map_err(|e| {
-            WingsError::Io(std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))
+            WingsError::Io(std::io::Error::other(e.to_string()))
         })?;
-        std::fs::write(&path, &data).map_err(WingsError::Io)?;
+        let owner = state.docker.container_user();
+        if let Some(parent) = path.parent() {
+            file_ops::create_directory(parent, owner)?;
+        }
+        file_ops::write_file(&path, &data)?;
+        file_ops::chown_recursive(&path, owner)?;
     }
 
     Ok(Json(serde_json::json!({ "success": true })))
 }
+
+pub async fn list_jobs(
+    State(state): State<Arc<AppState>>,
+    AxumPath(uuid): AxumPath<String>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "jobs": state.jobs.list(&uuid) }))
+}
+
+pub async fn cancel_job(
+    State(state): State<Arc<AppState>>,
+    AxumPath((uuid, job_id)): AxumPath<(String, String)>,
+) -> Result<Json<serde_json::Value>, WingsError> {
+    if !state.jobs.cancel(&uuid, &job_id) {
+        return Err(WingsError::InvalidRequest(format!("No running job {job_id}")));
+    }
+    Ok(Json(serde_json::json!({ "success": true })))
+}
@@ -1,4 +1,6 @@
+pub mod backups;
 pub mod files;
 pub mod servers;
 pub mod system;
+pub mod transfers;
 pub mod ws;
@@ -24,6 +24,14 @@ pub struct ResourceUpdate {
     pub memory_limit: Option<u64>,
     pub cpu_limit: Option<u64>,
     pub disk_limit: Option<u64>,
+    pub swap_limit: Option<i64>,
+    pub memory_overhead: Option<u64>,
+    pub io_weight: Option<u16>,
+    pub pids_limit: Option<i64>,
+    pub cpuset: Option<String>,
+    pub oom_kill_disable: Option<bool>,
+    pub egress_limit: Option<u64>,
+    pub ingress_limit: Option<u64>,
 }
 
 #[derive(Serialize)]
@@ -64,19 +72,25 @@ pub async fn create_server(
     Json(body): Json<CreateServerRequest>,
 ) -> Result<Json<serde_json::Value>, WingsError> {
     let config = body.server;
-
-    // Ensure the data directory exists
-    let server_dir = std::path::Path::new(&state.config.storage.data_dir).join(&config.uuid);
-    std::fs::create_dir_all(&server_dir).map_err(WingsError::Io)?;
+    crate::docker::io_weight(config.io_weight.into())?;
 
     // Update volume_path to use actual storage dir
     let mut config = config;
-    config.volume_path = server_dir.to_string_lossy().to_string();
+    config.volume_path = crate::containers::volume_path(&state, &config.uuid);
+    crate::ports::claim(&state, &config).await?;
 
-    let container_id = state.docker.create_server(&config).await?;
+    // Ensure the data directory exists
+    let server_dir = std::path::Path::new(&config.volume_path);
+    std::fs::create_dir_all(server_dir).map_err(WingsError::Io)?;
+    crate::files::chown_recursive(server_dir, state.docker.container_user())?;
+
+    crate::images::ensure_server_image(&state, &mut config).await?;
+    let container_id = state.docker.create_container(&config).await?;
 
     // Run install script if provided
     if let (Some(script), Some(image)) = (body.install_script, body.install_docker_image) {
+        let policy = state.config.docker.pull_policy;
+        crate::images::ensure(&state, &image, policy, Some(&config.uuid)).await?;
         let panel_url = Some(state.config.panel.url.as_str());
         let panel_auth_str = format!("{}.{}", state.config.panel.token_id, state.config.panel.token);
         let panel_auth = Some(panel_auth_str.as_str());
@@ -108,6 +122,7 @@ pub async fn delete_server(
 
     // Remove console buffer
     state.console_buffers.write().await.remove(&uuid);
+    crate::schedules::remove(&state, &uuid);
 
     Ok(Json(serde_json::json!({ "success": true })))
 }
@@ -117,10 +132,16 @@ pub async fn power_action(
     AxumPath(uuid): AxumPath<String>,
     Json(action): Json<PowerAction>,
 ) -> Result<Json<serde_json::Value>, WingsError> {
+    let lock = state.server_lock(&uuid);
+    let _guard = lock.lock().await;
     match action.action.as_str() {
-        "start" => state.docker.start_server(&uuid).await?,
+        "start" => {
+            // Create a missing container or apply a pending rebuild first
+            crate::containers::prepare_start(&state, &uuid).await?;
+            state.docker.start_server(&uuid).await?
+        }
         "stop" => state.docker.stop_server(&uuid, 30).await?,
-        "restart" => state.docker.restart_server(&uuid).await?,
+        "restart" => crate::containers::restart(&state, &uuid).await?,
         "kill" => state.docker.kill_server(&uuid).await?,
         other => {
             return Err(WingsError::Config(format!("Unknown power action: {other}")));
@@ -147,23 +168,52 @@ pub async fn update_resources(
     Json(body): Json<ResourceUpdate>,
 ) -> Result<Json<serde_json::Value>, WingsError> {
     tracing::info!(uuid = %uuid, "Updating container resources");
+    let lock = state.server_lock(&uuid);
+    let _guard = lock.lock().await;
 
-    let short = uuid.replace('-', "");
-    let container_name = format!("nexus-{}", &short[..std::cmp::min(8, short.len())]);
-
-    let update_config = bollard::container::UpdateContainerOptions::<String> {
-        memory: body.memory_limit.map(|m| (m * 1024 * 1024) as i64),
-        nano_cpus: body.cpu_limit.map(|c| (c as i64) * 10_000_000),
-        ..Default::default()
-    };
-
-    state.docker.client().update_container(&container_name, update_config)
+    let mut cfg = state
+        .get_server_config(&uuid)
         .await
-        .map_err(WingsError::Docker)?;
+        .ok_or_else(|| WingsError::ServerNotFound(uuid.clone()))?;
+    if let Some(memory_limit) = body.memory_limit {
+        cfg.memory_limit = memory_limit;
+    }
+    if let Some(cpu_limit) = body.cpu_limit {
+        cfg.cpu_limit = cpu_limit;
+    }
+    if let Some(disk_limit) = body.disk_limit {
+        cfg.disk_limit = disk_limit;
+    }
+    if let Some(swap_limit) = body.swap_limit {
+        cfg.swap_limit = swap_limit;
+    }
+    if let Some(memory_overhead) = body.memory_overhead {
+        cfg.memory_overhead = memory_overhead;
+    }
+    if let Some(io_weight) = body.io_weight {
+        cfg.io_weight = crate::docker::io_weight(io_weight.into())?;
+    }
+    if let Some(pids_limit) = body.pids_limit {
+        cfg.pids_limit = pids_limit;
+    }
+    if let Some(cpuset) = body.cpuset {
+        cfg.cpuset = cpuset;
+    }
+    if let Some(oom_kill_disable) = body.oom_kill_disable {
+        cfg.oom_kill_disable = oom_kill_disable;
+    }
+    if let Some(egress_limit) = body.egress_limit {
+        cfg.egress_limit = egress_limit;
+    }
+    if let Some(ingress_limit) = body.ingress_limit {
+        cfg.ingress_limit = ingress_limit;
+    }
+    let rebuild_pending = crate::containers::sync_config(&state, cfg).await?;
 
     Ok(Json(serde_json::json!({
         "success": true,
-        "message": "Resource limits updated"
+        "message": "Resource limits updated",
+        "rebuild_pending": rebuild_pending,
     })))
 }
 
@@ -173,7 +223,11 @@ pub async fn get_status(
 ) -> Result<Json<ServerStatus>, WingsError> {
     let container_state = state.docker.get_container_status(&uuid).await?;
     let resources = if container_state == "running" {
-        state.docker.get_stats(&uuid).await.ok()
+        let cpu_limit = state
+            .get_server_config(&uuid)
+            .await
+            .map_or(0, |config| config.cpu_limit);
+        state.docker.get_stats(&uuid, cpu_limit).await.ok()
     } else {
         None
     };
@@ -204,14 +258,27 @@ pub async fn install_server(
         memory_limit: 0,
         cpu_limit: 0,
         disk_limit: 0,
+        swap_limit: 0,
+        memory_overhead: 0,
+        io_weight: 0,
+        pids_limit: 0,
+        cpuset: String::new(),
+        oom_kill_disable: false,
+        network_isolated: false,
+        egress_limit: 0,
+        ingress_limit: 0,
         port_mappings: vec![],
         volume_path: server_dir.to_string_lossy().to_string(),
+        pull_policy: None,
+        image_digest: None,
+        rebuild_pending: false,
     });
 
     let panel_url = Some(state.config.panel.url.as_str());
     let panel_auth_str = format!("{}.{}", state.config.panel.token_id, state.config.panel.token);
     let panel_auth = Some(panel_auth_str.as_str());
 
+    crate::images::ensure(&state, &image, state.config.docker.pull_policy, Some(&uuid)).await?;
     let output = installer::run_install(
         &state.docker,
         &config,
@@ -5,6 +5,7 @@ use axum::Json;
 use serde::Serialize;
 
 use crate::error::WingsError;
+use crate::ports::{PortRegistry, ServerPorts};
 use crate::state::AppState;
 
 #[derive(Serialize)]
@@ -44,3 +45,11 @@ pub async fn system_info(
         uptime_seconds: uptime,
     }))
 }
+
+/// Host ports used by each server, for the Panel to reconcile allocations.
+pub async fn used_ports(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<ServerPorts>>, WingsError> {
+    let registry = PortRegistry::build(&state).await?;
+    Ok(Json(registry.into_servers()))
+}
@@ -0,0 +1,62 @@
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::extract::{Path as AxumPath, State};
+use axum::http::HeaderMap;
+use axum::Json;
+use serde::Deserialize;
+
+use crate::error::WingsError;
+use crate::state::AppState;
+use crate::transfer::{self, CompleteRequest, TransferTarget};
+
+#[derive(Deserialize)]
+pub struct AcceptTransferRequest {
+    pub uuid: String,
+    pub token: String,
+}
+
+/// Panel: prepare this node to receive a server.
+pub async fn accept_transfer(
+    State(state): State<Arc<AppState>>,
+    Json(body): Json<AcceptTransferRequest>,
+) -> Result<Json<serde_json::Value>, WingsError> {
+    transfer::accept(&state, &body.uuid, &body.token).await?;
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+/// Panel: send a server on this node to another node.
+pub async fn start_transfer(
+    State(state): State<Arc<AppState>>,
+    AxumPath(uuid): AxumPath<String>,
+    Json(target): Json<TransferTarget>,
+) -> Result<Json<serde_json::Value>, WingsError> {
+    let job_id = transfer::start(&state, &uuid, target).await?;
+    Ok(Json(
+        serde_json::json!({ "success": true, "job_id": job_id }),
+    ))
+}
+
+/// Source node: upload the server archive. Authenticated with the transfer token.
+pub async fn receive_archive(
+    State(state): State<Arc<AppState>>,
+    AxumPath(uuid): AxumPath<String>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Json<transfer::ArchiveReceipt>, WingsError> {
+    let token = transfer::bearer_token(&headers)?;
+    Ok(Json(transfer::receive(&state, &uuid, token, body).await?))
+}
+
+/// Source node: confirm the upload and hand over the server config.
+/// Authenticated with the transfer token.
+pub async fn complete_transfer(
+    State(state): State<Arc<AppState>>,
+    AxumPath(uuid): AxumPath<String>,
+    headers: HeaderMap,
+    Json(body): Json<CompleteRequest>,
+) -> Result<Json<serde_json::Value>, WingsError> {
+    let token = transfer::bearer_token(&headers)?;
+    transfer::complete(&state, &uuid, token, body).await?;
+    Ok(Json(serde_json::json!({ "success": true })))
+}
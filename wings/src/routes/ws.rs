@@ -8,7 +8,11 @@ use futures_util::SinkExt;
 use serde::Deserialize;
 
 use crate::error::WingsError;
+use crate::grpc::proto::{
+    wings_event, FileChangeKind, FileOperationStatus, ImagePullStatus, TransferRole, WingsEvent,
+};
 use crate::state::AppState;
+use crate::watcher;
 
 #[derive(Deserialize)]
 pub struct WsQuery {
@@ -64,6 +68,14 @@ async fn handle_ws(socket: WebSocket, state: Arc<AppState>, uuid: String) {
     let stats_task = tokio::spawn(async move {
         let mut stream = stats_state.docker.stream_stats(&stats_uuid);
         while let Some(Ok(stats)) = stream.next().await {
+            // The limit can change while the socket is open
+            let cpu_limit = stats_state
+                .get_server_config(&stats_uuid)
+                .await
+                .map_or(0, |config| config.cpu_limit);
+            let stats = stats_state
+                .docker
+                .resource_stats(&stats_uuid, &stats, cpu_limit);
             let msg = serde_json::json!({ "type": "stats", "data": stats });
             if stats_tx.send(msg.to_string()).await.is_err() {
                 break;
@@ -109,10 +121,27 @@ async fn handle_ws(socket: WebSocket, state: Arc<AppState>, uuid: String) {
         }
     });
 
-    // Forward stats and logs to WebSocket
+    // Subscribe to server events (file job progress, ...)
+    let mut events_rx = state.subscribe_events();
+    let events_uuid = uuid.clone();
+
+    // Forward stats, logs and events to WebSocket
     let forward_task = tokio::spawn(async move {
         loop {
             tokio::select! {
+                event = events_rx.recv() => {
+                    match event {
+                        Ok(event) => {
+                            if let Some(msg) = event_message(&events_uuid, &event) {
+                                if ws_tx.send(Message::Text(msg.to_string().into())).await.is_err() {
+                                    break;
+                                }
+                            }
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
                 Some(stats_msg) = stats_rx.recv() => {
                     if ws_tx.send(Message::Text(stats_msg.into())).await.is_err() {
                         break;
@@ -129,6 +158,9 @@ async fn handle_ws(socket: WebSocket, state: Arc<AppState>, uuid: String) {
         }
     });
 
+    // Held while this client has asked for file change notifications
+    let mut file_watch = None;
+
     // Read commands from WebSocket client
     while let Some(Ok(msg)) = ws_rx.next().await {
         if let Message::Text(text) = msg {
@@ -136,12 +168,145 @@ async fn handle_ws(socket: WebSocket, state: Arc<AppState>, uuid: String) {
                 if let Some(cmd) = parsed.get("command").and_then(|v| v.as_str()) {
                     let _ = state.docker.send_command(&uuid, cmd).await;
                 }
+                match parsed.get("watch_files").and_then(|v| v.as_bool()) {
+                    Some(true) if file_watch.is_none() => match watcher::watch(&state, &uuid) {
+                        Ok(guard) => file_watch = Some(guard),
+                        Err(e) => {
+                            tracing::warn!(uuid = %uuid, error = %e, "Failed to watch server files")
+                        }
+                    },
+                    Some(false) => file_watch = None,
+                    _ => {}
+                }
             }
         }
     }
+    drop(file_watch);
 
     // Clean up
     stats_task.abort();
     log_task.abort();
     forward_task.abort();
 }
+
+fn transfer_role(role: i32) -> &'static str {
+    match TransferRole::try_from(role) {
+        Ok(TransferRole::Source) => "source",
+        Ok(TransferRole::Destination) => "destination",
+        Err(_) => "unknown",
+    }
+}
+
+/// Convert a Wings event into a WebSocket message if it concerns `uuid`.
+fn event_message(uuid: &str, event: &WingsEvent) -> Option<serde_json::Value> {
+    match event.event.as_ref()? {
+        wings_event::Event::FileOperationProgress(p) if p.uuid == uuid => {
+            let status = match FileOperationStatus::try_from(p.status) {
+                Ok(FileOperationStatus::FileOpRunning) => "running",
+                Ok(FileOperationStatus::FileOpCompleted) => "completed",
+                Ok(FileOperationStatus::FileOpFailed) => "failed",
+                Ok(FileOperationStatus::FileOpCancelled) => "cancelled",
+                Err(_) => "unknown",
+            };
+            Some(serde_json::json!({
+                "type": "file_progress",
+                "data": {
+                    "job_id": p.job_id,
+                    "operation": p.operation,
+                    "bytes_processed": p.bytes_processed,
+                    "bytes_total": p.bytes_total,
+                    "status": status,
+                    "error": p.error_message,
+                },
+            }))
+        }
+        wings_event::Event::FileChanged(c) if c.uuid == uuid => {
+            let kind = match FileChangeKind::try_from(c.kind) {
+                Ok(FileChangeKind::FileChangeCreated) => "created",
+                Ok(FileChangeKind::FileChangeModified) => "modified",
+                Ok(FileChangeKind::FileChangeDeleted) => "deleted",
+                Ok(FileChangeKind::FileChangeRenamed) => "renamed",
+                Ok(FileChangeKind::FileChangeRescan) => "rescan",
+                Err(_) => "unknown",
+            };
+            let mut data = serde_json::json!({ "kind": kind, "path": c.path });
+            if !c.new_path.is_empty() {
+                data["new_path"] = c.new_path.clone().into();
+            }
+            Some(serde_json::json!({ "type": "file_changed", "data": data }))
+        }
+        wings_event::Event::BackupCompleted(b) if b.uuid == uuid => Some(serde_json::json!({
+            "type": "backup_completed",
+            "data": {
+                "backup_uuid": b.backup_uuid,
+                "successful": b.successful,
+                "checksum": b.backup.as_ref().map(|info| info.checksum.clone()),
+                "size": b.backup.as_ref().map(|info| info.size),
+                "error": b.error_message,
+            },
+        })),
+        wings_event::Event::BackupRestored(b) if b.uuid == uuid => Some(serde_json::json!({
+            "type": "backup_restored",
+            "data": {
+                "backup_uuid": b.backup_uuid,
+                "successful": b.successful,
+                "error": b.error_message,
+            },
+        })),
+        wings_event::Event::TransferProgress(t) if t.uuid == uuid => Some(serde_json::json!({
+            "type": "transfer_progress",
+            "data": {
+                "role": transfer_role(t.role),
+                "bytes_processed": t.bytes_processed,
+                "bytes_total": t.bytes_total,
+            },
+        })),
+        wings_event::Event::TransferCompleted(t) if t.uuid == uuid => Some(serde_json::json!({
+            "type": "transfer_completed",
+            "data": {
+                "role": transfer_role(t.role),
+                "successful": t.successful,
+                "error": t.error_message,
+            },
+        })),
+        wings_event::Event::BackupsPruned(p) if p.uuid == uuid => Some(serde_json::json!({
+            "type": "backups_pruned",
+            "data": {
+                "successful": p.successful,
+                "removed": p.removed_backup_uuids,
+                "freed_bytes": p.freed_bytes,
+                "error": p.error_message,
+            },
+        })),
+        wings_event::Event::ScheduleCompleted(s) if s.uuid == uuid => Some(serde_json::json!({
+            "type": "schedule_completed",
+            "data": {
+                "schedule_id": s.schedule_id,
+                "skipped": s.skipped,
+                "successful": s.successful,
+                "actions_completed": s.actions_completed,
+                "error": s.error_message,
+                "next_run_at_ms": s.next_run_at_ms,
+            },
+        })),
+        wings_event::Event::ImagePullProgress(p) if p.uuid == uuid => {
+            let status = match ImagePullStatus::try_from(p.status) {
+                Ok(ImagePullStatus::ImagePullRunning) => "running",
+                Ok(ImagePullStatus::ImagePullCompleted) => "completed",
+                Ok(ImagePullStatus::ImagePullFailed) => "failed",
+                Err(_) => "unknown",
+            };
+            Some(serde_json::json!({
+                "type": "image_pull_progress",
+                "data": {
+                    "image": p.image,
+                    "status": status,
+                    "bytes_downloaded": p.bytes_downloaded,
+                    "bytes_total": p.bytes_total,
+                    "error": p.error_message,
+                },
+            }))
+        }
+        _ => None,
+    }
+}
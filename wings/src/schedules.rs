@@ -0,0 +1,489 @@
+//! Cron schedules run by Wings itself.
+//!
+//! The Panel syncs each server's schedules here so they keep firing while
+//! the Panel is unreachable. They are stored next to the server config and
+//! reloaded on start; every run is reported with a ScheduleCompleted event.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use croner::Cron;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::AbortHandle;
+
+use crate::backups::{self, BackupAdapter, BackupFormat};
+use crate::error::WingsError;
+use crate::files;
+use crate::grpc::proto::{wings_event, ScheduleCompleted, ServerStateChanged, WingsEvent};
+use crate::grpc::WingsGrpcService;
+use crate::state::AppState;
+
+/// File in the server root holding its schedules.
+const SCHEDULES_FILE: &str = ".nexus-schedules.json";
+/// Longest single sleep while waiting for the next run, so a changed system
+/// clock is noticed.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+/// Grace period for the server to shut down on a scheduled stop.
+const STOP_TIMEOUT_SECS: u64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PowerSignal {
+    Start,
+    Stop,
+    Restart,
+    Kill,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Action {
+    Command {
+        command: String,
+    },
+    Power {
+        signal: PowerSignal,
+    },
+    Backup {
+        adapter: BackupAdapter,
+        format: BackupFormat,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduleStep {
+    /// Seconds to wait before running the action
+    pub delay_seconds: u32,
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Schedule {
+    pub id: String,
+    pub name: String,
+    /// Five-field cron expression, evaluated in UTC
+    pub cron: String,
+    pub enabled: bool,
+    pub only_when_online: bool,
+    pub steps: Vec<ScheduleStep>,
+}
+
+impl Schedule {
+    fn parse_cron(&self) -> Result<Cron, WingsError> {
+        Cron::new(&self.cron).parse().map_err(|e| {
+            WingsError::InvalidRequest(format!(
+                "Invalid cron expression for schedule {}: {e}",
+                self.id
+            ))
+        })
+    }
+}
+
+struct Running {
+    schedule: Schedule,
+    task: AbortHandle,
+}
+
+/// Schedule tasks of every server, keyed by server uuid.
+#[derive(Default)]
+pub struct Scheduler {
+    servers: Mutex<HashMap<String, Vec<Running>>>,
+}
+
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        for running in self.servers.get_mut().unwrap().values().flatten() {
+            running.task.abort();
+        }
+    }
+}
+
+fn schedules_path(state: &AppState, uuid: &str) -> PathBuf {
+    Path::new(&state.config.storage.data_dir)
+        .join(uuid)
+        .join(SCHEDULES_FILE)
+}
+
+fn load_file(path: &Path) -> Result<Vec<Schedule>, WingsError> {
+    let json = std::fs::read(path)?;
+    serde_json::from_slice(&json)
+        .map_err(|e| WingsError::Config(format!("Invalid schedules file {}: {e}", path.display())))
+}
+
+/// Replace the schedules of a server, persisting them and restarting the
+/// ones that changed. Runs of unchanged schedules carry on.
+pub fn sync(state: &Arc<AppState>, uuid: &str, schedules: Vec<Schedule>) -> Result<(), WingsError> {
+    if uuid::Uuid::parse_str(uuid).is_err() {
+        return Err(WingsError::InvalidRequest(format!(
+            "Invalid server uuid: {uuid}"
+        )));
+    }
+    let path = schedules_path(state, uuid);
+    if !path.parent().is_some_and(Path::is_dir) {
+        return Err(WingsError::ServerNotFound(uuid.to_string()));
+    }
+    let mut ids = std::collections::HashSet::new();
+    for schedule in &schedules {
+        if schedule.id.is_empty() || !ids.insert(schedule.id.as_str()) {
+            return Err(WingsError::InvalidRequest(format!(
+                "Schedule ids must be unique and non-empty: {:?}",
+                schedule.id
+            )));
+        }
+        schedule.parse_cron()?;
+    }
+
+    let json = serde_json::to_vec_pretty(&schedules).map_err(|e| WingsError::Io(e.into()))?;
+    files::write_file(&path, &json)?;
+    activate(state, uuid, schedules);
+    Ok(())
+}
+
+/// Stop a server's schedules and forget them, e.g. when it is deleted.
+pub fn remove(state: &AppState, uuid: &str) {
+    if let Some(running) = state.scheduler.servers.lock().unwrap().remove(uuid) {
+        for r in running {
+            r.task.abort();
+        }
+    }
+    let _ = std::fs::remove_file(schedules_path(state, uuid));
+}
+
+/// Start the persisted schedules of every server on this node.
+pub fn start(state: &Arc<AppState>) {
+    let Ok(entries) = std::fs::read_dir(&state.config.storage.data_dir) else {
+        return;
+    };
+    let mut count = 0;
+    for entry in entries.flatten() {
+        let path = entry.path().join(SCHEDULES_FILE);
+        if !path.exists() {
+            continue;
+        }
+        let uuid = entry.file_name().to_string_lossy().to_string();
+        match load_file(&path) {
+            Ok(schedules) => {
+                count += schedules.iter().filter(|s| s.enabled).count();
+                activate(state, &uuid, schedules);
+            }
+            Err(e) => tracing::warn!(uuid = %uuid, error = %e, "Failed to load schedules"),
+        }
+    }
+    if count > 0 {
+        tracing::info!("Started {count} schedule(s)");
+    }
+}
+
+fn activate(state: &Arc<AppState>, uuid: &str, schedules: Vec<Schedule>) {
+    let mut servers = state.scheduler.servers.lock().unwrap();
+    let mut previous = servers.remove(uuid).unwrap_or_default();
+
+    let mut running = Vec::new();
+    for schedule in schedules.into_iter().filter(|s| s.enabled) {
+        if let Some(i) = previous.iter().position(|r| r.schedule == schedule) {
+            running.push(previous.swap_remove(i));
+            continue;
+        }
+        let cron = match schedule.parse_cron() {
+            Ok(cron) => cron,
+            Err(e) => {
+                tracing::warn!(uuid = %uuid, error = %e, "Skipping schedule");
+                continue;
+            }
+        };
+        let task = tokio::spawn(run_forever(
+            state.clone(),
+            uuid.to_string(),
+            schedule.clone(),
+            cron,
+        ));
+        running.push(Running {
+            schedule,
+            task: task.abort_handle(),
+        });
+    }
+    for stale in previous {
+        stale.task.abort();
+    }
+    if !running.is_empty() {
+        servers.insert(uuid.to_string(), running);
+    }
+}
+
+fn next_run(cron: &Cron, after: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+    cron.find_next_occurrence(after, false).ok()
+}
+
+async fn run_forever(state: Arc<AppState>, uuid: String, schedule: Schedule, cron: Cron) {
+    loop {
+        let Some(next) = next_run(&cron, &Utc::now()) else {
+            tracing::warn!(uuid = %uuid, schedule = %schedule.id, "Schedule never fires again");
+            return;
+        };
+        loop {
+            let remaining = (next - Utc::now()).to_std().unwrap_or_default();
+            if remaining.is_zero() {
+                break;
+            }
+            tokio::time::sleep(remaining.min(MAX_SLEEP)).await;
+        }
+        run(&state, &uuid, &schedule, &cron).await;
+    }
+}
+
+/// Run a schedule's steps once and report the outcome.
+async fn run(state: &Arc<AppState>, uuid: &str, schedule: &Schedule, cron: &Cron) {
+    let started = Utc::now();
+    let online = state
+        .docker
+        .get_container_status(uuid)
+        .await
+        .is_ok_and(|status| status == "running");
+    let skipped = schedule.only_when_online && !online;
+
+    let mut completed = 0;
+    let mut error = None;
+    if !skipped {
+        tracing::info!(uuid = %uuid, schedule = %schedule.id, "Running schedule");
+        for step in &schedule.steps {
+            if step.delay_seconds > 0 {
+                tokio::time::sleep(Duration::from_secs(step.delay_seconds.into())).await;
+            }
+            if let Err(e) = execute(state, uuid, &step.action).await {
+                tracing::warn!(uuid = %uuid, schedule = %schedule.id, error = %e, "Schedule step failed");
+                error = Some(format!("Step {} failed: {e}", completed + 1));
+                break;
+            }
+            completed += 1;
+        }
+    }
+
+    let now = Utc::now();
+    state.emit_event(WingsEvent {
+        event: Some(wings_event::Event::ScheduleCompleted(ScheduleCompleted {
+            uuid: uuid.to_string(),
+            schedule_id: schedule.id.clone(),
+            skipped,
+            successful: !skipped && error.is_none(),
+            actions_completed: completed,
+            error_message: error.unwrap_or_default(),
+            started_at_ms: started.timestamp_millis(),
+            next_run_at_ms: next_run(cron, &now).map_or(0, |next| next.timestamp_millis()),
+            timestamp_ms: now.timestamp_millis(),
+        })),
+    });
+}
+
+async fn execute(state: &Arc<AppState>, uuid: &str, action: &Action) -> Result<(), WingsError> {
+    match action {
+        Action::Command { command } => state.docker.send_command(uuid, command).await,
+        Action::Power { signal } => power(state, uuid, *signal).await,
+        Action::Backup { adapter, format } => backup(state, uuid, *adapter, *format).await,
+    }
+}
+
+async fn power(state: &AppState, uuid: &str, signal: PowerSignal) -> Result<(), WingsError> {
+    let lock = state.server_lock(uuid);
+    let _guard = lock.lock().await;
+    let previous = state.docker.get_container_status(uuid).await;
+    match signal {
+        PowerSignal::Start => {
+            crate::containers::prepare_start(state, uuid).await?;
+            state.docker.start_server(uuid).await?
+        }
+        PowerSignal::Stop => state.docker.stop_server(uuid, STOP_TIMEOUT_SECS).await?,
+        PowerSignal::Restart => crate::containers::restart(state, uuid).await?,
+        PowerSignal::Kill => state.docker.kill_server(uuid).await?,
+    }
+
+    let previous = previous.unwrap_or_else(|_| "unknown".to_string());
+    let current = state
+        .docker
+        .get_container_status(uuid)
+        .await
+        .unwrap_or_else(|_| "unknown".to_string());
+    state.emit_event(WingsEvent {
+        event: Some(wings_event::Event::StateChanged(ServerStateChanged {
+            uuid: uuid.to_string(),
+            previous_state: WingsGrpcService::docker_state_to_proto(&previous).into(),
+            new_state: WingsGrpcService::docker_state_to_proto(&current).into(),
+            timestamp_ms: Utc::now().timestamp_millis(),
+        })),
+    });
+    Ok(())
+}
+
+/// Back the server up and wait for the backup to finish.
+async fn backup(
+    state: &Arc<AppState>,
+    uuid: &str,
+    adapter: BackupAdapter,
+    format: BackupFormat,
+) -> Result<(), WingsError> {
+    let mut events = state.subscribe_events();
+    let (backup_uuid, _) = backups::start_backup(state, uuid, None, adapter, format)?;
+    loop {
+        match events.recv().await {
+            Ok(WingsEvent {
+                event: Some(wings_event::Event::BackupCompleted(done)),
+            }) if done.backup_uuid == backup_uuid => {
+                return if done.successful {
+                    Ok(())
+                } else {
+                    Err(WingsError::Io(std::io::Error::other(done.error_message)))
+                };
+            }
+            Ok(_) | Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => {
+                return Err(WingsError::Io(std::io::Error::other("Event bus closed")))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::test_node;
+    use chrono::TimeZone;
+
+    const UUID: &str = "5c0ffee0-0000-4000-8000-000000000000";
+
+    fn schedule(id: &str, cron: &str) -> Schedule {
+        Schedule {
+            id: id.to_string(),
+            name: "Nightly restart".to_string(),
+            cron: cron.to_string(),
+            enabled: true,
+            only_when_online: true,
+            steps: vec![
+                ScheduleStep {
+                    delay_seconds: 0,
+                    action: Action::Command {
+                        command: "say Restarting in 60s".to_string(),
+                    },
+                },
+                ScheduleStep {
+                    delay_seconds: 60,
+                    action: Action::Power {
+                        signal: PowerSignal::Restart,
+                    },
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_cron_uses_standard_fields() {
+        // Sunday is day 0, as in crontab
+        let cron = schedule("a", "30 4 * * 0").parse_cron().unwrap();
+        let saturday = Utc.with_ymd_and_hms(2026, 10, 17, 12, 0, 0).unwrap();
+        let next = next_run(&cron, &saturday).unwrap();
+        assert_eq!(next, Utc.with_ymd_and_hms(2026, 10, 18, 4, 30, 0).unwrap());
+
+        assert!(schedule("a", "* * *").parse_cron().is_err());
+        assert!(schedule("a", "61 * * * *").parse_cron().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sync_persists_and_validates() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let state = test_node(tmp.path()).0;
+        std::fs::create_dir_all(tmp.path().join("data").join(UUID)).unwrap();
+
+        let schedules = vec![schedule("a", "0 4 * * *"), schedule("b", "*/15 * * * *")];
+        sync(&state, UUID, schedules.clone()).unwrap();
+        assert_eq!(load_file(&schedules_path(&state, UUID)).unwrap(), schedules);
+        assert_eq!(state.scheduler.servers.lock().unwrap()[UUID].len(), 2);
+
+        // Bad definitions leave the stored schedules alone
+        let err = sync(&state, UUID, vec![schedule("a", "0 4 * *")]).unwrap_err();
+        assert!(matches!(err, WingsError::InvalidRequest(_)));
+        let err = sync(
+            &state,
+            UUID,
+            vec![schedule("a", "* * * * *"), schedule("a", "* * * * *")],
+        );
+        assert!(matches!(err, Err(WingsError::InvalidRequest(_))));
+        assert_eq!(load_file(&schedules_path(&state, UUID)).unwrap(), schedules);
+
+        let err = sync(&state, "5c0ffee0-0000-4000-8000-00000000ffff", schedules).unwrap_err();
+        assert!(matches!(err, WingsError::ServerNotFound(_)));
+
+        remove(&state, UUID);
+        assert!(!schedules_path(&state, UUID).exists());
+        assert!(!state.scheduler.servers.lock().unwrap().contains_key(UUID));
+    }
+
+    #[tokio::test]
+    async fn test_start_reloads_enabled_schedules() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let root = tmp.path().join("data").join(UUID);
+        std::fs::create_dir_all(&root).unwrap();
+        let mut disabled = schedule("b", "0 * * * *");
+        disabled.enabled = false;
+        let json = serde_json::to_vec(&[schedule("a", "0 4 * * *"), disabled]).unwrap();
+        std::fs::write(root.join(SCHEDULES_FILE), json).unwrap();
+
+        let state = test_node(tmp.path()).0;
+        start(&state);
+        let servers = state.scheduler.servers.lock().unwrap();
+        assert_eq!(servers[UUID].len(), 1);
+        assert_eq!(servers[UUID][0].schedule.id, "a");
+    }
+
+    #[tokio::test]
+    async fn test_run_reports_result() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let state = test_node(tmp.path()).0;
+        let mut events = state.subscribe_events();
+        let mut nightly = schedule("a", "0 4 * * *");
+        let cron = nightly.parse_cron().unwrap();
+
+        let completed = |event: WingsEvent| match event.event {
+            Some(wings_event::Event::ScheduleCompleted(done)) => done,
+            other => panic!("unexpected event {other:?}"),
+        };
+
+        // Docker isn't reachable, so the server counts as offline
+        run(&state, UUID, &nightly, &cron).await;
+        let done = completed(events.recv().await.unwrap());
+        assert!(done.skipped && !done.successful);
+        assert_eq!(done.actions_completed, 0);
+        assert!(done.next_run_at_ms > done.started_at_ms);
+
+        nightly.only_when_online = false;
+        run(&state, UUID, &nightly, &cron).await;
+        let done = completed(events.recv().await.unwrap());
+        assert!(!done.skipped && !done.successful);
+        assert!(
+            done.error_message.starts_with("Step 1 failed"),
+            "{}",
+            done.error_message
+        );
+    }
+
+    #[tokio::test]
+    async fn test_power_waits_for_server_lock() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let state = test_node(tmp.path()).0;
+        let lock = state.server_lock(UUID);
+        let guard = lock.lock().await;
+
+        let task = tokio::spawn({
+            let state = state.clone();
+            async move { power(&state, UUID, PowerSignal::Kill).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!task.is_finished());
+
+        drop(guard);
+        // Docker isn't reachable
+        assert!(task.await.unwrap().is_err());
+    }
+}
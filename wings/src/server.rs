@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use axum::extract::Request;
+use axum::extract::{DefaultBodyLimit, Request};
 use axum::middleware::{self, Next};
 use axum::response::Response;
 use axum::routing::{delete, get, post, put};
@@ -36,6 +36,7 @@ pub fn build_router(state: Arc<AppState>) -> Router {
     let protected_routes = Router::new()
         // System
         .route("/api/system", get(routes::system::system_info))
+        .route("/api/system/ports", get(routes::system::used_ports))
         // Servers
         .route("/api/servers", post(routes::servers::create_server))
         .route("/api/servers/{uuid}", delete(routes::servers::delete_server))
@@ -59,18 +60,50 @@ pub fn build_router(state: Arc<AppState>) -> Router {
             "/api/servers/{uuid}/install",
             post(routes::servers::install_server),
         )
+        // Backups
+        .route(
+            "/api/servers/{uuid}/backups",
+            get(routes::backups::list_backups).post(routes::backups::create_backup),
+        )
+        .route(
+            "/api/servers/{uuid}/backups/{backup_uuid}",
+            delete(routes::backups::delete_backup),
+        )
+        .route(
+            "/api/servers/{uuid}/backups/{backup_uuid}/restore",
+            post(routes::backups::restore_backup),
+        )
+        .route(
+            "/api/servers/{uuid}/backups/{backup_uuid}/verify",
+            post(routes::backups::verify_backup),
+        )
+        .route(
+            "/api/servers/{uuid}/backups/prune",
+            post(routes::backups::prune_backups),
+        )
+        // Transfers
+        .route("/api/transfers", post(routes::transfers::accept_transfer))
+        .route(
+            "/api/servers/{uuid}/transfer",
+            post(routes::transfers::start_transfer),
+        )
         // Files
         .route(
             "/api/servers/{uuid}/files",
             get(routes::files::list_files),
         )
+        .route(
+            "/api/servers/{uuid}/files/search",
+            get(routes::files::search_files),
+        )
         .route(
             "/api/servers/{uuid}/files/read",
             get(routes::files::read_file),
         )
         .route(
             "/api/servers/{uuid}/files/write",
-            post(routes::files::write_file),
+            post(routes::files::write_file)
+                .layer(DefaultBodyLimit::max(routes::files::MAX_WRITE_BODY)),
         )
         .route(
             "/api/servers/{uuid}/files/directory",
@@ -84,6 +117,18 @@ pub fn build_router(state: Arc<AppState>) -> Router {
             "/api/servers/{uuid}/files/delete",
             post(routes::files::delete_files),
         )
+        .route(
+            "/api/servers/{uuid}/files/copy",
+            post(routes::files::copy_files),
+        )
+        .route(
+            "/api/servers/{uuid}/files/move",
+            post(routes::files::move_files),
+        )
+        .route(
+            "/api/servers/{uuid}/files/chmod",
+            post(routes::files::chmod_files),
+        )
         .route(
             "/api/servers/{uuid}/files/compress",
             post(routes::files::compress_files),
@@ -96,12 +141,31 @@ pub fn build_router(state: Arc<AppState>) -> Router {
             "/api/servers/{uuid}/files/upload",
             post(routes::files::upload_file),
         )
+        .route(
+            "/api/servers/{uuid}/files/jobs",
+            get(routes::files::list_jobs),
+        )
+        .route(
+            "/api/servers/{uuid}/files/jobs/{job_id}/cancel",
+            post(routes::files::cancel_job),
+        )
         .layer(middleware::from_fn(auth_middleware))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             inject_config,
         ));
 
+    // Node-to-node transfer routes (auth via transfer token)
+    let transfer_routes = Router::new()
+        .route(
+            "/api/transfers/{uuid}/archive",
+            put(routes::transfers::receive_archive),
+        )
+        .route(
+            "/api/transfers/{uuid}/complete",
+            post(routes::transfers::complete_transfer),
+        );
+
     // WebSocket routes (auth via query param)
     let ws_routes = Router::new().route(
         "/api/servers/{uuid}/ws",
@@ -111,6 +175,7 @@ pub fn build_router(state: Arc<AppState>) -> Router {
     Router::new()
         .merge(public_routes)
         .merge(protected_routes)
+        .merge(transfer_routes)
         .merge(ws_routes)
         .layer(TraceLayer::new_for_http())
         .layer(cors)
@@ -0,0 +1,777 @@
+//! SFTP requests of one authenticated user, confined to a server's root.
+
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use russh_sftp::protocol::{
+    Attrs, Data, File, FileAttributes, Handle, Name, OpenFlags, Status, StatusCode, Version,
+};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use crate::config::ContainerUser;
+use crate::error::WingsError;
+use crate::files;
+use crate::state::AppState;
+
+/// Largest chunk returned for a single read request.
+const MAX_READ_LEN: u32 = 256 * 1024;
+/// Directory entries returned per READDIR response.
+const READDIR_BATCH: usize = 128;
+
+/// What a subuser may do over SFTP, from the Panel's permission names.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Permissions {
+    /// List directories and stat files
+    pub read: bool,
+    /// Download file contents
+    pub read_content: bool,
+    /// Create files and directories
+    pub create: bool,
+    /// Modify, rename and chmod existing files
+    pub update: bool,
+    pub delete: bool,
+}
+
+impl Permissions {
+    pub fn from_names<S: AsRef<str>>(names: &[S]) -> Self {
+        let mut perms = Self::default();
+        for name in names {
+            match name.as_ref() {
+                "*" => {
+                    return Self {
+                        read: true,
+                        read_content: true,
+                        create: true,
+                        update: true,
+                        delete: true,
+                    }
+                }
+                "file.read" => perms.read = true,
+                "file.read-content" => perms.read_content = true,
+                "file.create" => perms.create = true,
+                "file.update" => perms.update = true,
+                "file.delete" => perms.delete = true,
+                _ => {}
+            }
+        }
+        perms
+    }
+}
+
+/// Disk quota of the server a session belongs to, checked the way the
+/// HTTP file routes check it.
+pub struct Quota {
+    state: Arc<AppState>,
+    server: String,
+    /// Bytes writes may still add, as of the last file opened for writing
+    remaining: Option<u64>,
+}
+
+impl Quota {
+    pub fn new(state: Arc<AppState>, server: String) -> Self {
+        Self {
+            state,
+            server,
+            remaining: None,
+        }
+    }
+
+    async fn refresh(&mut self) -> Result<(), StatusCode> {
+        self.remaining = self.state.remaining_quota(&self.server).await;
+        match self.remaining {
+            Some(0) => Err(status(WingsError::QuotaExceeded)),
+            _ => Ok(()),
+        }
+    }
+
+    fn take(&mut self, bytes: u64) -> Result<(), StatusCode> {
+        match &mut self.remaining {
+            Some(remaining) if bytes > *remaining => Err(status(WingsError::QuotaExceeded)),
+            Some(remaining) => {
+                *remaining -= bytes;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+enum OpenHandle {
+    File(tokio::fs::File),
+    Dir(Vec<File>),
+}
+
+pub struct ServerHandler {
+    root: PathBuf,
+    perms: Permissions,
+    owner: Option<ContainerUser>,
+    quota: Option<Quota>,
+    handles: HashMap<String, OpenHandle>,
+    next_handle: u64,
+}
+
+fn status(err: WingsError) -> StatusCode {
+    match err {
+        WingsError::PathTraversal => StatusCode::PermissionDenied,
+        WingsError::Io(e) => io_status(e),
+        _ => StatusCode::Failure,
+    }
+}
+
+fn io_status(err: std::io::Error) -> StatusCode {
+    match err.kind() {
+        std::io::ErrorKind::NotFound => StatusCode::NoSuchFile,
+        std::io::ErrorKind::PermissionDenied => StatusCode::PermissionDenied,
+        _ => StatusCode::Failure,
+    }
+}
+
+fn ok(id: u32) -> Status {
+    Status {
+        id,
+        status_code: StatusCode::Ok,
+        error_message: "Ok".to_string(),
+        language_tag: "en-US".to_string(),
+    }
+}
+
+fn allow(granted: bool) -> Result<(), StatusCode> {
+    if granted {
+        Ok(())
+    } else {
+        Err(StatusCode::PermissionDenied)
+    }
+}
+
+/// Absolute form of a client path as seen inside the server root. Clients
+/// start out in `/`, and `..` never climbs above it.
+fn virtual_path(path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(c) => parts.push(c.to_str().unwrap_or_default()),
+            Component::ParentDir => {
+                parts.pop();
+            }
+            _ => {}
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
+
+impl ServerHandler {
+    pub fn new(
+        root: PathBuf,
+        perms: Permissions,
+        owner: Option<ContainerUser>,
+        quota: Option<Quota>,
+    ) -> Self {
+        Self {
+            root,
+            perms,
+            owner,
+            quota,
+            handles: HashMap::new(),
+            next_handle: 0,
+        }
+    }
+
+    /// Locate `path` without following a symlink in its last component.
+    /// The parent directory must exist and lie inside the root.
+    fn entry(&self, path: &str) -> Result<PathBuf, StatusCode> {
+        let path = virtual_path(path);
+        let path = Path::new(&path);
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return files::validate_path(&self.root, "/").map_err(status);
+        };
+        if files::is_wings_file(&name.to_string_lossy()) {
+            return Err(StatusCode::NoSuchFile);
+        }
+        let dir = files::validate_path(&self.root, &parent.to_string_lossy()).map_err(status)?;
+        if !dir.is_dir() {
+            return Err(StatusCode::NoSuchFile);
+        }
+        Ok(dir.join(name))
+    }
+
+    /// Locate `path`, following a symlink in its last component as long as
+    /// its target stays inside the root.
+    fn resolve(&self, path: &str) -> Result<PathBuf, StatusCode> {
+        let entry = self.entry(path)?;
+        match entry.symlink_metadata() {
+            Ok(meta) if meta.file_type().is_symlink() => {
+                if !entry.exists() {
+                    return Err(StatusCode::NoSuchFile);
+                }
+                files::validate_path(&self.root, &virtual_path(path)).map_err(status)
+            }
+            _ => Ok(entry),
+        }
+    }
+
+    fn is_root(&self, path: &Path) -> bool {
+        self.root.canonicalize().is_ok_and(|root| root == path)
+    }
+
+    fn insert_handle(&mut self, handle: OpenHandle) -> String {
+        self.next_handle += 1;
+        let id = self.next_handle.to_string();
+        self.handles.insert(id.clone(), handle);
+        id
+    }
+
+    fn file(&mut self, handle: &str) -> Result<&mut tokio::fs::File, StatusCode> {
+        match self.handles.get_mut(handle) {
+            Some(OpenHandle::File(file)) => Ok(file),
+            _ => Err(StatusCode::Failure),
+        }
+    }
+
+    fn chown(&self, path: &Path) {
+        if let Err(e) = files::chown_recursive(path, self.owner) {
+            tracing::warn!(path = %path.display(), error = %e, "Failed to chown SFTP upload");
+        }
+    }
+
+    async fn set_attrs(path: &Path, attrs: &FileAttributes) -> Result<(), StatusCode> {
+        if let Some(size) = attrs.size {
+            let file = tokio::fs::OpenOptions::new()
+                .write(true)
+                .custom_flags(nix::fcntl::OFlag::O_NOFOLLOW.bits())
+                .open(path)
+                .await
+                .map_err(io_status)?;
+            file.set_len(size).await.map_err(io_status)?;
+        }
+        if let Some(mode) = attrs.permissions {
+            files::chmod(path, mode & 0o777).map_err(status)?;
+        }
+        Ok(())
+    }
+}
+
+impl russh_sftp::server::Handler for ServerHandler {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn realpath(&mut self, id: u32, path: String) -> Result<Name, Self::Error> {
+        Ok(Name {
+            id,
+            files: vec![File::dummy(virtual_path(&path))],
+        })
+    }
+
+    async fn stat(&mut self, id: u32, path: String) -> Result<Attrs, Self::Error> {
+        allow(self.perms.read)?;
+        let path = self.resolve(&path)?;
+        let meta = tokio::fs::metadata(&path).await.map_err(io_status)?;
+        Ok(Attrs {
+            id,
+            attrs: FileAttributes::from(&meta),
+        })
+    }
+
+    async fn lstat(&mut self, id: u32, path: String) -> Result<Attrs, Self::Error> {
+        allow(self.perms.read)?;
+        let path = self.entry(&path)?;
+        let meta = tokio::fs::symlink_metadata(&path)
+            .await
+            .map_err(io_status)?;
+        let mut attrs = FileAttributes::from(&meta);
+        attrs.set_symlink(meta.file_type().is_symlink());
+        Ok(Attrs { id, attrs })
+    }
+
+    async fn fstat(&mut self, id: u32, handle: String) -> Result<Attrs, Self::Error> {
+        let meta = self.file(&handle)?.metadata().await.map_err(io_status)?;
+        Ok(Attrs {
+            id,
+            attrs: FileAttributes::from(&meta),
+        })
+    }
+
+    async fn setstat(
+        &mut self,
+        id: u32,
+        path: String,
+        attrs: FileAttributes,
+    ) -> Result<Status, Self::Error> {
+        allow(self.perms.update)?;
+        let path = self.resolve(&path)?;
+        Self::set_attrs(&path, &attrs).await?;
+        Ok(ok(id))
+    }
+
+    async fn fsetstat(
+        &mut self,
+        id: u32,
+        handle: String,
+        attrs: FileAttributes,
+    ) -> Result<Status, Self::Error> {
+        allow(self.perms.update)?;
+        let file = self.file(&handle)?;
+        if let Some(size) = attrs.size {
+            file.set_len(size).await.map_err(io_status)?;
+        }
+        if let Some(mode) = attrs.permissions {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(std::fs::Permissions::from_mode(mode & 0o777))
+                .await
+                .map_err(io_status)?;
+        }
+        Ok(ok(id))
+    }
+
+    async fn open(
+        &mut self,
+        id: u32,
+        filename: String,
+        pflags: OpenFlags,
+        _attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        let path = self.resolve(&filename)?;
+        let exists = path.exists();
+        let writes = pflags.intersects(OpenFlags::WRITE | OpenFlags::APPEND | OpenFlags::TRUNCATE);
+        if pflags.contains(OpenFlags::READ) {
+            allow(self.perms.read_content)?;
+        }
+        if writes && exists {
+            allow(self.perms.update)?;
+        }
+        if !exists {
+            if !pflags.contains(OpenFlags::CREATE) {
+                return Err(StatusCode::NoSuchFile);
+            }
+            allow(self.perms.create)?;
+        }
+        if writes {
+            if let Some(quota) = &mut self.quota {
+                quota.refresh().await?;
+            }
+        }
+
+        let mut options: std::fs::OpenOptions = pflags.into();
+        options.custom_flags(nix::fcntl::OFlag::O_NOFOLLOW.bits());
+        let file = tokio::fs::OpenOptions::from(options)
+            .open(&path)
+            .await
+            .map_err(io_status)?;
+        if !exists {
+            self.chown(&path);
+        }
+
+        Ok(Handle {
+            id,
+            handle: self.insert_handle(OpenHandle::File(file)),
+        })
+    }
+
+    async fn read(
+        &mut self,
+        id: u32,
+        handle: String,
+        offset: u64,
+        len: u32,
+    ) -> Result<Data, Self::Error> {
+        let file = self.file(&handle)?;
+        file.seek(SeekFrom::Start(offset))
+            .await
+            .map_err(io_status)?;
+        let mut data = vec![0u8; len.min(MAX_READ_LEN) as usize];
+        let n = file.read(&mut data).await.map_err(io_status)?;
+        if n == 0 && !data.is_empty() {
+            return Err(StatusCode::Eof);
+        }
+        data.truncate(n);
+        Ok(Data { id, data })
+    }
+
+    async fn write(
+        &mut self,
+        id: u32,
+        handle: String,
+        offset: u64,
+        data: Vec<u8>,
+    ) -> Result<Status, Self::Error> {
+        // Only bytes past the end of the file take up more space
+        let len = self
+            .file(&handle)?
+            .metadata()
+            .await
+            .map_err(io_status)?
+            .len();
+        if let Some(quota) = &mut self.quota {
+            quota.take((offset + data.len() as u64).saturating_sub(len))?;
+        }
+        let file = self.file(&handle)?;
+        file.seek(SeekFrom::Start(offset))
+            .await
+            .map_err(io_status)?;
+        file.write_all(&data).await.map_err(io_status)?;
+        Ok(ok(id))
+    }
+
+    async fn close(&mut self, id: u32, handle: String) -> Result<Status, Self::Error> {
+        match self.handles.remove(&handle) {
+            Some(OpenHandle::File(mut file)) => file.flush().await.map_err(io_status)?,
+            Some(OpenHandle::Dir(_)) => {}
+            None => return Err(StatusCode::Failure),
+        }
+        Ok(ok(id))
+    }
+
+    async fn opendir(&mut self, id: u32, path: String) -> Result<Handle, Self::Error> {
+        allow(self.perms.read)?;
+        let path = self.resolve(&path)?;
+        let mut dir = tokio::fs::read_dir(&path).await.map_err(io_status)?;
+
+        let mut entries = Vec::new();
+        while let Some(entry) = dir.next_entry().await.map_err(io_status)? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if files::is_wings_file(&name) {
+                continue;
+            }
+            let Ok(meta) = entry.metadata().await else {
+                continue;
+            };
+            let mut attrs = FileAttributes::from(&meta);
+            attrs.set_symlink(meta.file_type().is_symlink());
+            entries.push(File::new(name, attrs));
+        }
+        // Handed out from the back
+        entries.reverse();
+
+        Ok(Handle {
+            id,
+            handle: self.insert_handle(OpenHandle::Dir(entries)),
+        })
+    }
+
+    async fn readdir(&mut self, id: u32, handle: String) -> Result<Name, Self::Error> {
+        let Some(OpenHandle::Dir(entries)) = self.handles.get_mut(&handle) else {
+            return Err(StatusCode::Failure);
+        };
+        if entries.is_empty() {
+            return Err(StatusCode::Eof);
+        }
+        let batch = entries.split_off(entries.len().saturating_sub(READDIR_BATCH));
+        Ok(Name {
+            id,
+            files: batch.into_iter().rev().collect(),
+        })
+    }
+
+    async fn mkdir(
+        &mut self,
+        id: u32,
+        path: String,
+        _attrs: FileAttributes,
+    ) -> Result<Status, Self::Error> {
+        allow(self.perms.create)?;
+        let path = self.entry(&path)?;
+        tokio::fs::create_dir(&path).await.map_err(io_status)?;
+        self.chown(&path);
+        Ok(ok(id))
+    }
+
+    async fn remove(&mut self, id: u32, filename: String) -> Result<Status, Self::Error> {
+        allow(self.perms.delete)?;
+        let path = self.entry(&filename)?;
+        tokio::fs::remove_file(&path).await.map_err(io_status)?;
+        Ok(ok(id))
+    }
+
+    async fn rmdir(&mut self, id: u32, path: String) -> Result<Status, Self::Error> {
+        allow(self.perms.delete)?;
+        let path = self.entry(&path)?;
+        if self.is_root(&path) {
+            return Err(StatusCode::PermissionDenied);
+        }
+        tokio::fs::remove_dir(&path).await.map_err(io_status)?;
+        Ok(ok(id))
+    }
+
+    async fn rename(
+        &mut self,
+        id: u32,
+        oldpath: String,
+        newpath: String,
+    ) -> Result<Status, Self::Error> {
+        allow(self.perms.update)?;
+        let from = self.entry(&oldpath)?;
+        let to = self.entry(&newpath)?;
+        if self.is_root(&from) || self.is_root(&to) {
+            return Err(StatusCode::PermissionDenied);
+        }
+        if to.symlink_metadata().is_ok() {
+            return Err(StatusCode::Failure);
+        }
+        files::rename_entry(&from, &to).map_err(status)?;
+        Ok(ok(id))
+    }
+
+    async fn readlink(&mut self, id: u32, path: String) -> Result<Name, Self::Error> {
+        allow(self.perms.read)?;
+        let path = self.entry(&path)?;
+        let target = tokio::fs::read_link(&path).await.map_err(io_status)?;
+        // Only reveal targets that resolve inside the root, as root-relative
+        // paths
+        let root = self.root.canonicalize().map_err(io_status)?;
+        let target = if target.is_absolute() {
+            match target.strip_prefix(&root) {
+                Ok(rel) => format!("/{}", rel.to_string_lossy()),
+                Err(_) => return Err(StatusCode::NoSuchFile),
+            }
+        } else {
+            target.to_string_lossy().to_string()
+        };
+        Ok(Name {
+            id,
+            files: vec![File::dummy(target)],
+        })
+    }
+
+    // Symlinks could point anywhere on the node, so they can't be created
+    async fn symlink(
+        &mut self,
+        _id: u32,
+        _linkpath: String,
+        _targetpath: String,
+    ) -> Result<Status, Self::Error> {
+        Err(StatusCode::PermissionDenied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use russh_sftp::server::Handler;
+
+    fn handler(root: &Path, perms: Permissions) -> ServerHandler {
+        ServerHandler::new(root.to_path_buf(), perms, None, None)
+    }
+
+    #[test]
+    fn test_permissions_from_names() {
+        let perms = Permissions::from_names(&["file.read", "file.read-content", "control.start"]);
+        assert!(perms.read && perms.read_content);
+        assert!(!perms.create && !perms.update && !perms.delete);
+
+        let all = Permissions::from_names(&["*"]);
+        assert!(all.read && all.read_content && all.create && all.update && all.delete);
+    }
+
+    #[test]
+    fn test_virtual_path_stays_in_root() {
+        assert_eq!(virtual_path("."), "/");
+        assert_eq!(virtual_path(""), "/");
+        assert_eq!(virtual_path("../../etc/passwd"), "/etc/passwd");
+        assert_eq!(
+            virtual_path("/world/./region/../level.dat"),
+            "/world/level.dat"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_paths_are_scoped_to_root() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().join("server");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(tmp.path().join("secret.txt"), "node secret").unwrap();
+        std::os::unix::fs::symlink(tmp.path(), root.join("escape")).unwrap();
+        std::os::unix::fs::symlink(tmp.path().join("secret.txt"), root.join("secret")).unwrap();
+
+        let mut h = handler(&root, Permissions::from_names(&["*"]));
+
+        // `..` resolves against the root, not the host filesystem
+        let err = h.stat(1, "../secret.txt".into()).await.unwrap_err();
+        assert_eq!(err, StatusCode::NoSuchFile);
+
+        // Symlinks leading out of the root can't be followed
+        let err = h.opendir(2, "/escape".into()).await.unwrap_err();
+        assert_eq!(err, StatusCode::PermissionDenied);
+        let err = h
+            .open(
+                3,
+                "/escape/secret.txt".into(),
+                OpenFlags::READ,
+                FileAttributes::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err, StatusCode::PermissionDenied);
+        let err = h
+            .open(
+                4,
+                "/secret".into(),
+                OpenFlags::READ,
+                FileAttributes::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err, StatusCode::PermissionDenied);
+        let err = h
+            .open(
+                5,
+                "/escape/new.txt".into(),
+                OpenFlags::WRITE | OpenFlags::CREATE,
+                FileAttributes::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err, StatusCode::PermissionDenied);
+        assert!(!tmp.path().join("new.txt").exists());
+
+        // Removing the link only removes the link
+        h.remove(6, "/escape".into()).await.unwrap();
+        assert!(tmp.path().join("secret.txt").exists());
+
+        let err = h.rmdir(7, "/".into()).await.unwrap_err();
+        assert_eq!(err, StatusCode::PermissionDenied);
+        let err = h
+            .symlink(8, "/link".into(), "/etc".into())
+            .await
+            .unwrap_err();
+        assert_eq!(err, StatusCode::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn test_permissions_are_enforced() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::write(tmp.path().join("server.properties"), "motd=hi").unwrap();
+
+        let mut h = handler(tmp.path(), Permissions::from_names(&["file.read"]));
+        h.stat(1, "/server.properties".into()).await.unwrap();
+        let err = h
+            .open(
+                2,
+                "/server.properties".into(),
+                OpenFlags::READ,
+                FileAttributes::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err, StatusCode::PermissionDenied);
+        let err = h
+            .open(
+                3,
+                "/new.txt".into(),
+                OpenFlags::WRITE | OpenFlags::CREATE,
+                FileAttributes::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err, StatusCode::PermissionDenied);
+        let err = h.remove(4, "/server.properties".into()).await.unwrap_err();
+        assert_eq!(err, StatusCode::PermissionDenied);
+
+        let mut h = handler(tmp.path(), Permissions::from_names(&["file.create"]));
+        let handle = h
+            .open(
+                5,
+                "/new.txt".into(),
+                OpenFlags::WRITE | OpenFlags::CREATE,
+                FileAttributes::default(),
+            )
+            .await
+            .unwrap()
+            .handle;
+        h.write(6, handle.clone(), 0, b"hello".to_vec())
+            .await
+            .unwrap();
+        h.close(7, handle).await.unwrap();
+        assert_eq!(std::fs::read(tmp.path().join("new.txt")).unwrap(), b"hello");
+
+        // Creating doesn't grant overwriting
+        let err = h
+            .open(
+                8,
+                "/server.properties".into(),
+                OpenFlags::WRITE | OpenFlags::TRUNCATE,
+                FileAttributes::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err, StatusCode::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn test_writes_respect_disk_quota() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (state, _events) = crate::state::test_node(tmp.path());
+        let uuid = "1a2b3c4d-0000-4000-8000-000000000000";
+        let config = serde_json::from_value(serde_json::json!({
+            "uuid": uuid,
+            "docker_image": "ghcr.io/nexus/java:21",
+            "startup_command": "java -jar server.jar",
+            "environment": {},
+            "memory_limit": 1024,
+            "cpu_limit": 100,
+            "disk_limit": 1,
+            "port_mappings": [],
+            "volume_path": "/data/server",
+        }))
+        .unwrap();
+        state.store_server_config(&config).await;
+        let root = tmp.path().join("data").join(uuid);
+        let quota = Quota::new(state.clone(), uuid.to_string());
+        let mut h = ServerHandler::new(
+            root.clone(),
+            Permissions::from_names(&["*"]),
+            None,
+            Some(quota),
+        );
+
+        let handle = h
+            .open(
+                1,
+                "/world.dat".into(),
+                OpenFlags::WRITE | OpenFlags::CREATE,
+                FileAttributes::default(),
+            )
+            .await
+            .unwrap()
+            .handle;
+        let half = vec![0u8; 512 * 1024];
+        h.write(2, handle.clone(), 0, half.clone()).await.unwrap();
+        // Rewriting what is there takes no more space
+        h.write(3, handle.clone(), 0, half.clone()).await.unwrap();
+        let err = h
+            .write(4, handle.clone(), half.len() as u64, half.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(err, StatusCode::Failure);
+        h.close(5, handle).await.unwrap();
+        assert_eq!(
+            std::fs::metadata(root.join("world.dat")).unwrap().len(),
+            half.len() as u64
+        );
+
+        // A full server can't open files for writing
+        std::fs::write(root.join("filler"), vec![0u8; 512 * 1024]).unwrap();
+        let err = h
+            .open(
+                6,
+                "/world.dat".into(),
+                OpenFlags::WRITE,
+                FileAttributes::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err, StatusCode::Failure);
+    }
+}
@@ -0,0 +1,257 @@
+//! Built-in SFTP server giving users direct access to their server files.
+//!
+//! Users log in as `<username>.<server short id>` with their Panel password.
+//! The Panel checks the credentials and answers with the server and the
+//! user's permissions on it; the session is then confined to that server's
+//! root with those permissions.
+
+mod handler;
+mod transport;
+
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+
+use russh::keys::ssh_key::private::Ed25519Keypair;
+use russh::keys::PrivateKey;
+use serde::{Deserialize, Serialize};
+
+use crate::error::WingsError;
+use crate::state::AppState;
+
+use handler::{Permissions, Quota, ServerHandler};
+
+/// Length of the server id suffix in SFTP usernames.
+const SHORT_ID_LEN: usize = 8;
+
+#[derive(Serialize)]
+struct AuthRequest<'a> {
+    username: &'a str,
+    password: &'a str,
+}
+
+#[derive(Deserialize)]
+struct AuthResponse {
+    /// UUID of the server the credentials grant access to
+    server: String,
+    #[serde(default)]
+    permissions: Vec<String>,
+}
+
+/// Split an SFTP username into the Panel username and the server short id.
+fn parse_username(username: &str) -> Option<(&str, &str)> {
+    let (user, short_id) = username.rsplit_once('.')?;
+    let valid = !user.is_empty()
+        && short_id.len() == SHORT_ID_LEN
+        && short_id.chars().all(|c| c.is_ascii_hexdigit());
+    valid.then_some((user, short_id))
+}
+
+/// Whether `uuid` is the server `short_id` refers to.
+fn matches_short_id(uuid: &str, short_id: &str) -> bool {
+    uuid.replace('-', "")
+        .to_ascii_lowercase()
+        .starts_with(&short_id.to_ascii_lowercase())
+}
+
+fn auth_url(state: &AppState) -> String {
+    match &state.config.sftp.auth_url {
+        Some(url) => url.clone(),
+        None => format!(
+            "{}/api/v1/nodes/sftp/auth",
+            state.config.panel.url.trim_end_matches('/')
+        ),
+    }
+}
+
+/// Check credentials with the Panel and build the session for the server
+/// they grant access to.
+async fn authenticate(
+    state: &Arc<AppState>,
+    client: &reqwest::Client,
+    peer: SocketAddr,
+    username: &str,
+    password: &str,
+) -> Option<ServerHandler> {
+    let (_, short_id) = parse_username(username)?;
+
+    let response = client
+        .post(auth_url(state))
+        .bearer_auth(format!(
+            "{}.{}",
+            state.config.panel.token_id, state.config.panel.token
+        ))
+        .json(&AuthRequest { username, password })
+        .send()
+        .await;
+    let auth: AuthResponse = match response {
+        Ok(resp) if resp.status().is_success() => match resp.json().await {
+            Ok(auth) => auth,
+            Err(e) => {
+                tracing::warn!("Invalid SFTP auth response from Panel: {e}");
+                return None;
+            }
+        },
+        Ok(resp) => {
+            tracing::info!(%peer, username, status = %resp.status(), "SFTP login rejected");
+            return None;
+        }
+        Err(e) => {
+            tracing::warn!("SFTP auth request to Panel failed: {e}");
+            return None;
+        }
+    };
+
+    // The uuid becomes a path; only trust a well-formed one for the server
+    // the username named
+    if uuid::Uuid::parse_str(&auth.server).is_err() || !matches_short_id(&auth.server, short_id) {
+        tracing::warn!(%peer, username, server = %auth.server, "Panel granted SFTP access to another server");
+        return None;
+    }
+    let root = Path::new(&state.config.storage.data_dir).join(&auth.server);
+    if !root.is_dir() {
+        tracing::info!(%peer, username, server = %auth.server, "SFTP login for server not on this node");
+        return None;
+    }
+
+    tracing::info!(%peer, username, server = %auth.server, "SFTP login");
+    Some(ServerHandler::new(
+        root,
+        Permissions::from_names(&auth.permissions),
+        state.docker.container_user(),
+        Some(Quota::new(state.clone(), auth.server)),
+    ))
+}
+
+/// Load the host key, generating one on first start. The file holds the
+/// hex-encoded Ed25519 seed.
+fn load_host_key(path: &Path) -> Result<PrivateKey, WingsError> {
+    match std::fs::read_to_string(path) {
+        Ok(hex_seed) => {
+            let seed: [u8; 32] = hex::decode(hex_seed.trim())
+                .ok()
+                .and_then(|seed| seed.try_into().ok())
+                .ok_or_else(|| {
+                    WingsError::Config(format!("Invalid SFTP host key in {}", path.display()))
+                })?;
+            Ok(Ed25519Keypair::from_seed(&seed).into())
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            use std::io::Write;
+            use std::os::unix::fs::OpenOptionsExt;
+
+            let mut seed = [0u8; 32];
+            getrandom::getrandom(&mut seed)
+                .map_err(|e| WingsError::Io(std::io::Error::other(e.to_string())))?;
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let mut file = std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(path)?;
+            writeln!(file, "{}", hex::encode(seed))?;
+            tracing::info!("Generated SFTP host key at {}", path.display());
+            Ok(Ed25519Keypair::from_seed(&seed).into())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Start the SFTP listener if it is enabled.
+pub async fn start(
+    state: Arc<AppState>,
+    mut shutdown: tokio::sync::watch::Receiver<()>,
+) -> Result<(), WingsError> {
+    let cfg = &state.config.sftp;
+    if !cfg.enabled {
+        return Ok(());
+    }
+    let config = transport::config(load_host_key(Path::new(&cfg.host_key))?);
+    let addr = format!("{}:{}", cfg.host, cfg.port);
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    tracing::info!("SFTP server listening on {addr}");
+
+    shutdown.borrow_and_update();
+    tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        loop {
+            let (stream, peer) = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        tracing::warn!("Failed to accept SFTP connection: {e}");
+                        continue;
+                    }
+                },
+                _ = shutdown.changed() => {
+                    tracing::info!("SFTP server shutting down");
+                    return;
+                }
+            };
+
+            let state = state.clone();
+            let client = client.clone();
+            let config = config.clone();
+            tokio::spawn(async move {
+                let auth = move |username: String, password: String| {
+                    let state = state.clone();
+                    let client = client.clone();
+                    async move { authenticate(&state, &client, peer, &username, &password).await }
+                };
+                let connection = transport::Connection::new(auth);
+                let result = match russh::server::run_stream(config, stream, connection).await {
+                    Ok(session) => session.await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    tracing::debug!(%peer, "SFTP connection closed: {e}");
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_username() {
+        assert_eq!(
+            parse_username("steve.1a2b3c4d"),
+            Some(("steve", "1a2b3c4d"))
+        );
+        assert_eq!(
+            parse_username("first.last.1A2B3C4D"),
+            Some(("first.last", "1A2B3C4D"))
+        );
+        assert_eq!(parse_username("steve"), None);
+        assert_eq!(parse_username(".1a2b3c4d"), None);
+        assert_eq!(parse_username("steve.1a2b3c"), None);
+        assert_eq!(parse_username("steve.../../x"), None);
+    }
+
+    #[test]
+    fn test_matches_short_id() {
+        let uuid = "1a2b3c4d-0000-4000-8000-000000000000";
+        assert!(matches_short_id(uuid, "1a2b3c4d"));
+        assert!(matches_short_id(uuid, "1A2B3C4D"));
+        assert!(!matches_short_id(uuid, "deadbeef"));
+    }
+
+    #[test]
+    fn test_host_key_is_generated_once() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("keys/sftp_host_key");
+        let first = load_host_key(&path).unwrap();
+        let second = load_host_key(&path).unwrap();
+        assert_eq!(first.public_key(), second.public_key());
+
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
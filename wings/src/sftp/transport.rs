@@ -0,0 +1,235 @@
+//! SSH transport carrying nothing but the SFTP subsystem, built on russh.
+//!
+//! Clients authenticate with a password and get a single session channel
+//! whose only accepted request is `subsystem sftp`. Everything else is
+//! refused. russh negotiates strict key exchange with clients that support
+//! it, which closes the Terrapin prefix truncation attack.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use russh::keys::PrivateKey;
+use russh::server::{Auth, Config, Handler, Msg, Session};
+use russh::{Channel, ChannelId, MethodKind, MethodSet};
+
+/// Password attempts before the connection is dropped.
+const MAX_AUTH_ATTEMPTS: usize = 3;
+/// Time a connection may stay silent before it is dropped.
+const INACTIVITY_TIMEOUT: Duration = Duration::from_secs(600);
+
+fn methods() -> MethodSet {
+    MethodSet::from(&[MethodKind::Password][..])
+}
+
+/// Reject a login, letting the client try another password.
+fn retry() -> Auth {
+    Auth::Reject {
+        proceed_with_methods: Some(methods()),
+        partial_success: false,
+    }
+}
+
+/// Server settings shared by every connection.
+pub fn config(host_key: PrivateKey) -> Arc<Config> {
+    Arc::new(Config {
+        server_id: russh::SshId::Standard(format!(
+            "SSH-2.0-NexusWings_{}",
+            env!("CARGO_PKG_VERSION")
+        )),
+        methods: methods(),
+        // Clients probe with "none" before sending their password
+        auth_rejection_time_initial: Some(Duration::ZERO),
+        keys: vec![host_key],
+        max_auth_attempts: MAX_AUTH_ATTEMPTS,
+        inactivity_timeout: Some(INACTIVITY_TIMEOUT),
+        nodelay: true,
+        ..Default::default()
+    })
+}
+
+/// One SSH connection. `authenticate` is called with each username and
+/// password a client tries and returns the SFTP handler for the session,
+/// or `None` to reject the attempt.
+pub struct Connection<F, H> {
+    authenticate: F,
+    /// SFTP handler of the authenticated user, until the subsystem starts
+    handler: Option<H>,
+    channel: Option<Channel<Msg>>,
+}
+
+impl<F, H> Connection<F, H> {
+    pub fn new(authenticate: F) -> Self {
+        Self {
+            authenticate,
+            handler: None,
+            channel: None,
+        }
+    }
+}
+
+impl<F, Fut, H> Handler for Connection<F, H>
+where
+    F: FnMut(String, String) -> Fut + Send,
+    Fut: Future<Output = Option<H>> + Send,
+    H: russh_sftp::server::Handler + Send + 'static,
+{
+    type Error = russh::Error;
+
+    async fn auth_password(&mut self, user: &str, password: &str) -> Result<Auth, Self::Error> {
+        if self.handler.is_some() {
+            return Ok(Auth::reject());
+        }
+        match (self.authenticate)(user.to_string(), password.to_string()).await {
+            Some(handler) => {
+                self.handler = Some(handler);
+                Ok(Auth::Accept)
+            }
+            None => Ok(retry()),
+        }
+    }
+
+    async fn channel_open_session(
+        &mut self,
+        channel: Channel<Msg>,
+        _session: &mut Session,
+    ) -> Result<bool, Self::Error> {
+        // Only one session, and only until it runs SFTP
+        if self.handler.is_none() || self.channel.is_some() {
+            return Ok(false);
+        }
+        self.channel = Some(channel);
+        Ok(true)
+    }
+
+    async fn subsystem_request(
+        &mut self,
+        channel: ChannelId,
+        name: &str,
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        let open = self.channel.as_ref().is_some_and(|ch| ch.id() == channel);
+        if name != "sftp" || !open {
+            return session.channel_failure(channel);
+        }
+        let (Some(channel_stream), Some(handler)) = (self.channel.take(), self.handler.take())
+        else {
+            return session.channel_failure(channel);
+        };
+        session.channel_success(channel)?;
+        russh_sftp::server::run(channel_stream.into_stream(), handler).await;
+        Ok(())
+    }
+
+    async fn shell_request(
+        &mut self,
+        channel: ChannelId,
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        session.channel_failure(channel)
+    }
+
+    async fn exec_request(
+        &mut self,
+        channel: ChannelId,
+        _data: &[u8],
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        session.channel_failure(channel)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn pty_request(
+        &mut self,
+        channel: ChannelId,
+        _term: &str,
+        _col_width: u32,
+        _row_height: u32,
+        _pix_width: u32,
+        _pix_height: u32,
+        _modes: &[(russh::Pty, u32)],
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        session.channel_failure(channel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sftp::handler::{Permissions, ServerHandler};
+    use russh::keys::ssh_key::private::Ed25519Keypair;
+    use tokio::io::AsyncWriteExt;
+
+    struct Client;
+
+    impl russh::client::Handler for Client {
+        type Error = russh::Error;
+
+        async fn check_server_key(
+            &mut self,
+            _key: &russh::keys::PublicKey,
+        ) -> Result<bool, Self::Error> {
+            Ok(true)
+        }
+    }
+
+    #[test]
+    fn test_strict_kex_is_offered() {
+        let config = config(Ed25519Keypair::from_seed(&[7; 32]).into());
+        assert!(config
+            .preferred
+            .kex
+            .contains(&russh::kex::EXTENSION_OPENSSH_STRICT_KEX_AS_SERVER));
+    }
+
+    #[tokio::test]
+    async fn test_sftp_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_path_buf();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let auth = move |username: String, password: String| {
+                let handler =
+                    ServerHandler::new(root.clone(), Permissions::from_names(&["*"]), None, None);
+                async move { (username == "steve.1a2b3c4d" && password == "hunter2").then_some(handler) }
+            };
+            let config = config(Ed25519Keypair::from_seed(&[7; 32]).into());
+            let _ = russh::server::run_stream(config, stream, Connection::new(auth))
+                .await
+                .unwrap()
+                .await;
+        });
+
+        let mut session = russh::client::connect(Arc::new(Default::default()), addr, Client)
+            .await
+            .unwrap();
+        let auth = session
+            .authenticate_password("steve.1a2b3c4d", "nope")
+            .await;
+        assert!(!auth.unwrap().success());
+        let auth = session
+            .authenticate_password("steve.1a2b3c4d", "hunter2")
+            .await;
+        assert!(auth.unwrap().success());
+
+        let channel = session.channel_open_session().await.unwrap();
+        channel.request_subsystem(true, "sftp").await.unwrap();
+        let sftp = russh_sftp::client::SftpSession::new(channel.into_stream())
+            .await
+            .unwrap();
+        let mut file = sftp.create("hello.txt").await.unwrap();
+        file.write_all(b"hello").await.unwrap();
+        file.shutdown().await.unwrap();
+        assert_eq!(sftp.read("hello.txt").await.unwrap(), b"hello");
+        assert_eq!(
+            std::fs::read(dir.path().join("hello.txt")).unwrap(),
+            b"hello"
+        );
+
+        // The one session is taken
+        assert!(session.channel_open_session().await.is_err());
+    }
+}
@@ -0,0 +1,485 @@
+//! Incremental backups. Files are split with content-defined chunking and
+//! each chunk is stored once per server, compressed and addressed by its
+//! SHA-256. A snapshot is a manifest listing the chunks of every file, so a
+//! new snapshot only writes the chunks that changed since the last one.
+
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+use chrono::{DateTime, Datelike, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::WingsError;
+use crate::files::{self, ArchiveEntry, Compression, ExtractLimits, ProgressReader};
+use crate::jobs::JobProgress;
+
+const CHUNK_MIN_SIZE: u32 = 256 * 1024;
+const CHUNK_AVG_SIZE: u32 = 1024 * 1024;
+const CHUNK_MAX_SIZE: u32 = 4 * 1024 * 1024;
+const CHUNK_DIR: &str = "chunks";
+const ZSTD_LEVEL: i32 = 3;
+
+/// Buffers in flight between the tar writer and the extractor on restore.
+const PIPE_DEPTH: usize = 4;
+const PIPE_BUFFER: usize = 256 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ManifestEntry {
+    name: String,
+    kind: EntryKind,
+    mode: u32,
+    mtime: i64,
+    #[serde(default)]
+    size: u64,
+    /// Hashes of the file's chunks, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    chunks: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+}
+
+/// The files of one snapshot.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    fn chunks(&self) -> impl Iterator<Item = &String> {
+        self.entries.iter().flat_map(|e| e.chunks.iter())
+    }
+}
+
+/// Result of writing a snapshot.
+#[derive(Debug)]
+pub struct SnapshotStats {
+    /// Hex digest of the stored manifest
+    pub checksum: String,
+    /// Total size of the snapshotted files
+    pub size: u64,
+    pub new_chunks: u64,
+    /// Compressed size of the chunks written by this snapshot
+    pub new_bytes: u64,
+}
+
+/// Outcome of checking a backup against its checksums.
+#[derive(Debug, Default, Serialize)]
+pub struct VerifyReport {
+    pub valid: bool,
+    /// The archive, or the manifest of a chunked snapshot, is gone
+    pub archive_missing: bool,
+    pub checked_chunks: u64,
+    pub missing_chunks: Vec<String>,
+    pub corrupt_chunks: Vec<String>,
+}
+
+pub fn manifest_path(dir: &Path, backup_uuid: &str) -> PathBuf {
+    dir.join(format!("{backup_uuid}.manifest"))
+}
+
+fn chunk_path(dir: &Path, hash: &str) -> PathBuf {
+    dir.join(CHUNK_DIR).join(&hash[..2]).join(hash)
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// Store a chunk unless it already exists. Returns its hash and, if it was
+/// new, its compressed size.
+fn store_chunk(dir: &Path, data: &[u8]) -> std::io::Result<(String, Option<u64>)> {
+    let hash = sha256_hex(data);
+    let path = chunk_path(dir, &hash);
+    if path.exists() {
+        return Ok((hash, None));
+    }
+    std::fs::create_dir_all(path.parent().unwrap_or(dir))?;
+    let compressed = zstd::encode_all(data, ZSTD_LEVEL)?;
+    // Written aside and renamed so a crash never leaves a truncated chunk
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, &compressed)?;
+    std::fs::rename(&tmp_path, &path)?;
+    Ok((hash, Some(compressed.len() as u64)))
+}
+
+/// Read a chunk back, failing with `InvalidData` if it does not match its hash.
+fn read_chunk(dir: &Path, hash: &str) -> std::io::Result<Vec<u8>> {
+    let file = std::fs::File::open(chunk_path(dir, hash))?;
+    let data = zstd::decode_all(file)?;
+    if sha256_hex(&data) != hash {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("chunk {hash} is corrupt"),
+        ));
+    }
+    Ok(data)
+}
+
+/// Chunk `entries` into the chunk store in `dir` and write the manifest of
+/// snapshot `backup_uuid`.
+pub fn create(
+    dir: &Path,
+    backup_uuid: &str,
+    entries: &[ArchiveEntry],
+    progress: &JobProgress,
+) -> Result<SnapshotStats, WingsError> {
+    let mut manifest = Manifest::default();
+    let mut stats = SnapshotStats {
+        checksum: String::new(),
+        size: 0,
+        new_chunks: 0,
+        new_bytes: 0,
+    };
+
+    for entry in entries {
+        progress
+            .check_cancelled()
+            .map_err(|_| WingsError::Cancelled)?;
+        let metadata = &entry.metadata;
+        let mut item = ManifestEntry {
+            name: entry.name.clone(),
+            kind: EntryKind::File,
+            mode: metadata.permissions().mode() & 0o7777,
+            mtime: metadata.mtime(),
+            size: 0,
+            chunks: Vec::new(),
+            target: None,
+        };
+        if metadata.is_symlink() {
+            let target = std::fs::read_link(&entry.path).map_err(WingsError::Io)?;
+            item.kind = EntryKind::Symlink;
+            item.target = Some(target.to_string_lossy().to_string());
+        } else if metadata.is_dir() {
+            item.kind = EntryKind::Dir;
+        } else if metadata.is_file() {
+            let file = std::fs::File::open(&entry.path).map_err(WingsError::Io)?;
+            let reader = ProgressReader {
+                inner: file,
+                progress,
+            };
+            let chunker = fastcdc::v2020::StreamCDC::new(
+                reader,
+                CHUNK_MIN_SIZE,
+                CHUNK_AVG_SIZE,
+                CHUNK_MAX_SIZE,
+            );
+            for chunk in chunker {
+                let chunk = match chunk {
+                    Ok(chunk) => chunk,
+                    Err(_) if progress.is_cancelled() => return Err(WingsError::Cancelled),
+                    Err(e) => return Err(WingsError::Io(std::io::Error::other(e))),
+                };
+                let (hash, written) = store_chunk(dir, &chunk.data).map_err(WingsError::Io)?;
+                if let Some(bytes) = written {
+                    stats.new_chunks += 1;
+                    stats.new_bytes += bytes;
+                }
+                item.size += chunk.length as u64;
+                item.chunks.push(hash);
+            }
+            stats.size += item.size;
+        } else {
+            continue;
+        }
+        manifest.entries.push(item);
+    }
+
+    let json = serde_json::to_vec(&manifest).map_err(|e| WingsError::Io(e.into()))?;
+    let compressed = zstd::encode_all(&json[..], ZSTD_LEVEL).map_err(WingsError::Io)?;
+    stats.checksum = sha256_hex(&compressed);
+    files::write_file(&manifest_path(dir, backup_uuid), &compressed)?;
+    Ok(stats)
+}
+
+/// Load the manifest of a snapshot, checking it against `checksum`.
+pub fn open(dir: &Path, backup_uuid: &str, checksum: &str) -> Result<Manifest, WingsError> {
+    let compressed =
+        std::fs::read(manifest_path(dir, backup_uuid)).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => WingsError::BackupNotFound(backup_uuid.to_string()),
+            _ => WingsError::Io(e),
+        })?;
+    if sha256_hex(&compressed) != checksum {
+        return Err(WingsError::ArchiveRejected(format!(
+            "Manifest of backup {backup_uuid} does not match its checksum"
+        )));
+    }
+    let json = zstd::decode_all(&compressed[..]).map_err(WingsError::Io)?;
+    serde_json::from_slice(&json).map_err(|e| WingsError::Io(std::io::Error::other(e)))
+}
+
+/// Reads a file's content back from its chunks.
+struct ChunkReader<'a> {
+    dir: &'a Path,
+    chunks: std::slice::Iter<'a, String>,
+    current: std::io::Cursor<Vec<u8>>,
+}
+
+impl Read for ChunkReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let n = self.current.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            match self.chunks.next() {
+                Some(hash) => self.current = std::io::Cursor::new(read_chunk(self.dir, hash)?),
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+/// Render a snapshot as a tar stream.
+fn write_tar(dir: &Path, manifest: &Manifest, writer: impl Write) -> std::io::Result<()> {
+    let mut builder = tar::Builder::new(writer);
+    for entry in &manifest.entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(entry.mode);
+        header.set_mtime(entry.mtime.max(0) as u64);
+        header.set_size(0);
+        match entry.kind {
+            EntryKind::File => {
+                header.set_entry_type(tar::EntryType::Regular);
+                header.set_size(entry.size);
+                let reader = ChunkReader {
+                    dir,
+                    chunks: entry.chunks.iter(),
+                    current: Default::default(),
+                };
+                builder.append_data(&mut header, &entry.name, reader)?;
+            }
+            EntryKind::Dir => {
+                header.set_entry_type(tar::EntryType::Directory);
+                builder.append_data(&mut header, &entry.name, std::io::empty())?;
+            }
+            EntryKind::Symlink => {
+                header.set_entry_type(tar::EntryType::Symlink);
+                let target = entry.target.as_deref().unwrap_or_default();
+                builder.append_link(&mut header, &entry.name, target)?;
+            }
+        }
+    }
+    builder.into_inner()?.flush()
+}
+
+/// Write half of an in-memory pipe between two threads.
+struct PipeWriter(mpsc::SyncSender<std::io::Result<Vec<u8>>>);
+
+impl Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0
+            .send(Ok(buf.to_vec()))
+            .map_err(|_| std::io::ErrorKind::BrokenPipe)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Read half of the pipe; errors of the writing side are passed through.
+struct PipeReader {
+    rx: mpsc::Receiver<std::io::Result<Vec<u8>>>,
+    current: std::io::Cursor<Vec<u8>>,
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let n = self.current.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            match self.rx.recv() {
+                Ok(Ok(data)) => self.current = std::io::Cursor::new(data),
+                Ok(Err(e)) => return Err(e),
+                Err(_) => return Ok(0),
+            }
+        }
+    }
+}
+
+/// Extract a snapshot into `dest`. The snapshot is streamed as a tar through
+/// the regular archive extractor, so restores get the same path checks and
+/// roll back the same way when a chunk turns out to be missing or corrupt.
+pub fn extract(
+    dir: &Path,
+    manifest: &Manifest,
+    root: &Path,
+    dest: &Path,
+    size: u64,
+    progress: &JobProgress,
+    limits: &ExtractLimits,
+) -> Result<(), WingsError> {
+    let (tx, rx) = mpsc::sync_channel(PIPE_DEPTH);
+    std::thread::scope(|scope| {
+        scope.spawn(move || {
+            let writer = std::io::BufWriter::with_capacity(PIPE_BUFFER, PipeWriter(tx.clone()));
+            if let Err(e) = write_tar(dir, manifest, writer) {
+                let _ = tx.send(Err(e));
+            }
+        });
+        let reader = PipeReader {
+            rx,
+            current: Default::default(),
+        };
+        files::decompress_tar_stream(
+            root,
+            reader,
+            dest,
+            Compression::None,
+            size,
+            progress,
+            limits,
+        )
+    })
+}
+
+/// Check that every chunk of a snapshot is present and intact.
+pub fn verify(dir: &Path, backup_uuid: &str, checksum: &str) -> Result<VerifyReport, WingsError> {
+    let manifest = open(dir, backup_uuid, checksum)?;
+    let unique: HashSet<&String> = manifest.chunks().collect();
+    let mut report = VerifyReport::default();
+    for hash in unique {
+        report.checked_chunks += 1;
+        match read_chunk(dir, hash) {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                report.missing_chunks.push(hash.clone())
+            }
+            Err(_) => report.corrupt_chunks.push(hash.clone()),
+        }
+    }
+    report.missing_chunks.sort();
+    report.corrupt_chunks.sort();
+    report.valid = report.missing_chunks.is_empty() && report.corrupt_chunks.is_empty();
+    Ok(report)
+}
+
+/// Remove every chunk in `dir` not referenced by `manifests`, along with
+/// leftovers of interrupted writes. Returns the number and size of the
+/// removed chunks.
+pub fn collect_garbage(dir: &Path, manifests: &[Manifest]) -> Result<(u64, u64), WingsError> {
+    let referenced: HashSet<&str> = manifests
+        .iter()
+        .flat_map(|m| m.chunks())
+        .map(String::as_str)
+        .collect();
+    let (mut count, mut bytes) = (0, 0);
+    for entry in walkdir::WalkDir::new(dir.join(CHUNK_DIR))
+        .min_depth(2)
+        .max_depth(2)
+    {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) if e.io_error().map(|e| e.kind()) == Some(std::io::ErrorKind::NotFound) => break,
+            Err(e) => return Err(WingsError::Io(std::io::Error::other(e))),
+        };
+        if referenced.contains(entry.file_name().to_string_lossy().as_ref()) {
+            continue;
+        }
+        let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+        std::fs::remove_file(entry.path()).map_err(WingsError::Io)?;
+        count += 1;
+        bytes += size;
+    }
+    Ok((count, bytes))
+}
+
+/// Names the hour, day or week a snapshot falls into.
+type PeriodKey = fn(&DateTime<Utc>) -> String;
+
+/// How many snapshots a prune keeps: the newest snapshot of each of the last
+/// `hourly` hours, `daily` days and `weekly` ISO weeks that have one.
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+pub struct RetentionPolicy {
+    #[serde(default)]
+    pub hourly: u32,
+    #[serde(default)]
+    pub daily: u32,
+    #[serde(default)]
+    pub weekly: u32,
+}
+
+impl RetentionPolicy {
+    pub fn keeps_nothing(&self) -> bool {
+        self.hourly == 0 && self.daily == 0 && self.weekly == 0
+    }
+
+    /// Indices of the snapshots to keep, given their creation times newest
+    /// first.
+    pub fn keep(&self, created: &[DateTime<Utc>]) -> HashSet<usize> {
+        let mut kept = HashSet::new();
+        let rules: [(u32, PeriodKey); 3] = [
+            (self.hourly, |t| t.format("%Y-%m-%d %H").to_string()),
+            (self.daily, |t| t.format("%Y-%m-%d").to_string()),
+            (self.weekly, |t| {
+                let week = t.iso_week();
+                format!("{}-{}", week.year(), week.week())
+            }),
+        ];
+        for (count, period) in rules {
+            let mut last = None;
+            let mut buckets = 0;
+            for (i, time) in created.iter().enumerate() {
+                if buckets == count {
+                    break;
+                }
+                let bucket = period(time);
+                if last.as_ref() != Some(&bucket) {
+                    kept.insert(i);
+                    buckets += 1;
+                    last = Some(bucket);
+                }
+            }
+        }
+        kept
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_retention_keeps_newest_per_period() {
+        let at = |d, h, m| Utc.with_ymd_and_hms(2024, 5, d, h, m, 0).unwrap();
+        // Newest first: two in the same hour, then earlier hours and days
+        let created = [
+            at(15, 12, 30),
+            at(15, 12, 0),
+            at(15, 11, 0),
+            at(14, 23, 0),
+            at(13, 8, 0),
+            at(6, 8, 0),
+        ];
+
+        let policy = RetentionPolicy {
+            hourly: 2,
+            ..Default::default()
+        };
+        assert_eq!(policy.keep(&created), HashSet::from([0, 2]));
+
+        let policy = RetentionPolicy {
+            hourly: 1,
+            daily: 3,
+            weekly: 2,
+        };
+        // 2024-05-13 is a Monday, so the 13th and 14th share the week of the 15th
+        assert_eq!(policy.keep(&created), HashSet::from([0, 3, 4, 5]));
+    }
+}
@@ -1,30 +1,121 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 
+use dashmap::DashMap;
+use tokio::sync::{broadcast, mpsc, Mutex};
+
 use crate::config::Config;
 use crate::console::ConsoleBuffer;
 use crate::docker::{DockerManager, ServerConfig};
+use crate::grpc::proto::WingsEvent;
+use crate::grpc::EventSender;
+use crate::jobs::JobRegistry;
+use crate::s3::S3Client;
+use crate::schedules::Scheduler;
+use crate::transfer::Transfers;
+use crate::watcher::FileWatchers;
+
+/// Capacity of the in-process event bus used by WebSocket subscribers
+const EVENT_BUS_CAPACITY: usize = 256;
+
+/// Slots of the Panel's event buffer only events that stay relevant may
+/// take, so progress piling up while the Panel is away can't crowd out
+/// completions.
+const RESERVED_EVENT_SLOTS: usize = 250;
+
+/// Whether an event only reports how something is going right now and is
+/// superseded by a later one.
+fn is_transient(event: &WingsEvent) -> bool {
+    use crate::grpc::proto::{wings_event::Event, FileOperationStatus, ImagePullStatus};
+    match &event.event {
+        Some(Event::ResourceStats(_))
+        | Some(Event::ConsoleOutput(_))
+        | Some(Event::FileChanged(_))
+        | Some(Event::TransferProgress(_)) => true,
+        Some(Event::FileOperationProgress(p)) => p.status() == FileOperationStatus::FileOpRunning,
+        Some(Event::ImagePullProgress(p)) => p.status() == ImagePullStatus::ImagePullRunning,
+        _ => false,
+    }
+}
+
+/// Per-server mutex to deduplicate concurrent power commands
+pub type ServerLocks = Arc<DashMap<String, Arc<Mutex<()>>>>;
 
 pub struct AppState {
     pub config: Config,
     pub docker: DockerManager,
     pub console_buffers: Arc<tokio::sync::RwLock<HashMap<String, ConsoleBuffer>>>,
+    pub jobs: JobRegistry,
+    pub watchers: FileWatchers,
+    /// Client for the configured backup bucket, if any
+    pub s3: Option<S3Client>,
+    /// Incoming transfers accepted from the Panel
+    pub transfers: Transfers,
+    /// Cron schedules synced from the Panel
+    pub scheduler: Scheduler,
+    /// Held while a server is created, deleted, powered, restored or
+    /// transferred, from gRPC or a schedule
+    pub server_locks: ServerLocks,
+    /// Held from a port check until the config claiming those ports is stored
+    pub port_claims: Mutex<()>,
+    /// Events for the Panel's gRPC stream
+    event_tx: EventSender,
+    /// Same events fanned out to WebSocket clients
+    event_bus: broadcast::Sender<WingsEvent>,
     /// Persistent server config registry — survives Wings restart via disk serialization
     server_configs: Arc<tokio::sync::RwLock<HashMap<String, ServerConfig>>>,
 }
 
 impl AppState {
-    pub fn new(config: Config, docker: DockerManager) -> Self {
+    pub fn new(config: Config, docker: DockerManager, event_tx: EventSender) -> Self {
         // Load persisted server configs from disk
         let configs = Self::load_configs(&config.storage.data_dir);
+        let (event_bus, _) = broadcast::channel(EVENT_BUS_CAPACITY);
+        let s3 = config.storage.s3.as_ref().and_then(|s3| {
+            S3Client::new(s3)
+                .map_err(|e| tracing::error!(error = %e, "S3 backups disabled"))
+                .ok()
+        });
         Self {
             config,
             docker,
             console_buffers: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
+            jobs: JobRegistry::default(),
+            watchers: FileWatchers::default(),
+            s3,
+            transfers: Transfers::default(),
+            scheduler: Scheduler::default(),
+            server_locks: Arc::new(DashMap::new()),
+            port_claims: Mutex::new(()),
+            event_tx,
+            event_bus,
             server_configs: Arc::new(tokio::sync::RwLock::new(configs)),
         }
     }
 
+    pub fn server_lock(&self, uuid: &str) -> Arc<Mutex<()>> {
+        self.server_locks
+            .entry(uuid.to_string())
+            .or_insert_with(|| Arc::new(Mutex::new(())))
+            .clone()
+    }
+
+    /// Publish an event to the Panel stream and to WebSocket subscribers.
+    /// Transient events are left out of a nearly full Panel buffer.
+    pub fn emit_event(&self, event: WingsEvent) {
+        let _ = self.event_bus.send(event.clone());
+        if is_transient(&event) && self.event_tx.capacity() <= RESERVED_EVENT_SLOTS {
+            return;
+        }
+        if let Err(mpsc::error::TrySendError::Full(_)) = self.event_tx.try_send(event) {
+            tracing::warn!("Event buffer full, dropping event");
+        }
+    }
+
+    pub fn subscribe_events(&self) -> broadcast::Receiver<WingsEvent> {
+        self.event_bus.subscribe()
+    }
+
     pub async fn get_or_create_buffer(&self, uuid: &str) -> ConsoleBuffer {
         let mut buffers = self.console_buffers.write().await;
         buffers
@@ -45,6 +136,25 @@ impl AppState {
         configs.get(uuid).cloned()
     }
 
+    /// Bytes the server may still write before reaching its disk limit, or
+    /// `None` if it has no limit.
+    pub async fn remaining_quota(&self, uuid: &str) -> Option<u64> {
+        let cfg = self.get_server_config(uuid).await?;
+        if cfg.disk_limit == 0 {
+            return None;
+        }
+        let root = std::path::Path::new(&self.config.storage.data_dir).join(uuid);
+        let used = tokio::task::spawn_blocking(move || crate::files::disk_usage(&root))
+            .await
+            .unwrap_or(0);
+        Some((cfg.disk_limit * 1024 * 1024).saturating_sub(used))
+    }
+
+    pub async fn list_server_configs(&self) -> Vec<ServerConfig> {
+        let configs = self.server_configs.read().await;
+        configs.values().cloned().collect()
+    }
+
     pub async fn remove_server_config(&self, uuid: &str) {
         let mut configs = self.server_configs.write().await;
         configs.remove(uuid);
@@ -85,3 +195,30 @@ impl AppState {
         configs
     }
 }
+
+/// Node rooted at `dir` for tests, with its Panel event stream. Docker
+/// points at a socket nothing listens on.
+#[cfg(test)]
+pub fn test_node(dir: &std::path::Path) -> (Arc<AppState>, crate::grpc::EventReceiver) {
+    let config: Config = toml::from_str(&format!(
+        r#"
+        [panel]
+        url = "http://127.0.0.1:1"
+        token = "panel-token"
+        [api]
+        [docker]
+        socket = "{0}/docker.sock"
+        [storage]
+        data_dir = "{0}/data"
+        backup_dir = "{0}/backups"
+        [logging]
+        "#,
+        dir.display()
+    ))
+    .unwrap();
+    // Bollard wants the socket to exist
+    std::fs::write(dir.join("docker.sock"), "").unwrap();
+    let docker = DockerManager::new(&config.docker).unwrap();
+    let (event_tx, event_rx) = crate::grpc::create_event_channel();
+    (Arc::new(AppState::new(config, docker, event_tx)), event_rx)
+}
@@ -0,0 +1,786 @@
+//! Moving a server between nodes.
+//!
+//! The Panel first registers the transfer with the destination under a
+//! one-time token, then tells the source to push. The source stops the
+//! server and streams a tar.zst of its root to the destination, which hashes
+//! what it receives. The source then sends its own checksum along with the
+//! server's config; the destination unpacks the archive and recreates the
+//! container, and the source deletes its copy once that succeeded.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::http::header::AUTHORIZATION;
+use axum::http::HeaderMap;
+use bytes::Bytes;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+
+use crate::backups;
+use crate::docker::ServerConfig;
+use crate::error::WingsError;
+use crate::files::{self, ArchiveEntry};
+use crate::grpc::proto::{
+    wings_event, TransferCompleted, TransferProgress, TransferRole, WingsEvent,
+};
+use crate::images;
+use crate::jobs::{self, JobProgress};
+use crate::state::AppState;
+
+/// How long an accepted transfer waits for the source. Receiving the
+/// archive restarts the clock for the completing request.
+const TOKEN_TTL: Duration = Duration::from_secs(60 * 60);
+/// Shorter tokens are refused so they can't be guessed.
+const MIN_TOKEN_LEN: usize = 32;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+/// Directory under the backup dir holding archives being received.
+const INCOMING_DIR: &str = ".transfers";
+/// Buffers in flight between the archive writer and the upload.
+const PIPE_DEPTH: usize = 8;
+const PIPE_BUFFER: usize = 256 * 1024;
+
+struct Incoming {
+    token_hash: [u8; 32],
+    expires_at: Instant,
+    /// Checksum and size of the archive, once received
+    received: Option<(String, u64)>,
+}
+
+/// Transfers this node has agreed to receive.
+#[derive(Default)]
+pub struct Transfers {
+    incoming: Mutex<HashMap<String, Incoming>>,
+}
+
+fn token_hash(token: &str) -> [u8; 32] {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+impl Transfers {
+    fn accept(&self, uuid: &str, token: &str) {
+        self.incoming.lock().unwrap().insert(
+            uuid.to_string(),
+            Incoming {
+                token_hash: token_hash(token),
+                expires_at: Instant::now() + TOKEN_TTL,
+                received: None,
+            },
+        );
+    }
+
+    /// Check `token` against the accepted transfer of `uuid`. Comparing
+    /// digests keeps the comparison from leaking the token.
+    fn authorize(&self, uuid: &str, token: &str) -> Result<(), WingsError> {
+        let mut incoming = self.incoming.lock().unwrap();
+        match incoming.get(uuid) {
+            Some(t) if Instant::now() >= t.expires_at => {
+                incoming.remove(uuid);
+                Err(WingsError::AuthFailed)
+            }
+            Some(t) if t.token_hash == token_hash(token) => Ok(()),
+            _ => Err(WingsError::AuthFailed),
+        }
+    }
+
+    fn set_received(&self, uuid: &str, checksum: String, size: u64) {
+        if let Some(t) = self.incoming.lock().unwrap().get_mut(uuid) {
+            t.expires_at = Instant::now() + TOKEN_TTL;
+            t.received = Some((checksum, size));
+        }
+    }
+
+    fn received(&self, uuid: &str) -> Option<(String, u64)> {
+        self.incoming
+            .lock()
+            .unwrap()
+            .get(uuid)
+            .and_then(|t| t.received.clone())
+    }
+
+    fn finish(&self, uuid: &str) {
+        self.incoming.lock().unwrap().remove(uuid);
+    }
+}
+
+/// Token sent by the source in the `Authorization` header.
+pub fn bearer_token(headers: &HeaderMap) -> Result<&str, WingsError> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .ok_or(WingsError::AuthFailed)
+}
+
+fn emit_progress(state: &AppState, uuid: &str, role: TransferRole, processed: u64, total: u64) {
+    state.emit_event(WingsEvent {
+        event: Some(wings_event::Event::TransferProgress(TransferProgress {
+            uuid: uuid.to_string(),
+            role: role.into(),
+            bytes_processed: processed,
+            bytes_total: total,
+            timestamp_ms: chrono::Utc::now().timestamp_millis(),
+        })),
+    });
+}
+
+fn emit_completed(
+    state: &AppState,
+    uuid: &str,
+    role: TransferRole,
+    result: &Result<(), WingsError>,
+) {
+    state.emit_event(WingsEvent {
+        event: Some(wings_event::Event::TransferCompleted(TransferCompleted {
+            uuid: uuid.to_string(),
+            role: role.into(),
+            successful: result.is_ok(),
+            error_message: result
+                .as_ref()
+                .err()
+                .map(|e| e.to_string())
+                .unwrap_or_default(),
+            timestamp_ms: chrono::Utc::now().timestamp_millis(),
+        })),
+    });
+}
+
+fn server_root(state: &AppState, uuid: &str) -> PathBuf {
+    Path::new(&state.config.storage.data_dir).join(uuid)
+}
+
+fn incoming_path(state: &AppState, uuid: &str) -> PathBuf {
+    Path::new(&state.config.storage.backup_dir)
+        .join(INCOMING_DIR)
+        .join(format!("{uuid}.tar.zst"))
+}
+
+// ---------------------------------------------------------------------------
+// Destination
+// ---------------------------------------------------------------------------
+
+/// Agree to receive server `uuid` from a source presenting `token`.
+pub async fn accept(state: &AppState, uuid: &str, token: &str) -> Result<(), WingsError> {
+    // The uuid becomes a directory name
+    uuid::Uuid::parse_str(uuid)
+        .map_err(|_| WingsError::InvalidRequest(format!("Invalid server uuid: {uuid}")))?;
+    if token.len() < MIN_TOKEN_LEN {
+        return Err(WingsError::InvalidRequest(format!(
+            "Transfer token must be at least {MIN_TOKEN_LEN} characters"
+        )));
+    }
+    if state.get_server_config(uuid).await.is_some() || server_root(state, uuid).exists() {
+        return Err(WingsError::InvalidRequest(format!(
+            "Server {uuid} already exists on this node"
+        )));
+    }
+    state.transfers.accept(uuid, token);
+    tracing::info!(uuid = %uuid, "Accepted incoming transfer");
+    Ok(())
+}
+
+/// Checksum and size of a received archive.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveReceipt {
+    pub checksum: String,
+    pub size: u64,
+}
+
+/// Store the archive streamed by the source, hashing it on the way.
+pub async fn receive(
+    state: &AppState,
+    uuid: &str,
+    token: &str,
+    body: axum::body::Body,
+) -> Result<ArchiveReceipt, WingsError> {
+    state.transfers.authorize(uuid, token)?;
+    let path = incoming_path(state, uuid);
+    tokio::fs::create_dir_all(path.parent().unwrap_or(Path::new("/")))
+        .await
+        .map_err(WingsError::Io)?;
+
+    let result = write_incoming(state, uuid, &path, body).await;
+    match &result {
+        Ok(receipt) => {
+            state
+                .transfers
+                .set_received(uuid, receipt.checksum.clone(), receipt.size);
+            tracing::info!(uuid = %uuid, size = receipt.size, "Transfer archive received");
+        }
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&path).await;
+            tracing::warn!(uuid = %uuid, error = %e, "Receiving transfer archive failed");
+        }
+    }
+    result
+}
+
+async fn write_incoming(
+    state: &AppState,
+    uuid: &str,
+    path: &Path,
+    body: axum::body::Body,
+) -> Result<ArchiveReceipt, WingsError> {
+    let mut file = tokio::fs::File::create(path)
+        .await
+        .map_err(WingsError::Io)?;
+    let mut hasher = Sha256::new();
+    let mut size = 0u64;
+    let mut last_progress = Instant::now();
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| WingsError::Transfer(e.to_string()))?;
+        hasher.update(&chunk);
+        file.write_all(&chunk).await.map_err(WingsError::Io)?;
+        size += chunk.len() as u64;
+        if last_progress.elapsed() >= PROGRESS_INTERVAL {
+            emit_progress(state, uuid, TransferRole::Destination, size, 0);
+            last_progress = Instant::now();
+        }
+    }
+    file.sync_all().await.map_err(WingsError::Io)?;
+    Ok(ArchiveReceipt {
+        checksum: hex::encode(hasher.finalize()),
+        size,
+    })
+}
+
+/// Sent by the source once the archive is uploaded.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompleteRequest {
+    pub checksum: String,
+    pub size: u64,
+    pub server: ServerConfig,
+}
+
+/// Verify the received archive against the source's checksum, unpack it and
+/// recreate the server.
+pub async fn complete(
+    state: &Arc<AppState>,
+    uuid: &str,
+    token: &str,
+    request: CompleteRequest,
+) -> Result<(), WingsError> {
+    state.transfers.authorize(uuid, token)?;
+    let (checksum, size) = state
+        .transfers
+        .received(uuid)
+        .ok_or_else(|| WingsError::InvalidRequest("No archive has been received".into()))?;
+
+    let result = if checksum != request.checksum || size != request.size {
+        Err(WingsError::ArchiveRejected(
+            "Received archive does not match the source's checksum".into(),
+        ))
+    } else if request.server.uuid != uuid {
+        Err(WingsError::InvalidRequest(
+            "Server config is for a different server".into(),
+        ))
+    } else {
+        install(state, uuid, request.server).await
+    };
+
+    let _ = tokio::fs::remove_file(incoming_path(state, uuid)).await;
+    state.transfers.finish(uuid);
+    match &result {
+        Ok(()) => tracing::info!(uuid = %uuid, "Transfer received"),
+        Err(e) => tracing::error!(uuid = %uuid, error = %e, "Transfer failed"),
+    }
+    emit_completed(state, uuid, TransferRole::Destination, &result);
+    result
+}
+
+async fn install(state: &AppState, uuid: &str, mut config: ServerConfig) -> Result<(), WingsError> {
+    let root = server_root(state, uuid);
+    let archive = incoming_path(state, uuid);
+    let extract_root = root.clone();
+    let unpacked = tokio::task::spawn_blocking(move || {
+        std::fs::create_dir_all(&extract_root).map_err(WingsError::Io)?;
+        let dest = extract_root.canonicalize().map_err(WingsError::Io)?;
+        files::decompress(
+            &extract_root,
+            &archive,
+            &dest,
+            &JobProgress::default(),
+            &backups::restore_limits(),
+        )
+    })
+    .await
+    .map_err(|e| WingsError::Io(std::io::Error::other(e)))
+    .and_then(|r| r);
+
+    let result = async {
+        unpacked?;
+        files::chown_recursive(&root, state.docker.container_user())?;
+        config.volume_path = root.to_string_lossy().to_string();
+        state.store_server_config(&config).await;
+        images::ensure_server_image(state, &mut config).await?;
+        state.docker.create_container(&config).await
+    }
+    .await;
+
+    if result.is_err() {
+        state.remove_server_config(uuid).await;
+        let _ = tokio::fs::remove_dir_all(&root).await;
+    }
+    result.map(|_| ())
+}
+
+// ---------------------------------------------------------------------------
+// Source
+// ---------------------------------------------------------------------------
+
+/// Everything in a server root except Wings' own files; unlike backups,
+/// `.nexusignore` does not apply.
+fn collect_entries(root: &Path) -> Result<Vec<ArchiveEntry>, WingsError> {
+    let mut entries = Vec::new();
+    let walker = walkdir::WalkDir::new(root).min_depth(1).follow_links(false);
+    for entry in walker
+        .into_iter()
+        .filter_entry(|e| !files::is_wings_file(&e.file_name().to_string_lossy()))
+    {
+        let entry = entry.map_err(|e| WingsError::Io(std::io::Error::other(e)))?;
+        let name = entry
+            .path()
+            .strip_prefix(root)
+            .unwrap_or(entry.path())
+            .to_string_lossy()
+            .to_string();
+        let metadata = entry.path().symlink_metadata().map_err(WingsError::Io)?;
+        entries.push(ArchiveEntry {
+            path: entry.into_path(),
+            name,
+            metadata,
+        });
+    }
+    Ok(entries)
+}
+
+/// Feeds the archive into the upload body and reports progress.
+struct UploadWriter<'a> {
+    tx: mpsc::Sender<std::io::Result<Bytes>>,
+    state: &'a AppState,
+    uuid: &'a str,
+    progress: &'a JobProgress,
+    last_progress: Instant,
+}
+
+impl Write for UploadWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.tx
+            .blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::BrokenPipe))?;
+        if self.last_progress.elapsed() >= PROGRESS_INTERVAL {
+            let (processed, total) = (self.progress.processed(), self.progress.total());
+            emit_progress(
+                self.state,
+                self.uuid,
+                TransferRole::Source,
+                processed,
+                total,
+            );
+            self.last_progress = Instant::now();
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+async fn remote_error(response: reqwest::Response) -> WingsError {
+    let status = response.status();
+    let message = response
+        .json::<serde_json::Value>()
+        .await
+        .ok()
+        .and_then(|body| body["error"].as_str().map(str::to_string))
+        .unwrap_or_else(|| status.to_string());
+    WingsError::Transfer(format!("destination answered {status}: {message}"))
+}
+
+/// Stream the server root to the destination at `base_url` and have it
+/// recreate the server from `config`. Runs on a blocking thread.
+pub fn send(
+    state: &AppState,
+    root: &Path,
+    base_url: &str,
+    token: &str,
+    config: &ServerConfig,
+    progress: &JobProgress,
+) -> Result<(), WingsError> {
+    let runtime = tokio::runtime::Handle::current();
+    let entries = collect_entries(root)?;
+    progress.set_total(
+        entries
+            .iter()
+            .filter(|e| e.metadata.is_file())
+            .map(|e| e.metadata.len())
+            .sum(),
+    );
+
+    let http = reqwest::Client::new();
+    let base = format!(
+        "{}/api/transfers/{}",
+        base_url.trim_end_matches('/'),
+        config.uuid
+    );
+    let (tx, rx) = mpsc::channel(PIPE_DEPTH);
+    let upload = runtime.spawn(
+        http.put(format!("{base}/archive"))
+            .bearer_auth(token)
+            .body(reqwest::Body::wrap_stream(
+                tokio_stream::wrappers::ReceiverStream::new(rx),
+            ))
+            .send(),
+    );
+
+    let writer = UploadWriter {
+        tx: tx.clone(),
+        state,
+        uuid: &config.uuid,
+        progress,
+        last_progress: Instant::now(),
+    };
+    let writer = std::io::BufWriter::with_capacity(PIPE_BUFFER, writer);
+    let written =
+        backups::write_archive(writer, &entries, progress).and_then(|(checksum, size, writer)| {
+            writer.into_inner().map_err(|e| e.into_error())?;
+            Ok((checksum, size))
+        });
+    let (checksum, size) = match written {
+        Ok(written) => written,
+        Err(e) => {
+            // Fail the request body so the destination doesn't take a
+            // truncated archive for a complete one
+            let _ = tx.blocking_send(Err(std::io::Error::other("transfer aborted")));
+            drop(tx);
+            // The destination hanging up early usually comes with a reason
+            if let Ok(Ok(response)) = runtime.block_on(upload) {
+                if !response.status().is_success() {
+                    return Err(runtime.block_on(remote_error(response)));
+                }
+            }
+            return Err(if progress.is_cancelled() {
+                WingsError::Cancelled
+            } else {
+                WingsError::Io(e)
+            });
+        }
+    };
+    drop(tx);
+
+    let response = runtime
+        .block_on(upload)
+        .map_err(|e| WingsError::Io(std::io::Error::other(e)))?
+        .map_err(|e| WingsError::Transfer(e.to_string()))?;
+    if !response.status().is_success() {
+        return Err(runtime.block_on(remote_error(response)));
+    }
+    let receipt: ArchiveReceipt = runtime
+        .block_on(response.json())
+        .map_err(|e| WingsError::Transfer(e.to_string()))?;
+    if receipt.checksum != checksum || receipt.size != size {
+        return Err(WingsError::Transfer(
+            "destination received a corrupted archive".into(),
+        ));
+    }
+
+    let request = CompleteRequest {
+        checksum,
+        size,
+        server: config.clone(),
+    };
+    let response = runtime
+        .block_on(
+            http.post(format!("{base}/complete"))
+                .bearer_auth(token)
+                .json(&request)
+                .send(),
+        )
+        .map_err(|e| WingsError::Transfer(e.to_string()))?;
+    if !response.status().is_success() {
+        return Err(runtime.block_on(remote_error(response)));
+    }
+    Ok(())
+}
+
+/// Where to send a server.
+#[derive(Debug, Deserialize)]
+pub struct TransferTarget {
+    /// Base URL of the destination's HTTP API
+    pub url: String,
+    pub token: String,
+}
+
+/// Start transferring a server to another node in the background.
+pub async fn start(
+    state: &Arc<AppState>,
+    uuid: &str,
+    target: TransferTarget,
+) -> Result<String, WingsError> {
+    let config = state
+        .get_server_config(uuid)
+        .await
+        .ok_or_else(|| WingsError::ServerNotFound(uuid.to_string()))?;
+    let url = reqwest::Url::parse(&target.url)
+        .map_err(|e| WingsError::InvalidRequest(format!("Invalid destination url: {e}")))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(WingsError::InvalidRequest(
+            "Destination url must be http or https".into(),
+        ));
+    }
+    let root = server_root(state, uuid);
+    if !root.is_dir() {
+        return Err(WingsError::ServerNotFound(uuid.to_string()));
+    }
+
+    let job_state = state.clone();
+    let server_uuid = uuid.to_string();
+    let work = move |progress: &JobProgress| {
+        let runtime = tokio::runtime::Handle::current();
+        let docker = &job_state.docker;
+        let uuid = &server_uuid;
+        // Keep power actions from starting the server mid-transfer
+        let lock = job_state.server_lock(uuid);
+        let _guard = lock.blocking_lock();
+        let was_running = runtime
+            .block_on(docker.get_container_status(uuid))
+            .is_ok_and(|status| status == "running");
+
+        let result = (|| {
+            if was_running {
+                runtime.block_on(docker.stop_server(uuid, 30))?;
+            }
+            send(
+                &job_state,
+                &root,
+                &target.url,
+                &target.token,
+                &config,
+                progress,
+            )
+        })();
+
+        match &result {
+            Ok(()) => {
+                tracing::info!(uuid = %uuid, "Transfer completed, removing local copy");
+                runtime.block_on(async {
+                    if let Err(e) = docker.delete_server(uuid, true).await {
+                        tracing::warn!(uuid = %uuid, error = %e, "Failed to remove container");
+                    }
+                    job_state.console_buffers.write().await.remove(uuid);
+                    job_state.remove_server_config(uuid).await;
+                    crate::schedules::remove(&job_state, uuid);
+                });
+                if let Err(e) = std::fs::remove_dir_all(&root) {
+                    tracing::warn!(uuid = %uuid, error = %e, "Failed to remove server files");
+                }
+            }
+            Err(e) => {
+                tracing::error!(uuid = %uuid, error = %e, "Transfer failed");
+                if was_running {
+                    if let Err(e) = runtime.block_on(docker.start_server(uuid)) {
+                        tracing::error!(uuid = %uuid, error = %e, "Failed to restart server");
+                    }
+                }
+            }
+        }
+        emit_completed(&job_state, uuid, TransferRole::Source, &result);
+        result
+    };
+    let job_id = jobs::spawn_exclusive(
+        state.clone(),
+        uuid,
+        "transfer",
+        jobs::SERVER_DATA_JOBS,
+        work,
+    )?;
+    Ok(job_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::test_node;
+
+    const UUID: &str = "2f0b4c7e-9a51-4c8e-b5d2-7e3f1a6c9d40";
+
+    #[test]
+    fn test_transfer_tokens() {
+        let transfers = Transfers::default();
+        let token = "a".repeat(MIN_TOKEN_LEN);
+        assert!(transfers.authorize(UUID, &token).is_err());
+
+        transfers.accept(UUID, &token);
+        assert!(transfers.authorize(UUID, &token).is_ok());
+        assert!(transfers.authorize(UUID, "wrong").is_err());
+        assert!(transfers.received(UUID).is_none());
+
+        transfers.set_received(UUID, "abc".into(), 3);
+        assert_eq!(transfers.received(UUID), Some(("abc".into(), 3)));
+
+        // Tokens are single use
+        transfers.finish(UUID);
+        assert!(transfers.authorize(UUID, &token).is_err());
+
+        transfers.accept(UUID, &token);
+        transfers
+            .incoming
+            .lock()
+            .unwrap()
+            .get_mut(UUID)
+            .unwrap()
+            .expires_at = Instant::now();
+        assert!(transfers.authorize(UUID, &token).is_err());
+    }
+
+    #[test]
+    fn test_transfer_includes_ignored_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("logs")).unwrap();
+        std::fs::write(dir.path().join("logs/latest.log"), "log").unwrap();
+        std::fs::write(dir.path().join(backups::IGNORE_FILE), "logs/\n").unwrap();
+        std::fs::write(dir.path().join(".nexus-config.json"), "{}").unwrap();
+
+        let mut names: Vec<_> = collect_entries(dir.path())
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name)
+            .collect();
+        names.sort();
+        assert_eq!(names, [backups::IGNORE_FILE, "logs", "logs/latest.log"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_transfer_between_nodes() {
+        let (source_dir, destination_dir) = (
+            tempfile::TempDir::new().unwrap(),
+            tempfile::TempDir::new().unwrap(),
+        );
+        let source = test_node(source_dir.path()).0;
+        let destination = test_node(destination_dir.path()).0;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let app = crate::server::build_router(destination.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let root = source_dir.path().join("data").join(UUID);
+        std::fs::create_dir_all(root.join("world")).unwrap();
+        std::fs::write(root.join("world/level.dat"), vec![7u8; 300_000]).unwrap();
+        let config = ServerConfig {
+            uuid: UUID.to_string(),
+            docker_image: "alpine".into(),
+            startup_command: String::new(),
+            environment: HashMap::new(),
+            memory_limit: 512,
+            cpu_limit: 100,
+            disk_limit: 1024,
+            swap_limit: 0,
+            memory_overhead: 0,
+            io_weight: 0,
+            pids_limit: 0,
+            cpuset: String::new(),
+            oom_kill_disable: false,
+            network_isolated: false,
+            egress_limit: 0,
+            ingress_limit: 0,
+            port_mappings: Vec::new(),
+            volume_path: root.to_string_lossy().to_string(),
+            pull_policy: None,
+            image_digest: None,
+            rebuild_pending: false,
+        };
+        let token = "t".repeat(MIN_TOKEN_LEN);
+        accept(&destination, UUID, &token).await.unwrap();
+        let mut events = destination.subscribe_events();
+
+        let send_with = |token: String| {
+            let (source, root, url, config) =
+                (source.clone(), root.clone(), url.clone(), config.clone());
+            tokio::task::spawn_blocking(move || {
+                send(
+                    &source,
+                    &root,
+                    &url,
+                    &token,
+                    &config,
+                    &JobProgress::default(),
+                )
+            })
+        };
+        let err = send_with("x".repeat(MIN_TOKEN_LEN))
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert!(err.to_string().contains("401"), "{err}");
+
+        // The archive checks out and is unpacked; recreating the container
+        // then fails without Docker, which rolls the destination back
+        assert!(send_with(token.clone()).await.unwrap().is_err());
+        let completed = loop {
+            if let Some(wings_event::Event::TransferCompleted(c)) =
+                events.recv().await.unwrap().event
+            {
+                break c;
+            }
+        };
+        assert_eq!(completed.role, TransferRole::Destination as i32);
+        assert!(
+            completed.error_message.starts_with("Docker error"),
+            "{}",
+            completed.error_message
+        );
+        assert!(!destination_dir.path().join("data").join(UUID).exists());
+        assert!(!incoming_path(&destination, UUID).exists());
+        // The source keeps its copy
+        assert!(root.join("world/level.dat").exists());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_transfer_refused_during_backup() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let state = test_node(dir.path()).0;
+        std::fs::create_dir_all(dir.path().join("data").join(UUID)).unwrap();
+        let config: ServerConfig = serde_json::from_value(serde_json::json!({
+            "uuid": UUID,
+            "docker_image": "alpine",
+            "startup_command": "",
+            "environment": {},
+            "memory_limit": 512,
+            "cpu_limit": 100,
+            "disk_limit": 1024,
+            "port_mappings": [],
+            "volume_path": "",
+        }))
+        .unwrap();
+        state.store_server_config(&config).await;
+
+        let (release, released) = std::sync::mpsc::channel::<()>();
+        jobs::spawn_exclusive(
+            state.clone(),
+            UUID,
+            "backup",
+            jobs::SERVER_DATA_JOBS,
+            move |_| {
+                let _ = released.recv();
+                Ok(())
+            },
+        )
+        .unwrap();
+        let target = || TransferTarget {
+            url: "http://127.0.0.1:1".into(),
+            token: "t".repeat(MIN_TOKEN_LEN),
+        };
+        assert!(matches!(
+            start(&state, UUID, target()).await,
+            Err(WingsError::InvalidRequest(_))
+        ));
+        release.send(()).unwrap();
+    }
+}
@@ -0,0 +1,327 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use notify::event::{ModifyKind, RenameMode};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+
+use crate::error::WingsError;
+use crate::files;
+use crate::grpc::proto::{wings_event, FileChangeKind, FileChanged, WingsEvent};
+use crate::state::AppState;
+
+/// Quiet period after which collected changes are flushed.
+const DEBOUNCE_QUIET: Duration = Duration::from_millis(250);
+/// Upper bound on how long changes are held back while events keep arriving.
+const DEBOUNCE_MAX: Duration = Duration::from_secs(1);
+/// More changes than this in one window collapse into a single rescan event.
+const MAX_BATCH: usize = 256;
+
+/// A single filesystem change, relative to the server root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub kind: FileChangeKind,
+    pub path: String,
+    pub new_path: String,
+}
+
+/// Changes collected during one debounce window, merged per path.
+#[derive(Debug, Default)]
+struct Batch {
+    changes: Vec<Change>,
+    index: HashMap<String, usize>,
+    rescan: bool,
+}
+
+impl Batch {
+    fn push(&mut self, change: Change) {
+        if self.rescan {
+            return;
+        }
+        if let Some(&i) = self.index.get(&change.path) {
+            let existing = &mut self.changes[i];
+            // A file created and then written to is still just "created"
+            if !(existing.kind == FileChangeKind::FileChangeCreated
+                && change.kind == FileChangeKind::FileChangeModified)
+            {
+                *existing = change;
+            }
+            return;
+        }
+        if self.changes.len() >= MAX_BATCH {
+            self.rescan = true;
+            self.changes.clear();
+            self.index.clear();
+            return;
+        }
+        self.index.insert(change.path.clone(), self.changes.len());
+        self.changes.push(change);
+    }
+
+    fn take(&mut self) -> Vec<Change> {
+        let batch = std::mem::take(self);
+        if batch.rescan {
+            vec![Change {
+                kind: FileChangeKind::FileChangeRescan,
+                path: "/".to_string(),
+                new_path: String::new(),
+            }]
+        } else {
+            batch.changes
+        }
+    }
+}
+
+/// Path of `path` relative to `root` with a leading slash, or `None` for
+/// paths outside the root and Wings' own bookkeeping files.
+fn relative_path(root: &Path, path: &Path) -> Option<String> {
+    let rel = path.strip_prefix(root).ok()?;
+    if files::is_wings_file(&path.file_name()?.to_string_lossy()) {
+        return None;
+    }
+    Some(format!("/{}", rel.to_string_lossy()))
+}
+
+/// Translate a raw notify event into the changes clients care about.
+fn changes_from_event(root: &Path, event: &notify::Event) -> Vec<Change> {
+    if event.need_rescan() {
+        return vec![Change {
+            kind: FileChangeKind::FileChangeRescan,
+            path: "/".to_string(),
+            new_path: String::new(),
+        }];
+    }
+    let kind = match event.kind {
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+            FileChangeKind::FileChangeCreated
+        }
+        EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+            FileChangeKind::FileChangeDeleted
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+            let (Some(from), Some(to)) = (event.paths.first(), event.paths.get(1)) else {
+                return Vec::new();
+            };
+            return match (relative_path(root, from), relative_path(root, to)) {
+                (Some(path), Some(new_path)) => vec![Change {
+                    kind: FileChangeKind::FileChangeRenamed,
+                    path,
+                    new_path,
+                }],
+                (Some(path), None) => vec![Change {
+                    kind: FileChangeKind::FileChangeDeleted,
+                    path,
+                    new_path: String::new(),
+                }],
+                // Atomic saves rename a temp file over the target
+                (None, Some(path)) => vec![Change {
+                    kind: FileChangeKind::FileChangeModified,
+                    path,
+                    new_path: String::new(),
+                }],
+                (None, None) => Vec::new(),
+            };
+        }
+        EventKind::Modify(_) => FileChangeKind::FileChangeModified,
+        _ => return Vec::new(),
+    };
+    event
+        .paths
+        .iter()
+        .filter_map(|p| relative_path(root, p))
+        .map(|path| Change {
+            kind,
+            path,
+            new_path: String::new(),
+        })
+        .collect()
+}
+
+struct Watch {
+    subscribers: usize,
+    _watcher: RecommendedWatcher,
+}
+
+/// Filesystem watches on server roots, reference counted by subscriber.
+#[derive(Default)]
+pub struct FileWatchers {
+    watches: Mutex<HashMap<String, Watch>>,
+}
+
+/// Keeps a server's root watched until dropped.
+pub struct WatchGuard {
+    state: Arc<AppState>,
+    uuid: String,
+}
+
+impl Drop for WatchGuard {
+    fn drop(&mut self) {
+        let mut watches = self.state.watchers.watches.lock().unwrap();
+        if let Some(watch) = watches.get_mut(&self.uuid) {
+            watch.subscribers -= 1;
+            if watch.subscribers == 0 {
+                // Dropping the watcher closes the channel and ends the debounce task
+                watches.remove(&self.uuid);
+                tracing::debug!(uuid = %self.uuid, "Stopped watching server files");
+            }
+        }
+    }
+}
+
+/// Start watching the root of `uuid` (or join an existing watch) and emit
+/// debounced [`FileChanged`] events until the returned guard is dropped.
+pub fn watch(state: &Arc<AppState>, uuid: &str) -> Result<WatchGuard, WingsError> {
+    let mut watches = state.watchers.watches.lock().unwrap();
+    if let Some(watch) = watches.get_mut(uuid) {
+        watch.subscribers += 1;
+    } else {
+        let root = Path::new(&state.config.storage.data_dir)
+            .join(uuid)
+            .canonicalize()
+            .map_err(|_| WingsError::ServerNotFound(uuid.to_string()))?;
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |res| {
+            let _ = tx.send(res);
+        })
+        .map_err(|e| WingsError::Io(std::io::Error::other(e)))?;
+        watcher
+            .watch(&root, RecursiveMode::Recursive)
+            .map_err(|e| WingsError::Io(std::io::Error::other(e)))?;
+
+        tokio::spawn(debounce(state.clone(), uuid.to_string(), root, rx));
+        watches.insert(
+            uuid.to_string(),
+            Watch {
+                subscribers: 1,
+                _watcher: watcher,
+            },
+        );
+        tracing::debug!(uuid = %uuid, "Started watching server files");
+    }
+
+    Ok(WatchGuard {
+        state: state.clone(),
+        uuid: uuid.to_string(),
+    })
+}
+
+async fn debounce(
+    state: Arc<AppState>,
+    uuid: String,
+    root: PathBuf,
+    mut rx: mpsc::UnboundedReceiver<notify::Result<notify::Event>>,
+) {
+    let mut batch = Batch::default();
+    loop {
+        // Wait for the first change of a window
+        let Some(first) = rx.recv().await else { break };
+        let window_end = tokio::time::Instant::now() + DEBOUNCE_MAX;
+        let mut next = Some(first);
+        while let Some(res) = next.take() {
+            match res {
+                Ok(event) => {
+                    for change in changes_from_event(&root, &event) {
+                        batch.push(change);
+                    }
+                }
+                Err(e) => tracing::warn!(uuid = %uuid, error = %e, "File watch error"),
+            }
+            let quiet = tokio::time::Instant::now() + DEBOUNCE_QUIET;
+            let deadline = quiet.min(window_end);
+            next = tokio::time::timeout_at(deadline, rx.recv())
+                .await
+                .ok()
+                .flatten();
+        }
+
+        let timestamp_ms = chrono::Utc::now().timestamp_millis();
+        for change in batch.take() {
+            state.emit_event(WingsEvent {
+                event: Some(wings_event::Event::FileChanged(FileChanged {
+                    uuid: uuid.clone(),
+                    kind: change.kind.into(),
+                    path: change.path,
+                    new_path: change.new_path,
+                    timestamp_ms,
+                })),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, DataChange};
+
+    fn event(kind: EventKind, paths: &[&str]) -> notify::Event {
+        notify::Event {
+            kind,
+            paths: paths.iter().map(PathBuf::from).collect(),
+            attrs: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_changes_from_event() {
+        let root = Path::new("/srv/abc");
+        let rename = event(
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+            &["/srv/abc/world", "/srv/abc/world_old"],
+        );
+        assert_eq!(
+            changes_from_event(root, &rename),
+            vec![Change {
+                kind: FileChangeKind::FileChangeRenamed,
+                path: "/world".into(),
+                new_path: "/world_old".into(),
+            }]
+        );
+
+        // An atomic save shows up as a modification of the target only
+        let save = event(
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+            &["/srv/abc/.server.properties.nexus-tmp-1", "/srv/abc/server.properties"],
+        );
+        let changes = changes_from_event(root, &save);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].kind, FileChangeKind::FileChangeModified);
+        assert_eq!(changes[0].path, "/server.properties");
+
+        let config = event(EventKind::Create(CreateKind::File), &["/srv/abc/.nexus-config.json"]);
+        assert!(changes_from_event(root, &config).is_empty());
+    }
+
+    #[test]
+    fn test_batch_merges_and_overflows() {
+        let root = Path::new("/srv/abc");
+        let mut batch = Batch::default();
+        let create = event(EventKind::Create(CreateKind::File), &["/srv/abc/latest.log"]);
+        let write = event(
+            EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+            &["/srv/abc/latest.log"],
+        );
+        for e in [&create, &write, &write] {
+            for change in changes_from_event(root, e) {
+                batch.push(change);
+            }
+        }
+        let changes = batch.take();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].kind, FileChangeKind::FileChangeCreated);
+
+        for i in 0..=MAX_BATCH {
+            batch.push(Change {
+                kind: FileChangeKind::FileChangeCreated,
+                path: format!("/region/r.{i}.mca"),
+                new_path: String::new(),
+            });
+        }
+        let changes = batch.take();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].kind, FileChangeKind::FileChangeRescan);
+    }
+}